@@ -0,0 +1,112 @@
+//! The state each model's boot ROM hands over to the cartridge with, so a
+//! game can be started without running (or bundling) the boot ROM.
+
+use crate::{Flag, Model, Registers};
+
+// Writing to this register unmaps the boot ROM until the next power cycle
+pub const BOOT_ROM_DISABLE: u16 = 0xff50;
+
+// The cartridge header stays visible while a CGB boot ROM is mapped
+const CARTRIDGE_HEADER_START: usize = 0x100;
+const CARTRIDGE_HEADER_END: usize = 0x200;
+
+#[rustfmt::skip]
+const POST_BOOT_IO_REGISTERS: [(u16, u8); 33] = [
+    (0xff00, 0xcf), // P1
+    (0xff02, 0x7e), // SC
+    (0xff0f, 0xe1), // IF
+    (0xff10, 0x80), // NR10
+    (0xff11, 0xbf), // NR11
+    (0xff12, 0xf3), // NR12
+    (0xff13, 0xff), // NR13
+    (0xff14, 0xbf), // NR14
+    (0xff16, 0x3f), // NR21
+    (0xff17, 0x00), // NR22
+    (0xff18, 0xff), // NR23
+    (0xff19, 0xbf), // NR24
+    (0xff1a, 0x7f), // NR30
+    (0xff1b, 0xff), // NR31
+    (0xff1c, 0x9f), // NR32
+    (0xff1d, 0xff), // NR33
+    (0xff1e, 0xbf), // NR34
+    (0xff20, 0xff), // NR41
+    (0xff21, 0x00), // NR42
+    (0xff22, 0x00), // NR43
+    (0xff23, 0xbf), // NR44
+    (0xff24, 0x77), // NR50
+    (0xff25, 0xf3), // NR51
+    (0xff26, 0xf1), // NR52
+    (0xff40, 0x91), // LCDC
+    (0xff41, 0x85), // STAT
+    (0xff46, 0xff), // DMA
+    (0xff47, 0xfc), // BGP
+    (0xff48, 0xff), // OBP0
+    (0xff49, 0xff), // OBP1
+    (0xff4a, 0x00), // WY
+    (0xff4b, 0x00), // WX
+    (0xff50, 0x01), // Boot ROM disabled
+];
+
+/// Whether `address` is covered by a mapped boot ROM of `boot_rom_size`.
+pub fn is_boot_rom_address(address: u16, boot_rom_size: usize) -> bool {
+    let address = address as usize;
+    address < boot_rom_size && !(CARTRIDGE_HEADER_START..CARTRIDGE_HEADER_END).contains(&address)
+}
+
+/// The IO registers the boot ROM leaves behind, as address and value pairs.
+pub fn post_boot_io_registers(model: Model) -> Vec<(u16, u8)> {
+    let mut io_registers = POST_BOOT_IO_REGISTERS.to_vec();
+
+    // The SGB boot ROM leaves both joypad lines deselected after talking to
+    // the SNES
+    if model == Model::Sgb {
+        io_registers[0] = (0xff00, 0xff);
+    }
+
+    io_registers
+}
+
+/// The internal timer counter the boot ROM jumps to 0x100 with, DIV being
+/// its upper byte. The SGB and CGB boot ROMs take a different amount of time
+/// so their values are only approximate.
+pub fn post_boot_system_counter(model: Model) -> u16 {
+    match model {
+        Model::Dmg | Model::Mgb => 0xabcc,
+        Model::Sgb => 0xd85c,
+        Model::Cgb => 0x267c,
+    }
+}
+
+/// CPU registers at 0x100. The DMG and MGB boot ROMs finish with a compare
+/// against the header checksum, which leaves H and C set unless it's 0.
+pub fn post_boot_registers(model: Model, header_checksum: u8) -> Registers {
+    let is_checksum_non_zero = header_checksum != 0;
+
+    // A, B, C, D, E, H, L, followed by the Z and H/C flags
+    #[rustfmt::skip]
+    let (a, b, c, d, e, h, l, flag_z, flag_hc) = match model {
+        Model::Dmg => (0x01, 0x00, 0x13, 0x00, 0xd8, 0x01, 0x4d, true, is_checksum_non_zero),
+        Model::Mgb => (0xff, 0x00, 0x13, 0x00, 0xd8, 0x01, 0x4d, true, is_checksum_non_zero),
+        Model::Sgb => (0x01, 0x00, 0x14, 0x00, 0x00, 0xc0, 0x60, false, false),
+        Model::Cgb => (0x11, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7c, true, false),
+    };
+
+    Registers {
+        a,
+        b,
+        c,
+        d,
+        e,
+        f: Flag {
+            z: flag_z,
+            n: false,
+            h: flag_hc,
+            c: flag_hc,
+            ime: false,
+        },
+        h,
+        l,
+        sp: 0xfffe,
+        pc: 0x100,
+    }
+}
@@ -0,0 +1,520 @@
+//! Decodes the cartridge header that lives at $0100-$014F of every ROM.
+
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+const HEADER_END: usize = 0x150;
+const LOGO_START: usize = 0x104;
+const TITLE_START: usize = 0x134;
+const MANUFACTURER_CODE_START: usize = 0x13f;
+const CGB_FLAG: usize = 0x143;
+const NEW_LICENSEE_CODE_START: usize = 0x144;
+const SGB_FLAG: usize = 0x146;
+const CARTRIDGE_TYPE: usize = 0x147;
+const ROM_SIZE: usize = 0x148;
+const RAM_SIZE: usize = 0x149;
+const DESTINATION_CODE: usize = 0x14a;
+const OLD_LICENSEE_CODE: usize = 0x14b;
+const VERSION: usize = 0x14c;
+const HEADER_CHECKSUM: usize = 0x14d;
+const GLOBAL_CHECKSUM: usize = 0x14e;
+
+// An old licensee code of $33 means the publisher is in the new licensee code
+const USE_NEW_LICENSEE_CODE: u8 = 0x33;
+
+// Cartridge types whose RAM is kept through power off by a battery
+const BATTERY_CARTRIDGE_TYPES: [u8; 13] = [
+    0x03, 0x06, 0x09, 0x0d, 0x0f, 0x10, 0x13, 0x1b, 0x1e, 0x22, 0xfc, 0xfe, 0xff,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderError {
+    RomTooShort(usize),
+    InvalidRomSize(u8),
+    InvalidRamSize(u8),
+    HeaderChecksumMismatch { expected: u8, actual: u8 },
+    GlobalChecksumMismatch { expected: u16, actual: u16 },
+    UnsupportedCartridgeType(u8),
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::RomTooShort(len) => write!(
+                f,
+                "ROM is {} bytes, too short to hold a cartridge header",
+                len
+            ),
+            HeaderError::InvalidRomSize(code) => {
+                write!(f, "Invalid ROM size code {:#04x} at $0148", code)
+            }
+            HeaderError::InvalidRamSize(code) => {
+                write!(f, "Invalid RAM size code {:#04x} at $0149", code)
+            }
+            HeaderError::HeaderChecksumMismatch { expected, actual } => write!(
+                f,
+                "Header checksum mismatch: header says {:#04x}, computed {:#04x}",
+                expected, actual
+            ),
+            HeaderError::GlobalChecksumMismatch { expected, actual } => write!(
+                f,
+                "Global checksum mismatch: header says {:#06x}, computed {:#06x}",
+                expected, actual
+            ),
+            HeaderError::UnsupportedCartridgeType(code) => write!(
+                f,
+                "Unsupported cartridge type {:#04x} ({})",
+                code,
+                cartridge_type_name(*code)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+impl From<HeaderError> for JsValue {
+    fn from(err: HeaderError) -> JsValue {
+        JsValue::from_str(&err.to_string())
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CgbSupport {
+    DmgOnly,
+    CgbCompatible,
+    CgbOnly,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Destination {
+    Japanese,
+    Overseas,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CartridgeHeader {
+    title: String,
+    manufacturer_code: String,
+    cgb_support: CgbSupport,
+    is_sgb_supported: bool,
+    new_licensee_code: String,
+    old_licensee_code: u8,
+    cartridge_type: u8,
+    rom_size: usize,
+    ram_size: usize,
+    destination: Destination,
+    version: u8,
+    header_checksum: u8,
+    global_checksum: u16,
+    computed_global_checksum: u16,
+}
+
+#[wasm_bindgen]
+impl CartridgeHeader {
+    /// Decodes the header of a ROM image and verifies the header checksum.
+    /// The global checksum is left to `verify_global_checksum`, since the
+    /// hardware never checks it and hacks and homebrew often leave it stale.
+    pub fn parse(rom: &[u8]) -> Result<CartridgeHeader, HeaderError> {
+        if rom.len() < HEADER_END {
+            return Err(HeaderError::RomTooShort(rom.len()));
+        }
+
+        let header_checksum = rom[HEADER_CHECKSUM];
+        let computed_header_checksum = compute_header_checksum(rom);
+        if header_checksum != computed_header_checksum {
+            return Err(HeaderError::HeaderChecksumMismatch {
+                expected: header_checksum,
+                actual: computed_header_checksum,
+            });
+        }
+
+        let global_checksum = u16::from_be_bytes([rom[GLOBAL_CHECKSUM], rom[GLOBAL_CHECKSUM + 1]]);
+
+        let cgb_support = match rom[CGB_FLAG] {
+            0x80 => CgbSupport::CgbCompatible,
+            0xc0 => CgbSupport::CgbOnly,
+            _ => CgbSupport::DmgOnly,
+        };
+
+        // Later cartridges took the tail of the title area for the
+        // manufacturer code and the CGB flag
+        let manufacturer_code = &rom[MANUFACTURER_CODE_START..CGB_FLAG];
+        let has_manufacturer_code = cgb_support != CgbSupport::DmgOnly
+            && manufacturer_code.iter().all(u8::is_ascii_uppercase);
+        let title_end = if has_manufacturer_code {
+            MANUFACTURER_CODE_START
+        } else if cgb_support != CgbSupport::DmgOnly {
+            CGB_FLAG
+        } else {
+            NEW_LICENSEE_CODE_START
+        };
+
+        let rom_size = match rom[ROM_SIZE] {
+            code @ 0x00..=0x08 => 0x8000 << code,
+            0x52 => 72 * 0x4000,
+            0x53 => 80 * 0x4000,
+            0x54 => 96 * 0x4000,
+            code => return Err(HeaderError::InvalidRomSize(code)),
+        };
+
+        let ram_size = match rom[RAM_SIZE] {
+            0x00 => 0,
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => return Err(HeaderError::InvalidRamSize(code)),
+        };
+
+        let destination = match rom[DESTINATION_CODE] {
+            0x00 => Destination::Japanese,
+            _ => Destination::Overseas,
+        };
+
+        Ok(CartridgeHeader {
+            title: ascii_field(&rom[TITLE_START..title_end]),
+            manufacturer_code: if has_manufacturer_code {
+                ascii_field(manufacturer_code)
+            } else {
+                String::new()
+            },
+            cgb_support,
+            is_sgb_supported: rom[SGB_FLAG] == 0x03,
+            new_licensee_code: ascii_field(&rom[NEW_LICENSEE_CODE_START..SGB_FLAG]),
+            old_licensee_code: rom[OLD_LICENSEE_CODE],
+            cartridge_type: rom[CARTRIDGE_TYPE],
+            rom_size,
+            ram_size,
+            destination,
+            version: rom[VERSION],
+            header_checksum,
+            global_checksum,
+            computed_global_checksum: compute_global_checksum(rom),
+        })
+    }
+
+    pub fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    pub fn manufacturer_code(&self) -> String {
+        self.manufacturer_code.clone()
+    }
+
+    pub fn cgb_support(&self) -> CgbSupport {
+        self.cgb_support
+    }
+
+    pub fn is_sgb_supported(&self) -> bool {
+        self.is_sgb_supported
+    }
+
+    pub fn new_licensee_code(&self) -> String {
+        self.new_licensee_code.clone()
+    }
+
+    pub fn old_licensee_code(&self) -> u8 {
+        self.old_licensee_code
+    }
+
+    /// The licensee code that actually identifies the publisher, either the
+    /// two character new code or the old code in hex.
+    pub fn licensee_code(&self) -> String {
+        if self.old_licensee_code == USE_NEW_LICENSEE_CODE {
+            self.new_licensee_code.clone()
+        } else {
+            format!("{:02X}", self.old_licensee_code)
+        }
+    }
+
+    pub fn cartridge_type(&self) -> u8 {
+        self.cartridge_type
+    }
+
+    pub fn cartridge_type_name(&self) -> String {
+        cartridge_type_name(self.cartridge_type).to_string()
+    }
+
+    /// Whether the cartridge RAM, and clock if any, keep their contents when
+    /// the power is off.
+    pub fn has_battery(&self) -> bool {
+        BATTERY_CARTRIDGE_TYPES.contains(&self.cartridge_type)
+    }
+
+    /// Whether the cartridge has an MBC3 real-time clock.
+    pub fn has_timer(&self) -> bool {
+        self.cartridge_type == 0x0f || self.cartridge_type == 0x10
+    }
+
+    /// Whether the cartridge has an MBC5 rumble motor.
+    pub fn has_rumble(&self) -> bool {
+        (0x1c..=0x1e).contains(&self.cartridge_type)
+    }
+
+    pub fn rom_size(&self) -> usize {
+        self.rom_size
+    }
+
+    pub fn ram_size(&self) -> usize {
+        self.ram_size
+    }
+
+    pub fn destination(&self) -> Destination {
+        self.destination
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn header_checksum(&self) -> u8 {
+        self.header_checksum
+    }
+
+    pub fn global_checksum(&self) -> u16 {
+        self.global_checksum
+    }
+
+    /// Checks the global checksum against the sum of the ROM's bytes. Real
+    /// hardware ignores it, so a mismatch is best treated as a warning.
+    pub fn verify_global_checksum(&self) -> Result<(), HeaderError> {
+        if self.global_checksum == self.computed_global_checksum {
+            Ok(())
+        } else {
+            Err(HeaderError::GlobalChecksumMismatch {
+                expected: self.global_checksum,
+                actual: self.computed_global_checksum,
+            })
+        }
+    }
+
+    /// Whether the global checksum matches the sum of the ROM's bytes.
+    pub fn is_global_checksum_valid(&self) -> bool {
+        self.verify_global_checksum().is_ok()
+    }
+}
+
+// Same calculation the boot ROM does over $0134-$014C before it hands over
+fn compute_header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE_START..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |checksum, byte| {
+            checksum.wrapping_sub(*byte).wrapping_sub(1)
+        })
+}
+
+// Sum of every byte in the ROM except the two checksum bytes themselves
+fn compute_global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(idx, _)| *idx != GLOBAL_CHECKSUM && *idx != GLOBAL_CHECKSUM + 1)
+        .fold(0u16, |checksum, (_, byte)| {
+            checksum.wrapping_add(u16::from(*byte))
+        })
+}
+
+/// MBC1M multicarts are 8 Mbit with a separate game, header and all, in each
+/// 2 Mbit quarter. Nothing in the header says so, but regular MBC1 games
+/// don't have the Nintendo logo at the start of their second quarter.
+pub fn is_mbc1_multicart(rom: &[u8]) -> bool {
+    const MULTICART_SIZE: usize = 0x100000;
+    const GAME_SIZE: usize = 0x40000;
+
+    rom.len() == MULTICART_SIZE
+        && rom[LOGO_START..TITLE_START] == rom[GAME_SIZE + LOGO_START..GAME_SIZE + TITLE_START]
+}
+
+fn ascii_field(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|byte| **byte != 0)
+        .map(|byte| *byte as char)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+fn cartridge_type_name(cartridge_type: u8) -> &'static str {
+    match cartridge_type {
+        0x00 => "ROM ONLY",
+        0x01 => "MBC1",
+        0x02 => "MBC1+RAM",
+        0x03 => "MBC1+RAM+BATTERY",
+        0x05 => "MBC2",
+        0x06 => "MBC2+BATTERY",
+        0x08 => "ROM+RAM",
+        0x09 => "ROM+RAM+BATTERY",
+        0x0b => "MMM01",
+        0x0c => "MMM01+RAM",
+        0x0d => "MMM01+RAM+BATTERY",
+        0x0f => "MBC3+TIMER+BATTERY",
+        0x10 => "MBC3+TIMER+RAM+BATTERY",
+        0x11 => "MBC3",
+        0x12 => "MBC3+RAM",
+        0x13 => "MBC3+RAM+BATTERY",
+        0x19 => "MBC5",
+        0x1a => "MBC5+RAM",
+        0x1b => "MBC5+RAM+BATTERY",
+        0x1c => "MBC5+RUMBLE",
+        0x1d => "MBC5+RUMBLE+RAM",
+        0x1e => "MBC5+RUMBLE+RAM+BATTERY",
+        0x20 => "MBC6",
+        0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+        0xfc => "POCKET CAMERA",
+        0xfd => "BANDAI TAMA5",
+        0xfe => "HuC3",
+        0xff => "HuC1+RAM+BATTERY",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Builds ROMs for the unit tests.
+#[cfg(test)]
+pub mod test_rom {
+    use super::*;
+
+    /// A blank ROM the size `rom_size_code` says, with a header that parses.
+    pub fn build(cartridge_type: u8, rom_size_code: u8, ram_size_code: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000 << rom_size_code];
+        write_header(&mut rom, cartridge_type, rom_size_code, ram_size_code);
+        rom
+    }
+
+    /// Fills in the cartridge type and sizes of the header at $0100 of
+    /// `rom`, which can also be a game or menu further into a multicart.
+    pub fn write_header(rom: &mut [u8], cartridge_type: u8, rom_size_code: u8, ram_size_code: u8) {
+        rom[CARTRIDGE_TYPE] = cartridge_type;
+        rom[ROM_SIZE] = rom_size_code;
+        rom[RAM_SIZE] = ram_size_code;
+        fix_checksums(rom);
+    }
+
+    /// Recomputes both checksums after the header or the ROM was changed.
+    pub fn fix_checksums(rom: &mut [u8]) {
+        rom[HEADER_CHECKSUM] = compute_header_checksum(rom);
+        let global_checksum = compute_global_checksum(rom);
+        rom[GLOBAL_CHECKSUM..GLOBAL_CHECKSUM + 2].copy_from_slice(&global_checksum.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_rom::fix_checksums;
+    use super::*;
+
+    // A 32KB ROM with `title_area` at $0134-$0143 and both checksums fixed up
+    fn rom_with(title_area: &[u8], edit: impl Fn(&mut Vec<u8>)) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[TITLE_START..TITLE_START + title_area.len()].copy_from_slice(title_area);
+        edit(&mut rom);
+        fix_checksums(&mut rom);
+        rom
+    }
+
+    #[test]
+    fn dmg_titles_take_the_whole_title_area() {
+        let header = CartridgeHeader::parse(&rom_with(b"SIXTEEN CHAR TTL", |_| {})).unwrap();
+        assert_eq!(header.title(), "SIXTEEN CHAR TTL");
+        assert_eq!(header.manufacturer_code(), "");
+        assert_eq!(header.cgb_support(), CgbSupport::DmgOnly);
+    }
+
+    #[test]
+    fn cgb_titles_leave_room_for_the_manufacturer_code_and_flag() {
+        let header = CartridgeHeader::parse(&rom_with(b"POKEMON CRYAAUE\xc0", |_| {})).unwrap();
+        assert_eq!(header.title(), "POKEMON CRY");
+        assert_eq!(header.manufacturer_code(), "AAUE");
+        assert_eq!(header.cgb_support(), CgbSupport::CgbOnly);
+    }
+
+    #[test]
+    fn cgb_titles_without_a_manufacturer_code_end_at_the_flag() {
+        let header =
+            CartridgeHeader::parse(&rom_with(b"ZELDA DX\0\0\0\0\0\0\0\x80", |_| {})).unwrap();
+        assert_eq!(header.title(), "ZELDA DX");
+        assert_eq!(header.manufacturer_code(), "");
+        assert_eq!(header.cgb_support(), CgbSupport::CgbCompatible);
+    }
+
+    #[test]
+    fn reads_the_old_licensee_code_unless_it_points_to_the_new_one() {
+        let old = CartridgeHeader::parse(&rom_with(b"OLD", |rom| {
+            rom[OLD_LICENSEE_CODE] = 0x01;
+            rom[NEW_LICENSEE_CODE_START..SGB_FLAG].copy_from_slice(b"52");
+        }))
+        .unwrap();
+        assert_eq!(old.licensee_code(), "01");
+
+        let new = CartridgeHeader::parse(&rom_with(b"NEW", |rom| {
+            rom[OLD_LICENSEE_CODE] = USE_NEW_LICENSEE_CODE;
+            rom[NEW_LICENSEE_CODE_START..SGB_FLAG].copy_from_slice(b"52");
+        }))
+        .unwrap();
+        assert_eq!(new.licensee_code(), "52");
+        assert_eq!(new.old_licensee_code(), 0x33);
+    }
+
+    #[test]
+    fn decodes_rom_and_ram_sizes() {
+        let sizes = |rom_code: u8, ram_code: u8| {
+            CartridgeHeader::parse(&rom_with(b"SIZES", |rom| {
+                rom[ROM_SIZE] = rom_code;
+                rom[RAM_SIZE] = ram_code;
+            }))
+            .map(|header| (header.rom_size(), header.ram_size()))
+        };
+
+        assert_eq!(sizes(0x00, 0x00), Ok((0x8000, 0)));
+        assert_eq!(sizes(0x05, 0x02), Ok((0x100000, 0x2000)));
+        assert_eq!(sizes(0x08, 0x03), Ok((0x800000, 0x8000)));
+        assert_eq!(sizes(0x52, 0x04), Ok((72 * 0x4000, 0x20000)));
+        assert_eq!(sizes(0x54, 0x05), Ok((96 * 0x4000, 0x10000)));
+        assert_eq!(sizes(0x09, 0x00), Err(HeaderError::InvalidRomSize(0x09)));
+        assert_eq!(sizes(0x00, 0x06), Err(HeaderError::InvalidRamSize(0x06)));
+    }
+
+    #[test]
+    fn rejects_a_bad_header_checksum() {
+        let mut rom = rom_with(b"BROKEN", |_| {});
+        rom[HEADER_CHECKSUM] ^= 0xff;
+        assert_eq!(
+            CartridgeHeader::parse(&rom),
+            Err(HeaderError::HeaderChecksumMismatch {
+                expected: rom[HEADER_CHECKSUM],
+                actual: rom[HEADER_CHECKSUM] ^ 0xff,
+            })
+        );
+    }
+
+    #[test]
+    fn only_reports_a_bad_global_checksum() {
+        let header = CartridgeHeader::parse(&rom_with(b"GOOD", |_| {})).unwrap();
+        assert!(header.is_global_checksum_valid());
+
+        let mut rom = rom_with(b"PATCHED", |_| {});
+        rom[0x4000] = 0xaa;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(!header.is_global_checksum_valid());
+        assert_eq!(
+            header.verify_global_checksum(),
+            Err(HeaderError::GlobalChecksumMismatch {
+                expected: header.global_checksum(),
+                actual: header.global_checksum().wrapping_add(0xaa),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_roms_too_short_for_a_header() {
+        assert_eq!(
+            CartridgeHeader::parse(&[0; 0x100]),
+            Err(HeaderError::RomTooShort(0x100))
+        );
+    }
+}
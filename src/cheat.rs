@@ -0,0 +1,370 @@
+//! Cheat codes. Game Genie codes patch bytes read from the cartridge ROM,
+//! optionally only while the byte there is the one the code was made for,
+//! which keeps them from hitting other banks mapped to the same address.
+//! GameShark codes write a byte to RAM once a frame, optionally to a given
+//! cartridge RAM bank.
+
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+// GameShark code types, the first byte of the code. Banked codes carry the
+// cartridge RAM bank in the lower nibble.
+const GAMESHARK_ANY_BANK: [u8; 2] = [0x00, 0x01];
+const GAMESHARK_BANKED: u8 = 0x80;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheatError {
+    InvalidLength(usize),
+    InvalidCharacter(char),
+    AddressOutOfRange(u16),
+    UnknownGameSharkType(u8),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheatError::InvalidLength(len) => write!(
+                f,
+                "Cheat code has {} digits, expected 6 or 9 for Game Genie or 8 for GameShark",
+                len
+            ),
+            CheatError::InvalidCharacter(character) => {
+                write!(f, "Invalid character {:?} in cheat code", character)
+            }
+            CheatError::AddressOutOfRange(address) => write!(
+                f,
+                "Cheat code address {:#06x} is outside ROM for Game Genie or RAM for GameShark",
+                address
+            ),
+            CheatError::UnknownGameSharkType(code_type) => {
+                write!(f, "Unknown GameShark code type {:#04x}", code_type)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CheatError {}
+
+impl From<CheatError> for JsValue {
+    fn from(err: CheatError) -> JsValue {
+        JsValue::from_str(&err.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Patch {
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    GameShark {
+        bank: Option<u8>,
+        address: u16,
+        value: u8,
+    },
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    code: String,
+    patch: Patch,
+}
+
+#[wasm_bindgen]
+impl Cheat {
+    /// Parses a Game Genie code, ABC-DEF or ABC-DEF-GHI with or without the
+    /// dashes, or an 8 digit GameShark code.
+    pub fn parse(code: &str) -> Result<Cheat, CheatError> {
+        let code = code.trim().to_uppercase();
+        let digits = code
+            .chars()
+            .filter(|character| *character != '-')
+            .map(|character| {
+                character
+                    .to_digit(16)
+                    .map(|digit| digit as u8)
+                    .ok_or(CheatError::InvalidCharacter(character))
+            })
+            .collect::<Result<Vec<u8>, CheatError>>()?;
+
+        let patch = match digits.len() {
+            6 | 9 => parse_game_genie(&digits)?,
+            8 => parse_gameshark(&digits)?,
+            len => return Err(CheatError::InvalidLength(len)),
+        };
+
+        Ok(Cheat { code, patch })
+    }
+
+    pub fn code(&self) -> String {
+        self.code.clone()
+    }
+
+    pub fn is_game_genie(&self) -> bool {
+        match self.patch {
+            Patch::GameGenie { .. } => true,
+            Patch::GameShark { .. } => false,
+        }
+    }
+
+    pub fn address(&self) -> u16 {
+        match self.patch {
+            Patch::GameGenie { address, .. } | Patch::GameShark { address, .. } => address,
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match self.patch {
+            Patch::GameGenie { value, .. } | Patch::GameShark { value, .. } => value,
+        }
+    }
+
+    /// The byte a Game Genie code only replaces, if it has one.
+    pub fn compare(&self) -> Option<u8> {
+        match self.patch {
+            Patch::GameGenie { compare, .. } => compare,
+            Patch::GameShark { .. } => None,
+        }
+    }
+
+    /// The cartridge RAM bank a GameShark code writes to, if it's banked.
+    pub fn bank(&self) -> Option<u8> {
+        match self.patch {
+            Patch::GameShark { bank, .. } => bank,
+            Patch::GameGenie { .. } => None,
+        }
+    }
+}
+
+fn hex_byte(high: u8, low: u8) -> u8 {
+    high << 4 | low
+}
+
+// ABC-DEF-GHI: AB is the new value and the address is FCDE with F inverted.
+// GI is the compare value XORed with 0xBA and rotated left 2, H isn't used.
+fn parse_game_genie(digits: &[u8]) -> Result<Patch, CheatError> {
+    let value = hex_byte(digits[0], digits[1]);
+    let address = ((digits[5] ^ 0xf) as u16) << 12
+        | (digits[2] as u16) << 8
+        | (digits[3] as u16) << 4
+        | digits[4] as u16;
+    if address >= 0x8000 {
+        return Err(CheatError::AddressOutOfRange(address));
+    }
+
+    let compare = if digits.len() == 9 {
+        Some(hex_byte(digits[6], digits[8]).rotate_right(2) ^ 0xba)
+    } else {
+        None
+    };
+
+    Ok(Patch::GameGenie {
+        address,
+        value,
+        compare,
+    })
+}
+
+// ttvvllhh: the code type, the value and the little endian address
+fn parse_gameshark(digits: &[u8]) -> Result<Patch, CheatError> {
+    let code_type = hex_byte(digits[0], digits[1]);
+    let value = hex_byte(digits[2], digits[3]);
+    let address =
+        (hex_byte(digits[6], digits[7]) as u16) << 8 | hex_byte(digits[4], digits[5]) as u16;
+
+    let bank = if GAMESHARK_ANY_BANK.contains(&code_type) {
+        None
+    } else if code_type & 0xf0 == GAMESHARK_BANKED {
+        Some(code_type & 0xf)
+    } else {
+        return Err(CheatError::UnknownGameSharkType(code_type));
+    };
+
+    if address < 0x8000 {
+        return Err(CheatError::AddressOutOfRange(address));
+    }
+
+    Ok(Patch::GameShark {
+        bank,
+        address,
+        value,
+    })
+}
+
+struct CheatEntry {
+    id: u32,
+    cheat: Cheat,
+    is_enabled: bool,
+}
+
+/// The cheats added to a gameboy, each with an id to toggle or remove it by.
+#[derive(Default)]
+pub struct Cheats {
+    entries: Vec<CheatEntry>,
+    next_id: u32,
+}
+
+impl Cheats {
+    /// Adds an enabled cheat and returns its id.
+    pub fn add(&mut self, cheat: Cheat) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.push(CheatEntry {
+            id,
+            cheat,
+            is_enabled: true,
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.entries.retain(|entry| entry.id != id);
+    }
+
+    pub fn set_enabled(&mut self, id: u32, is_enabled: bool) {
+        for entry in self.entries.iter_mut().filter(|entry| entry.id == id) {
+            entry.is_enabled = is_enabled;
+        }
+    }
+
+    fn enabled_patches(&self) -> impl Iterator<Item = Patch> + '_ {
+        self.entries
+            .iter()
+            .filter(|entry| entry.is_enabled)
+            .map(|entry| entry.cheat.patch)
+    }
+
+    /// The byte a ROM read returns with the Game Genie codes applied.
+    pub fn patch_rom_read(&self, read_address: u16, rom_value: u8) -> u8 {
+        for patch in self.enabled_patches() {
+            if let Patch::GameGenie {
+                address,
+                value,
+                compare,
+            } = patch
+            {
+                if address == read_address && compare.is_none_or(|compare| compare == rom_value) {
+                    return value;
+                }
+            }
+        }
+        rom_value
+    }
+
+    /// The writes the GameShark codes make each frame, as the bank, address
+    /// and value.
+    pub fn gameshark_writes(&self) -> Vec<(Option<u8>, u16, u8)> {
+        self.enabled_patches()
+            .filter_map(|patch| match patch {
+                Patch::GameShark {
+                    bank,
+                    address,
+                    value,
+                } => Some((bank, address, value)),
+                Patch::GameGenie { .. } => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_genie_codes_invert_the_top_address_nibble() {
+        let cheat = Cheat::parse("00A-17B").unwrap();
+        assert!(cheat.is_game_genie());
+        assert_eq!(cheat.value(), 0x00);
+        assert_eq!(cheat.address(), 0x4a17);
+        assert_eq!(cheat.compare(), None);
+    }
+
+    #[test]
+    fn game_genie_codes_only_patch_rom() {
+        assert_eq!(
+            Cheat::parse("3E0-0F7"),
+            Err(CheatError::AddressOutOfRange(0x800f))
+        );
+    }
+
+    #[test]
+    fn decodes_the_game_genie_compare_value() {
+        // C9 rotated right 2 is 72, XORed with BA that's C8
+        let cheat = Cheat::parse("00A-17B-C49").unwrap();
+        assert_eq!(cheat.address(), 0x4a17);
+        assert_eq!(cheat.compare(), Some(0xc8));
+    }
+
+    #[test]
+    fn game_genie_dashes_and_case_are_optional() {
+        let dashed = Cheat::parse("00A-17B-C49").unwrap();
+        let plain = Cheat::parse(" 00a17bc49 ").unwrap();
+        assert_eq!(plain.patch, dashed.patch);
+        assert_eq!(
+            Cheat::parse("00a17b").unwrap().patch,
+            Cheat::parse("00A-17B").unwrap().patch
+        );
+    }
+
+    #[test]
+    fn gameshark_codes_write_any_bank_or_a_given_one() {
+        let any_bank = Cheat::parse("01FF16D0").unwrap();
+        assert!(!any_bank.is_game_genie());
+        assert_eq!(any_bank.value(), 0xff);
+        assert_eq!(any_bank.address(), 0xd016);
+        assert_eq!(any_bank.bank(), None);
+        assert_eq!(Cheat::parse("00FF16D0").unwrap().bank(), None);
+
+        let banked = Cheat::parse("8A0102A0").unwrap();
+        assert_eq!(banked.value(), 0x01);
+        assert_eq!(banked.address(), 0xa002);
+        assert_eq!(banked.bank(), Some(0xa));
+    }
+
+    #[test]
+    fn gameshark_codes_only_write_ram() {
+        assert_eq!(
+            Cheat::parse("01FF1640"),
+            Err(CheatError::AddressOutOfRange(0x4016))
+        );
+        assert_eq!(
+            Cheat::parse("21FF16D0"),
+            Err(CheatError::UnknownGameSharkType(0x21))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        assert_eq!(Cheat::parse("123-45"), Err(CheatError::InvalidLength(5)));
+        assert_eq!(Cheat::parse(""), Err(CheatError::InvalidLength(0)));
+        assert_eq!(
+            Cheat::parse("00G-17B"),
+            Err(CheatError::InvalidCharacter('G'))
+        );
+    }
+
+    #[test]
+    fn patches_rom_reads_only_when_the_compare_value_matches() {
+        let mut cheats = Cheats::default();
+        let id = cheats.add(Cheat::parse("00A-17B-C49").unwrap());
+
+        assert_eq!(cheats.patch_rom_read(0x4a17, 0xc8), 0x00);
+        assert_eq!(cheats.patch_rom_read(0x4a17, 0x12), 0x12);
+        assert_eq!(cheats.patch_rom_read(0x4a18, 0xc8), 0xc8);
+
+        cheats.set_enabled(id, false);
+        assert_eq!(cheats.patch_rom_read(0x4a17, 0xc8), 0xc8);
+    }
+
+    #[test]
+    fn patches_rom_reads_whatever_the_byte_without_a_compare_value() {
+        let mut cheats = Cheats::default();
+        cheats.add(Cheat::parse("00A-17B").unwrap());
+        assert_eq!(cheats.patch_rom_read(0x4a17, 0x12), 0x00);
+        assert_eq!(cheats.patch_rom_read(0x4a17, 0xc8), 0x00);
+    }
+}
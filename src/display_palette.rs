@@ -0,0 +1,359 @@
+//! The colours the four DMG shades are shown in. A palette has four colours
+//! for each of the background, OBP0 and OBP1 layers, so sprites can be told
+//! apart from the background like on a Super Game Boy.
+//!
+//! As text a palette is its colours as `#rrggbb`, either four shared by all
+//! layers or twelve for the background, OBP0 and OBP1 in that order. With
+//! labels, the four colours after each of `bg:`, `obj0:` and `obj1:` go to
+//! that layer whatever order the labels are in:
+//!
+//! ```text
+//! bg: #ffffff #bfbfbf #404040 #000000
+//! obj0: #ffffff #ff8484 #943a3a #000000
+//! obj1: #ffffff #7bff31 #008400 #000000
+//! ```
+
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+const SHADE_NUM: usize = 4;
+const LAYER_NUM: usize = 3;
+const LAYER_LABELS: [&str; LAYER_NUM] = ["bg", "obj0", "obj1"];
+
+type Rgb = [u8; 3];
+
+const GREY: [Rgb; SHADE_NUM] = [
+    [0xff, 0xff, 0xff],
+    [0xbf, 0xbf, 0xbf],
+    [0x40, 0x40, 0x40],
+    [0x00, 0x00, 0x00],
+];
+const DMG: [Rgb; SHADE_NUM] = [
+    [0x9b, 0xbc, 0x0f],
+    [0x8b, 0xac, 0x0f],
+    [0x30, 0x62, 0x30],
+    [0x0f, 0x38, 0x0f],
+];
+const POCKET: [Rgb; SHADE_NUM] = [
+    [0xc4, 0xcf, 0xa1],
+    [0x8b, 0x95, 0x6d],
+    [0x4d, 0x53, 0x3c],
+    [0x1f, 0x1f, 0x1f],
+];
+// The Game Boy Light's backlit screen
+const LIGHT: [Rgb; SHADE_NUM] = [
+    [0x00, 0xb5, 0x81],
+    [0x00, 0x9a, 0x71],
+    [0x00, 0x69, 0x4a],
+    [0x00, 0x4f, 0x3b],
+];
+const HIGH_CONTRAST: [Rgb; SHADE_NUM] = [
+    [0xff, 0xff, 0xff],
+    [0xaa, 0xaa, 0xaa],
+    [0x55, 0x55, 0x55],
+    [0x00, 0x00, 0x00],
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaletteError {
+    UnknownPreset(String),
+    InvalidColor(String),
+    InvalidColorCount(usize),
+    UnknownLabel(String),
+    UnlabelledColors,
+    LayerColorCount { layer: &'static str, count: usize },
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::UnknownPreset(name) => write!(
+                f,
+                "Unknown palette {:?}, expected grey, dmg, pocket, light or high-contrast",
+                name
+            ),
+            PaletteError::InvalidColor(color) => {
+                write!(f, "Invalid colour {:?}, expected #rrggbb", color)
+            }
+            PaletteError::InvalidColorCount(count) => write!(
+                f,
+                "Palette has {} colours, expected 4 or 12 for separate BG, OBJ0 and OBJ1",
+                count
+            ),
+            PaletteError::UnknownLabel(label) => write!(
+                f,
+                "Unknown or repeated label {:?}, expected bg:, obj0: and obj1: once each",
+                label
+            ),
+            PaletteError::UnlabelledColors => {
+                write!(f, "Palette has colours before its first label")
+            }
+            PaletteError::LayerColorCount { layer, count } => {
+                write!(f, "Palette has {} {} colours, expected 4", count, layer)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl From<PaletteError> for JsValue {
+    fn from(err: PaletteError) -> JsValue {
+        JsValue::from_str(&err.to_string())
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayPalette {
+    // Indexed by layer * 4 + shade, the same as a frame buffer pixel
+    colors: [Rgb; SHADE_NUM * LAYER_NUM],
+}
+
+impl Default for DisplayPalette {
+    fn default() -> DisplayPalette {
+        DisplayPalette::shared(GREY)
+    }
+}
+
+#[wasm_bindgen]
+impl DisplayPalette {
+    /// One of the built in palettes: grey, dmg, pocket, light or
+    /// high-contrast.
+    pub fn preset(name: &str) -> Result<DisplayPalette, PaletteError> {
+        let colors = match name {
+            "grey" => GREY,
+            "dmg" => DMG,
+            "pocket" => POCKET,
+            "light" => LIGHT,
+            "high-contrast" => HIGH_CONTRAST,
+            _ => return Err(PaletteError::UnknownPreset(name.to_string())),
+        };
+        Ok(DisplayPalette::shared(colors))
+    }
+
+    /// A palette from RGB bytes, 4 colours shared by all layers or 12 for
+    /// the background, OBP0 and OBP1.
+    pub fn from_rgb(rgb: Vec<u8>) -> Result<DisplayPalette, PaletteError> {
+        if !rgb.len().is_multiple_of(3) {
+            return Err(PaletteError::InvalidColorCount(rgb.len() / 3));
+        }
+        let colors: Vec<Rgb> = rgb
+            .chunks(3)
+            .map(|color| [color[0], color[1], color[2]])
+            .collect();
+        DisplayPalette::from_colors(&colors)
+    }
+
+    /// Reads a palette in the text format above.
+    pub fn parse(text: &str) -> Result<DisplayPalette, PaletteError> {
+        let mut unlabelled = Vec::new();
+        let mut layers: [Option<Vec<Rgb>>; LAYER_NUM] = Default::default();
+        let mut current_layer = None;
+
+        let tokens = text
+            .split(|character: char| character.is_whitespace() || character == ',')
+            .filter(|token| !token.is_empty());
+        for token in tokens {
+            if let Some(label) = token.strip_suffix(':') {
+                let layer = LAYER_LABELS
+                    .iter()
+                    .position(|layer_label| layer_label.eq_ignore_ascii_case(label))
+                    .filter(|layer| layers[*layer].is_none())
+                    .ok_or_else(|| PaletteError::UnknownLabel(label.to_string()))?;
+                layers[layer] = Some(Vec::new());
+                current_layer = Some(layer);
+                continue;
+            }
+
+            let color = parse_color(token)?;
+            match current_layer {
+                Some(layer) => layers[layer].get_or_insert_with(Vec::new).push(color),
+                None => unlabelled.push(color),
+            }
+        }
+
+        if current_layer.is_none() {
+            return DisplayPalette::from_colors(&unlabelled);
+        }
+        if !unlabelled.is_empty() {
+            return Err(PaletteError::UnlabelledColors);
+        }
+
+        let mut palette = DisplayPalette::default();
+        for ((label, colors), layer) in LAYER_LABELS
+            .iter()
+            .zip(layers.iter())
+            .zip(palette.colors.chunks_mut(SHADE_NUM))
+        {
+            let colors = colors.as_deref().unwrap_or_default();
+            if colors.len() != SHADE_NUM {
+                return Err(PaletteError::LayerColorCount {
+                    layer: label,
+                    count: colors.len(),
+                });
+            }
+            layer.copy_from_slice(colors);
+        }
+        Ok(palette)
+    }
+
+    /// The palette as text, on one line if the layers share their colours.
+    pub fn to_text(&self) -> String {
+        let layers: Vec<&[Rgb]> = self.colors.chunks(SHADE_NUM).collect();
+        if layers.iter().all(|layer| *layer == layers[0]) {
+            return format_colors(layers[0]);
+        }
+
+        LAYER_LABELS
+            .iter()
+            .zip(layers)
+            .map(|(label, layer)| format!("{}: {}", label, format_colors(layer)))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// All 12 colours as RGB bytes, the background's first.
+    pub fn rgb(&self) -> Vec<u8> {
+        self.colors.concat()
+    }
+}
+
+impl DisplayPalette {
+    fn shared(colors: [Rgb; SHADE_NUM]) -> DisplayPalette {
+        let mut palette = DisplayPalette {
+            colors: [[0; 3]; SHADE_NUM * LAYER_NUM],
+        };
+        for layer in palette.colors.chunks_mut(SHADE_NUM) {
+            layer.copy_from_slice(&colors);
+        }
+        palette
+    }
+
+    fn from_colors(colors: &[Rgb]) -> Result<DisplayPalette, PaletteError> {
+        match colors.len() {
+            SHADE_NUM => {
+                let mut shared = [[0; 3]; SHADE_NUM];
+                shared.copy_from_slice(colors);
+                Ok(DisplayPalette::shared(shared))
+            }
+            len if len == SHADE_NUM * LAYER_NUM => {
+                let mut palette = DisplayPalette::default();
+                palette.colors.copy_from_slice(colors);
+                Ok(palette)
+            }
+            len => Err(PaletteError::InvalidColorCount(len)),
+        }
+    }
+
+    /// The RGBA colour of a frame buffer pixel, which is the layer and shade.
+    pub fn frame_rgba(&self, pixel: u8) -> [u8; 4] {
+        let [r, g, b] = self.colors[pixel as usize % self.colors.len()];
+        [r, g, b, 0xff]
+    }
+
+    /// The RGBA colour of a background or window shade.
+    pub fn bg_rgba(&self, shade: u8) -> [u8; 4] {
+        self.frame_rgba(shade)
+    }
+
+    /// The RGBA colour of a sprite shade, with `palette_num` set for OBP1.
+    pub fn obj_rgba(&self, palette_num: bool, shade: u8) -> [u8; 4] {
+        let layer = if palette_num { 2 } else { 1 };
+        self.frame_rgba(layer * SHADE_NUM as u8 + shade)
+    }
+}
+
+fn parse_color(token: &str) -> Result<Rgb, PaletteError> {
+    let invalid = || PaletteError::InvalidColor(token.to_string());
+    let hex = token.strip_prefix('#').unwrap_or(token);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut color = [0; 3];
+    for (idx, component) in color.iter_mut().enumerate() {
+        *component = u8::from_str_radix(&hex[idx * 2..idx * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(color)
+}
+
+fn format_colors(colors: &[Rgb]) -> String {
+    colors
+        .iter()
+        .map(|[r, g, b]| format!("#{:02x}{:02x}{:02x}", r, g, b))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layered() -> DisplayPalette {
+        DisplayPalette::parse(
+            "bg: #ffffff #bfbfbf #404040 #000000
+             obj0: #ffffff #ff8484 #943a3a #000000
+             obj1: #ffffff #7bff31 #008400 #000000",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trips_shared_palettes_through_text() {
+        for name in &["grey", "dmg", "pocket", "light", "high-contrast"] {
+            let palette = DisplayPalette::preset(name).unwrap();
+            let text = palette.to_text();
+            assert!(!text.contains(':'), "{} is written on one line", name);
+            assert_eq!(DisplayPalette::parse(&text), Ok(palette));
+        }
+    }
+
+    #[test]
+    fn round_trips_layered_palettes_through_text() {
+        let palette = layered();
+        assert_eq!(DisplayPalette::parse(&palette.to_text()), Ok(palette));
+    }
+
+    #[test]
+    fn assigns_colours_by_label() {
+        let reordered = DisplayPalette::parse(
+            "obj1: #ffffff #7bff31 #008400 #000000
+             bg: #ffffff #bfbfbf #404040 #000000
+             OBJ0: #ffffff #ff8484 #943a3a #000000",
+        );
+        assert_eq!(reordered, Ok(layered()));
+        assert_eq!(layered().obj_rgba(true, 1), [0x7b, 0xff, 0x31, 0xff]);
+    }
+
+    #[test]
+    fn rejects_bad_labels() {
+        assert_eq!(
+            DisplayPalette::parse("bg: #ffffff #bfbfbf #404040 #000000 sprites: #ffffff"),
+            Err(PaletteError::UnknownLabel("sprites".to_string()))
+        );
+        assert_eq!(
+            DisplayPalette::parse("bg: #ffffff bg: #ffffff"),
+            Err(PaletteError::UnknownLabel("bg".to_string()))
+        );
+        assert_eq!(
+            DisplayPalette::parse("#ffffff obj0: #ffffff"),
+            Err(PaletteError::UnlabelledColors)
+        );
+        assert_eq!(
+            DisplayPalette::parse("bg: #ffffff #bfbfbf #404040 #000000 obj0: #ffffff"),
+            Err(PaletteError::LayerColorCount {
+                layer: "obj0",
+                count: 1
+            })
+        );
+        assert_eq!(
+            DisplayPalette::parse("bg: #ffffff #bfbfbf #404040 #000000"),
+            Err(PaletteError::LayerColorCount {
+                layer: "obj0",
+                count: 0
+            })
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Errors that stop emulation, or stop a gameboy from being built, without
+//! taking the whole wasm instance down.
+
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{HeaderError, Model};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmulationError {
+    IllegalOpcode {
+        opcode: u8,
+        pc: u16,
+    },
+    // A write the mapper understands but doesn't emulate
+    UnsupportedMapperFeature {
+        mapper: &'static str,
+        address: u16,
+        value: u8,
+        pc: u16,
+    },
+    InvalidRegisterState {
+        description: String,
+        pc: u16,
+    },
+}
+
+impl EmulationError {
+    /// Address of the instruction that was executing when the error happened.
+    pub fn pc(&self) -> u16 {
+        match self {
+            EmulationError::IllegalOpcode { pc, .. } => *pc,
+            EmulationError::UnsupportedMapperFeature { pc, .. } => *pc,
+            EmulationError::InvalidRegisterState { pc, .. } => *pc,
+        }
+    }
+}
+
+impl fmt::Display for EmulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulationError::IllegalOpcode { opcode, pc } => {
+                write!(f, "Illegal opcode {:#04x} at {:#06x}", opcode, pc)
+            }
+            EmulationError::UnsupportedMapperFeature {
+                mapper,
+                address,
+                value,
+                pc,
+            } => write!(
+                f,
+                "Unsupported {} feature: wrote {:#04x} to {:#06x} at {:#06x}",
+                mapper, value, address, pc
+            ),
+            EmulationError::InvalidRegisterState { description, pc } => {
+                write!(f, "Invalid register state at {:#06x}: {}", pc, description)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmulationError {}
+
+impl From<EmulationError> for JsValue {
+    fn from(err: EmulationError) -> JsValue {
+        JsValue::from_str(&err.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    Header(HeaderError),
+    InvalidBootRomSize { model: Model, len: usize },
+    InvalidSaveSize { expected: usize, len: usize },
+    // A save state whose memory isn't the whole 64KB address space
+    InvalidSaveStateMemory { len: usize },
+    // A save state from a cartridge with a different mapper
+    MapperStateMismatch { mapper: &'static str },
+}
+
+impl From<HeaderError> for LoadError {
+    fn from(err: HeaderError) -> LoadError {
+        LoadError::Header(err)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Header(err) => err.fmt(f),
+            LoadError::InvalidBootRomSize { model, len } => write!(
+                f,
+                "Boot ROM is {} bytes, expected {} for a {} boot ROM",
+                len,
+                model.boot_rom_size(),
+                model
+            ),
+            LoadError::InvalidSaveSize { expected, len } => {
+                write!(f, "Save is {} bytes, expected {}", len, expected)
+            }
+            LoadError::InvalidSaveStateMemory { len } => write!(
+                f,
+                "Save state memory is {} bytes, expected {}",
+                len,
+                crate::MEMORY_SIZE
+            ),
+            LoadError::MapperStateMismatch { mapper } => write!(
+                f,
+                "Save state is for a different mapper, expected {}",
+                mapper
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<LoadError> for JsValue {
+    fn from(err: LoadError) -> JsValue {
+        JsValue::from_str(&err.to_string())
+    }
+}
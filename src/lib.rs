@@ -1,28 +1,47 @@
 use log::debug;
 use log::info;
-use log::Level;
 
+mod boot;
+mod cartridge;
+mod cheat;
+mod display_palette;
+mod error;
+mod mapper;
+mod model;
+mod ppu;
+mod timer;
+mod timing;
 mod utils;
+#[cfg(feature = "web")]
+mod web;
 
 use bit_vec::BitVec;
+pub use cartridge::{CartridgeHeader, CgbSupport, Destination, HeaderError};
+use cheat::Cheats;
+pub use cheat::{Cheat, CheatError};
+pub use display_palette::{DisplayPalette, PaletteError};
+pub use error::{EmulationError, LoadError};
+pub use mapper::{ImageSource, MapperStatus, StaticImage, CAMERA_IMAGE_HEIGHT, CAMERA_IMAGE_WIDTH};
+use mapper::{Mapper, MapperState};
+pub use model::Model;
+#[cfg(feature = "web")]
+use ppu::Sprite;
+use ppu::{Mode, Ppu};
+use timer::Timer;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
-use web_sys::{AudioContext, OscillatorType};
 
-const MAX_GAMEBOY_VOLUME: u8 = 0xf;
-const PIXEL_ZOOM: u32 = 1;
 const BACKGROUND_WIDTH: u32 = 255;
 const BACKGROUND_HEIGHT: u32 = 255;
 const SCREEN_WIDTH: u32 = 160;
 const SCREEN_HEIGHT: u32 = 144;
-const BYTES_PER_TILE: usize = 16;
-const BYTES_PER_8_PIXEL: usize = 2;
-const SCREEN_PIXEL_NUM_PER_ROW: usize = 160;
-const IMAGE_DATA_LENGTH_PER_PIXEL: usize = 4; //r,g,b,a
-const PIXEL_NUM_PER_TILE_COL: usize = 8;
-const BACKGROUND_PIXEL_NUM_PER_ROW: usize = 256;
-const BYTES_PER_SPRITE: usize = 4;
-const SPRITE_PIXEL_NUM_PER_ROW: usize = 8;
+// The whole 16 bit address space
+const MEMORY_SIZE: usize = 0x10000;
+// Pushing PC and jumping to the handler takes 5 machine cycles
+const INTERRUPT_DISPATCH_CYCLES: u8 = 20;
+
+// Indexed by the 3 bit sweep time in NR10 and the 2 bit wave duty in NR11
+const SWEEP_TIMES_MS: [f32; 8] = [0.0, 7.8, 15.6, 23.4, 31.3, 39.1, 46.9, 54.7];
+const WAVE_DUTY_PCTS: [f32; 4] = [12.5, 25.0, 50.0, 75.0];
 
 #[macro_use]
 extern crate serde_derive;
@@ -32,9 +51,7 @@ extern crate serde_derive;
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 enum CycleRegister {
-    CpuCycle,
-    VramCycle,
-    TimerCycle,
+    Cpu,
 }
 
 // enum LcdMode {
@@ -44,744 +61,6 @@ enum CycleRegister {
 //     DataTransfer,
 // }
 
-#[wasm_bindgen]
-struct Sprite {
-    y: u8,
-    x: u8,
-    pattern_num: u8,
-    attributes: u8,
-    priority: bool,
-    y_flip: bool,
-    x_flip: bool,
-    palette_num: bool,
-}
-
-#[wasm_bindgen]
-pub struct Canvases {
-    background_canvas: web_sys::CanvasRenderingContext2d,
-    screen_canvas: web_sys::CanvasRenderingContext2d,
-    char_map_canvas: web_sys::CanvasRenderingContext2d,
-    char_map_debug_canvas: web_sys::CanvasRenderingContext2d,
-    update_char_map_canvas_last_data: Vec<u8>,
-}
-
-#[wasm_bindgen]
-impl Canvases {
-    pub fn new() -> Canvases {
-        let background_canvas = Canvases::make_canvas(
-            "gameboy-background-canvas-rust",
-            PIXEL_ZOOM * BACKGROUND_WIDTH,
-            PIXEL_ZOOM * BACKGROUND_HEIGHT,
-        );
-        let screen_canvas = Canvases::make_canvas(
-            "gameboy-screen-canvas-rust",
-            PIXEL_ZOOM * SCREEN_WIDTH,
-            PIXEL_ZOOM * SCREEN_HEIGHT,
-        );
-
-        let char_map_canvas = Canvases::make_canvas("char-map-actual-canvas-rust", 8, 1024);
-
-        let char_map_debug_canvas =
-            Canvases::make_canvas("char-map-debug-canvas-rust", 8 * 12, 8 * 8);
-
-        let canvases = Canvases {
-            background_canvas,
-            screen_canvas,
-            char_map_canvas,
-            char_map_debug_canvas,
-            update_char_map_canvas_last_data: Vec::new(),
-        };
-
-        canvases
-    }
-
-    pub fn render_background_map_as_image_data(&mut self, gameboy: &mut Gameboy) {
-        // if !gameboy.is_vblank() {
-        //     return;
-        // }
-
-        let background_map = gameboy.bg_map(); //Tile index
-        let char_map_vec = gameboy.bg_window_char_map_bytes(); //Tile data
-        let mut char_map_tiles_bytes = Vec::new();
-
-        //Get Tiles
-        for idx in (0..char_map_vec.len()).step_by(BYTES_PER_TILE) {
-            let tile_bytes = &char_map_vec[idx..idx + BYTES_PER_TILE];
-            let mut image_data_source = Vec::new();
-            //Get Tile Pixel rgba data
-            for i in (0..tile_bytes.len()).step_by(BYTES_PER_8_PIXEL) {
-                let low_bits = BitVec::from_bytes(&[tile_bytes[i]]);
-                let high_bits = BitVec::from_bytes(&[tile_bytes[i + 1]]);
-
-                for pixel_index in 0..8 {
-                    let [r, g, b, a] = match (low_bits[pixel_index], high_bits[pixel_index]) {
-                        (false, false) => [255, 255, 255, 255],
-                        (false, true) => [191, 191, 191, 255],
-                        (true, false) => [64, 64, 64, 255],
-                        (true, true) => [0, 0, 0, 255],
-                    };
-
-                    image_data_source.push(r);
-                    image_data_source.push(g);
-                    image_data_source.push(b);
-                    image_data_source.push(a);
-                }
-            }
-
-            char_map_tiles_bytes.push(image_data_source);
-        }
-
-        //Clear context
-        self.background_canvas.clear_rect(
-            0.0,
-            0.0,
-            self.background_canvas.canvas().unwrap().width() as f64,
-            self.background_canvas.canvas().unwrap().height() as f64,
-        );
-
-        let mut x = 0;
-        let mut y = 0;
-
-        for ele in background_map {
-            // Generate Tile Image data
-            let tile_idx: usize = if gameboy.get_tile_data_selection() == 1 {
-                ele as usize
-            } else {
-                ((ele as i8) as i16 + 128) as usize
-            };
-
-            let tile_bytes = &mut char_map_tiles_bytes[tile_idx];
-            let clamped_image_source = wasm_bindgen::Clamped(&mut tile_bytes[..]);
-
-            let tile_image_data =
-                web_sys::ImageData::new_with_u8_clamped_array(clamped_image_source, 8).unwrap();
-
-            self.background_canvas
-                .put_image_data(&tile_image_data, x as f64, y as f64)
-                .unwrap();
-
-            x = x + 8;
-            if x >= 32 * 8 {
-                x = 0;
-                y = y + 8;
-            }
-        }
-    }
-
-    pub fn update_char_map_canvas(&mut self, gameboy: &mut Gameboy) {
-        let mut image_source = gameboy.char_map_to_image_data();
-        let clamped_image_source = wasm_bindgen::Clamped(&mut image_source[..]);
-
-        let image_data: web_sys::ImageData =
-            web_sys::ImageData::new_with_u8_clamped_array(clamped_image_source, 8).unwrap();
-
-        self.char_map_canvas
-            .put_image_data(&image_data, 0.0, 0.0)
-            .unwrap();
-
-        let tiles_per_row = 12;
-        let width = self.char_map_debug_canvas.canvas().unwrap().width() as f64;
-        let height = self.char_map_debug_canvas.canvas().unwrap().height() as f64;
-
-        self.char_map_debug_canvas
-            .clear_rect(0.0, 0.0, width, height);
-
-        for tile_idx in 0..96 {
-            //Get tile image data
-            let y0 = (tile_idx * 8) as f64;
-            let image_data = self.char_map_canvas.get_image_data(0.0, y0, 8.0, y0 + 8.0);
-            let tile = image_data.unwrap();
-
-            let x = (tile_idx % tiles_per_row) as f64;
-            let y = ((tile_idx / tiles_per_row) as f64).floor();
-            self.char_map_debug_canvas
-                .put_image_data(&tile, x * 8.0, y * 8.0)
-                .unwrap();
-        }
-
-        self.update_char_map_canvas_last_data = image_source;
-    }
-
-    pub fn draw_screen_from_memory(&self, gameboy: &mut Gameboy) {
-        if !gameboy.is_vblank() {
-            return;
-        }
-
-        let is_lcd_enable = gameboy.is_lcd_display_enable();
-        if !is_lcd_enable {
-            return;
-        }
-
-        let background_map = gameboy.bg_map();
-        let char_map_vec = gameboy.bg_window_char_map_bytes();
-
-        //Generate background bytes from char map
-        let mut background_pixels_row_rgba: Vec<Vec<u8>> = Vec::new();
-        background_pixels_row_rgba.resize(256, Vec::new());
-
-        let mut idx = 0;
-        for ele in background_map {
-            let tile_idx: usize = if gameboy.get_tile_data_selection() == 1 {
-                ele as usize
-            } else {
-                ((ele as i8) as i16 + 128) as usize
-            };
-            let tile_start_idx = tile_idx as usize * BYTES_PER_TILE;
-            let tile_end_idx = tile_start_idx + BYTES_PER_TILE;
-
-            let tile_bytes = &char_map_vec[tile_start_idx..tile_end_idx];
-            for i in (0..tile_bytes.len()).step_by(BYTES_PER_8_PIXEL) {
-                let background_y = (idx / 32) * PIXEL_NUM_PER_TILE_COL + i / BYTES_PER_8_PIXEL;
-                let low_bits = BitVec::from_bytes(&[tile_bytes[i]]);
-                let high_bits = BitVec::from_bytes(&[tile_bytes[i + 1]]);
-
-                for pixel_index in 0..8 {
-                    let [r, g, b, a] = match (low_bits[pixel_index], high_bits[pixel_index]) {
-                        (false, false) => [255, 255, 255, 255],
-                        (false, true) => [191, 191, 191, 255],
-                        (true, false) => [64, 64, 64, 255],
-                        (true, true) => [0, 0, 0, 255],
-                    };
-
-                    background_pixels_row_rgba[background_y].push(r);
-                    background_pixels_row_rgba[background_y].push(g);
-                    background_pixels_row_rgba[background_y].push(b);
-                    background_pixels_row_rgba[background_y].push(a);
-                }
-            }
-            idx = idx + 1
-        }
-
-        let background_pixels_rgba_vec: Vec<u8> = background_pixels_row_rgba.concat();
-
-        //Get screen bytes from background bytes
-        let scroll_x = gameboy.get_scroll_x() as usize;
-        let scroll_y = gameboy.get_scroll_y() as usize;
-        let mut screen_pixels_rgba_vec: Vec<u8> = Vec::new();
-
-        for screen_y in 0..144 {
-            //TODO: need to handle x overflow
-            let x = scroll_x;
-            let y = if scroll_y + screen_y > 255 {
-                scroll_y + screen_y - 256
-            } else {
-                scroll_y + screen_y
-            };
-
-            let start = y * BACKGROUND_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL
-                + x * IMAGE_DATA_LENGTH_PER_PIXEL;
-            let end = start + SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
-
-            let screen_row_bytes = &background_pixels_rgba_vec[start..end];
-            screen_pixels_rgba_vec.extend_from_slice(&screen_row_bytes);
-        }
-
-        //Drawing screen
-        self.screen_canvas.clear_rect(
-            0.0,
-            0.0,
-            self.screen_canvas.canvas().unwrap().width() as f64,
-            self.screen_canvas.canvas().unwrap().height() as f64,
-        );
-
-        for screen_y in 0..144 {
-            let start_row = screen_y * SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
-            let end_row = start_row + SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
-            let clamped_image_source =
-                wasm_bindgen::Clamped(&mut screen_pixels_rgba_vec[start_row..end_row]);
-
-            let pixel_row_image_data =
-                web_sys::ImageData::new_with_u8_clamped_array_and_sh(clamped_image_source, 160, 1)
-                    .unwrap();
-            self.screen_canvas
-                .put_image_data(&pixel_row_image_data, 0.0, screen_y as f64)
-                .unwrap();
-        }
-    }
-
-    pub fn draw_screen_with_obj(&self, gameboy: &mut Gameboy) {
-        // if !gameboy.is_vblank() {
-        //     return;
-        // }
-
-        // let is_lcd_enable = gameboy.is_lcd_display_enable();
-        // if !is_lcd_enable {
-        //     return;
-        // }
-
-        let background_map = gameboy.bg_map();
-        let char_map_vec = gameboy.bg_window_char_map_bytes();
-
-        //Generate background bytes from char map
-        let mut background_pixels_row_rgba: Vec<Vec<u8>> = Vec::new();
-        background_pixels_row_rgba.resize(256, Vec::new());
-
-        let mut idx = 0;
-        for ele in background_map {
-            let tile_idx: usize = if gameboy.get_tile_data_selection() == 1 {
-                ele as usize
-            } else {
-                ((ele as i8) as i16 + 128) as usize
-            };
-            let tile_start_idx = tile_idx as usize * BYTES_PER_TILE;
-            let tile_end_idx = tile_start_idx + BYTES_PER_TILE;
-
-            let tile_bytes = &char_map_vec[tile_start_idx..tile_end_idx];
-            for i in (0..tile_bytes.len()).step_by(BYTES_PER_8_PIXEL) {
-                let background_y = (idx / 32) * PIXEL_NUM_PER_TILE_COL + i / BYTES_PER_8_PIXEL;
-                let low_bits = BitVec::from_bytes(&[tile_bytes[i]]);
-                let high_bits = BitVec::from_bytes(&[tile_bytes[i + 1]]);
-
-                for pixel_index in 0..8 {
-                    let [r, g, b, a] = match (low_bits[pixel_index], high_bits[pixel_index]) {
-                        (false, false) => [255, 255, 255, 255],
-                        (false, true) => [191, 191, 191, 255],
-                        (true, false) => [64, 64, 64, 255],
-                        (true, true) => [0, 0, 0, 255],
-                    };
-
-                    background_pixels_row_rgba[background_y].push(r);
-                    background_pixels_row_rgba[background_y].push(g);
-                    background_pixels_row_rgba[background_y].push(b);
-                    background_pixels_row_rgba[background_y].push(a);
-                }
-            }
-            idx = idx + 1
-        }
-
-        let background_pixels_rgba_vec: Vec<u8> = background_pixels_row_rgba.concat();
-
-        //Get screen bytes from background bytes
-        let scroll_x = gameboy.get_scroll_x() as usize;
-        let scroll_y = gameboy.get_scroll_y() as usize;
-        let mut screen_pixels_rgba_vec: Vec<u8> = Vec::new();
-
-        for screen_y in 0..144 {
-            //TODO: need to handle x overflow
-            let x = scroll_x;
-            let y = if scroll_y + screen_y > 255 {
-                scroll_y + screen_y - 256
-            } else {
-                scroll_y + screen_y
-            };
-
-            let start = y * BACKGROUND_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL
-                + x * IMAGE_DATA_LENGTH_PER_PIXEL;
-            let end = start + SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
-
-            let screen_row_bytes = &background_pixels_rgba_vec[start..end];
-            screen_pixels_rgba_vec.extend_from_slice(&screen_row_bytes);
-        }
-
-        //Drawing screen
-        self.screen_canvas.clear_rect(
-            0.0,
-            0.0,
-            self.screen_canvas.canvas().unwrap().width() as f64,
-            self.screen_canvas.canvas().unwrap().height() as f64,
-        );
-
-        let blank_screen_with_sprites_rgba = self.get_blank_screen_pixel_with_sprites(gameboy);
-
-        for screen_y in 0..144 {
-            let start_row = screen_y * SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
-            let end_row = start_row + SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
-
-            let screen_row_rgba = &screen_pixels_rgba_vec[start_row..end_row];
-
-            let blank_screen_with_sprites_rgba_row =
-                &blank_screen_with_sprites_rgba[start_row..end_row];
-
-            let mut result: Vec<u8> = Vec::new();
-
-            //Overwrite screen with sprite rgb data
-            for pixel_rgba in
-                (0..blank_screen_with_sprites_rgba_row.len()).step_by(IMAGE_DATA_LENGTH_PER_PIXEL)
-            {
-                let r = blank_screen_with_sprites_rgba_row[pixel_rgba];
-                let g = blank_screen_with_sprites_rgba_row[pixel_rgba + 1];
-                let b = blank_screen_with_sprites_rgba_row[pixel_rgba + 2];
-                match (r, g, b) {
-                    (255, 255, 255) => {
-                        result.extend_from_slice(
-                            &screen_row_rgba[pixel_rgba..pixel_rgba + IMAGE_DATA_LENGTH_PER_PIXEL],
-                        );
-                    }
-                    _other => {
-                        result.extend_from_slice(
-                            &blank_screen_with_sprites_rgba_row
-                                [pixel_rgba..pixel_rgba + IMAGE_DATA_LENGTH_PER_PIXEL],
-                        );
-                    }
-                }
-            }
-
-            let clamped_image_source = wasm_bindgen::Clamped(&mut result[..]);
-
-            let pixel_row_image_data =
-                web_sys::ImageData::new_with_u8_clamped_array_and_sh(clamped_image_source, 160, 1)
-                    .unwrap();
-            self.screen_canvas
-                .put_image_data(&pixel_row_image_data, 0.0, screen_y as f64)
-                .unwrap();
-        }
-    }
-
-    pub fn draw_obj(&self, gameboy: &mut Gameboy) {
-        let background_map = gameboy.bg_map();
-        let char_map_vec = gameboy.bg_window_char_map_bytes();
-
-        //Generate background bytes from char map
-        let mut background_pixels_row_rgba: Vec<Vec<u8>> = Vec::new();
-        background_pixels_row_rgba.resize(256, Vec::new());
-
-        let mut idx = 0;
-        for ele in background_map {
-            let tile_idx: usize = if gameboy.get_tile_data_selection() == 1 {
-                ele as usize
-            } else {
-                ((ele as i8) as i16 + 128) as usize
-            };
-            let tile_start_idx = tile_idx as usize * BYTES_PER_TILE;
-            let tile_end_idx = tile_start_idx + BYTES_PER_TILE;
-
-            let tile_bytes = &char_map_vec[tile_start_idx..tile_end_idx];
-            for i in (0..tile_bytes.len()).step_by(BYTES_PER_8_PIXEL) {
-                let background_y = (idx / 32) * PIXEL_NUM_PER_TILE_COL + i / BYTES_PER_8_PIXEL;
-                let low_bits = BitVec::from_bytes(&[tile_bytes[i]]);
-                let high_bits = BitVec::from_bytes(&[tile_bytes[i + 1]]);
-
-                for pixel_index in 0..8 {
-                    let [r, g, b, a] = match (low_bits[pixel_index], high_bits[pixel_index]) {
-                        (false, false) => [255, 255, 255, 255],
-                        (false, true) => [191, 191, 191, 255],
-                        (true, false) => [64, 64, 64, 255],
-                        (true, true) => [0, 0, 0, 255],
-                    };
-
-                    background_pixels_row_rgba[background_y].push(r);
-                    background_pixels_row_rgba[background_y].push(g);
-                    background_pixels_row_rgba[background_y].push(b);
-                    background_pixels_row_rgba[background_y].push(a);
-                }
-            }
-            idx = idx + 1
-        }
-
-        let background_pixels_rgba_vec: Vec<u8> = background_pixels_row_rgba.concat();
-
-        //Get screen bytes from background bytes
-        let scroll_x = gameboy.get_scroll_x() as usize;
-        let scroll_y = gameboy.get_scroll_y() as usize;
-        let mut screen_pixels_rgba_vec: Vec<u8> = Vec::new();
-
-        for screen_y in 0..144 {
-            //TODO: need to handle x overflow
-            let x = scroll_x;
-            let y = if scroll_y + screen_y > 255 {
-                scroll_y + screen_y - 256
-            } else {
-                scroll_y + screen_y
-            };
-
-            let start = y * BACKGROUND_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL
-                + x * IMAGE_DATA_LENGTH_PER_PIXEL;
-            let end = start + SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
-
-            let screen_row_bytes = &background_pixels_rgba_vec[start..end];
-            screen_pixels_rgba_vec.extend_from_slice(&screen_row_bytes);
-        }
-
-        //Drawing screen
-        self.screen_canvas.clear_rect(
-            0.0,
-            0.0,
-            self.screen_canvas.canvas().unwrap().width() as f64,
-            self.screen_canvas.canvas().unwrap().height() as f64,
-        );
-
-        let mut blank_screen_with_sprites_rgba = self.get_blank_screen_pixel_with_sprites(gameboy);
-
-        for screen_y in 0..144 {
-            let start_row = screen_y * SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
-            let end_row = start_row + SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
-
-            let clamped_image_source =
-                wasm_bindgen::Clamped(&mut blank_screen_with_sprites_rgba[start_row..end_row]);
-
-            let pixel_row_image_data =
-                web_sys::ImageData::new_with_u8_clamped_array_and_sh(clamped_image_source, 160, 1)
-                    .unwrap();
-            self.screen_canvas
-                .put_image_data(&pixel_row_image_data, 0.0, screen_y as f64)
-                .unwrap();
-        }
-    }
-
-    fn get_blank_screen_pixel_with_sprites(&self, gameboy: &mut Gameboy) -> Vec<u8> {
-        let char_map_vec = gameboy.obj_char_map_bytes(); //Tile data
-
-        let mut tiles_rgba_vec = Vec::new();
-
-        let screen_rbga_vec_length =
-            SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * IMAGE_DATA_LENGTH_PER_PIXEL;
-
-        let mut entire_screen_pixels_rgba = Vec::new();
-        entire_screen_pixels_rgba.resize_with(screen_rbga_vec_length, || 255);
-
-        //Get Tiles
-        for idx in (0..char_map_vec.len()).step_by(BYTES_PER_TILE) {
-            let tile_bytes = &char_map_vec[idx..idx + BYTES_PER_TILE];
-            let mut image_data_source = Vec::new();
-            //Get Tile Pixel rgba data
-            for i in (0..tile_bytes.len()).step_by(BYTES_PER_8_PIXEL) {
-                let low_bits = BitVec::from_bytes(&[tile_bytes[i]]);
-                let high_bits = BitVec::from_bytes(&[tile_bytes[i + 1]]);
-
-                for pixel_index in 0..8 {
-                    let [r, g, b, a] = match (low_bits[pixel_index], high_bits[pixel_index]) {
-                        (false, false) => [255, 255, 255, 255],
-                        (false, true) => [191, 191, 191, 255],
-                        (true, false) => [64, 64, 64, 255],
-                        (true, true) => [0, 0, 0, 255],
-                    };
-
-                    image_data_source.push(r);
-                    image_data_source.push(g);
-                    image_data_source.push(b);
-                    image_data_source.push(a);
-                }
-            }
-
-            tiles_rgba_vec.push(image_data_source);
-        }
-
-        // Fill in sprites data in blank temp_screen
-        for obj in gameboy.all_sprites() {
-            let obj_in_screen = obj.y >= 0 && obj.y + 8 <= 160;
-            // && obj.x >= 0 && obj.x + 8 <= 0
-
-            if !obj_in_screen {
-                continue;
-            }
-
-            let tile_idx = obj.pattern_num as usize;
-            let tile_rgba = &tiles_rgba_vec[tile_idx];
-
-            for obj_row in 0..8 {
-                let x = obj.x;
-                let y = obj.y + obj_row;
-
-                let start: usize =
-                    y as usize * SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL
-                        + x as usize * IMAGE_DATA_LENGTH_PER_PIXEL;
-                let end: usize = start + SPRITE_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
-
-                //Filling sprite tile row
-                // entire_screen_pixels_rgba[start..end] =
-                //     tile_rgba[obj_row as usize..obj_row as usize + 8 * IMAGE_DATA_LENGTH_PER_PIXEL];
-
-                let tile_start =
-                    obj_row as usize * SPRITE_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
-                let tile_end = tile_start + 8 * IMAGE_DATA_LENGTH_PER_PIXEL;
-
-                let _a = entire_screen_pixels_rgba
-                    .splice(start..end, tile_rgba[tile_start..tile_end].iter().cloned());
-            }
-        }
-
-        // info!("entier screen pixels: {:?}", entire_screen_pixels_rgba);
-        entire_screen_pixels_rgba
-    }
-
-    pub fn make_canvas(
-        canvas_selector: &str,
-        width: u32,
-        height: u32,
-    ) -> web_sys::CanvasRenderingContext2d {
-        let document = web_sys::window().unwrap().document().unwrap();
-
-        let el = document.get_element_by_id(canvas_selector).unwrap();
-        let el: web_sys::HtmlCanvasElement = el
-            .dyn_into::<web_sys::HtmlCanvasElement>()
-            .map_err(|_| ())
-            .unwrap();
-
-        let ctx = el
-            .get_context("2d")
-            .unwrap()
-            .unwrap()
-            .dyn_into::<web_sys::CanvasRenderingContext2d>()
-            .unwrap();
-
-        el.set_width(width);
-        el.set_height(height);
-
-        ctx.set_image_smoothing_enabled(false);
-
-        ctx
-    }
-}
-
-#[wasm_bindgen]
-pub struct FmOsc {
-    ctx: AudioContext,
-    /// The primary oscillator.  This will be the fundamental frequency
-    primary: web_sys::OscillatorNode,
-    /// Overall gain (volume) control
-    gain: web_sys::GainNode,
-    /// Amount of frequency modulation
-    fm_gain: web_sys::GainNode,
-    /// The oscillator that will modulate the primary oscillator's frequency
-    fm_osc: web_sys::OscillatorNode,
-    /// The ratio between the primary frequency and the fm_osc frequency.
-    /// Generally fractional values like 1/2 or 1/4 sound best
-    fm_freq_ratio: f32,
-    fm_gain_ratio: f32,
-}
-
-#[wasm_bindgen]
-impl FmOsc {
-    #[wasm_bindgen(constructor)]
-    pub fn new() -> Result<FmOsc, JsValue> {
-        let ctx = web_sys::AudioContext::new()?;
-
-        // Create our web audio objects.
-        let primary = ctx.create_oscillator()?;
-        let fm_osc = ctx.create_oscillator()?;
-        let gain = ctx.create_gain()?;
-        let fm_gain = ctx.create_gain()?;
-
-        // Some initial settings:
-        primary.set_type(OscillatorType::Square);
-        primary.frequency().set_value(0.0);
-        gain.gain().set_value(0.0); // starts muted
-        fm_gain.gain().set_value(0.0); // no initial frequency modulation
-        fm_osc.set_type(OscillatorType::Square);
-        fm_osc.frequency().set_value(0.0);
-
-        // Connect the nodes up!
-
-        // The primary oscillator is routed through the gain node, so that
-        // it can control the overall output volume.
-        primary.connect_with_audio_node(&gain)?;
-
-        // Then connect the gain node to the AudioContext destination (aka
-        // your speakers).
-        gain.connect_with_audio_node(&ctx.destination())?;
-
-        // The FM oscillator is connected to its own gain node, so it can
-        // control the amount of modulation.
-        fm_osc.connect_with_audio_node(&fm_gain)?;
-
-        // Connect the FM oscillator to the frequency parameter of the main
-        // oscillator, so that the FM node can modulate its frequency.
-        fm_gain.connect_with_audio_param(&primary.frequency())?;
-
-        // Start the oscillators!
-        primary.start()?;
-        fm_osc.start()?;
-
-        Ok(FmOsc {
-            ctx,
-            primary,
-            gain,
-            fm_gain,
-            fm_osc,
-            fm_freq_ratio: 0.0,
-            fm_gain_ratio: 0.0,
-        })
-    }
-
-    pub fn volume(&self) -> f32 {
-        let gain = self.gain.gain().value();
-        gain
-    }
-
-    pub fn frequency(&self) -> f32 {
-        let fr = self.primary.frequency().value();
-        fr
-    }
-
-    /// Sets the gain for this oscillator, between 0.0 and 1.0.
-    #[wasm_bindgen]
-    pub fn set_gain(&self, mut gain: f32) {
-        if gain > 1.0 {
-            gain = 1.0;
-        }
-        if gain < 0.0 {
-            gain = 0.0;
-        }
-        self.gain.gain().set_value(gain);
-    }
-
-    #[wasm_bindgen]
-    pub fn set_primary_frequency(&self, freq: f32) {
-        self.primary.frequency().set_value(freq);
-
-        // The frequency of the FM oscillator depends on the frequency of the
-        // primary oscillator, so we update the frequency of both in this method.
-        self.fm_osc.frequency().set_value(self.fm_freq_ratio * freq);
-        self.fm_gain.gain().set_value(self.fm_gain_ratio * freq);
-    }
-
-    /// This should be between 0 and 1, though higher values are accepted.
-    #[wasm_bindgen]
-    pub fn set_fm_amount(&mut self, amt: f32) {
-        self.fm_gain_ratio = amt;
-
-        self.fm_gain
-            .gain()
-            .set_value(self.fm_gain_ratio * self.primary.frequency().value());
-    }
-
-    #[wasm_bindgen]
-    pub fn set_gain_shift(&mut self, original_volume_float: f32, shift_num: u8, is_increase: bool) {
-        let current_time = self.ctx.current_time();
-        let one64th = 1.0 / 64.0;
-        let shift_length = (one64th) as f64 * shift_num as f64;
-        let original_volume = (original_volume_float * 10.0) as u8;
-
-        if is_increase {
-            let steps_to_max = MAX_GAMEBOY_VOLUME - (original_volume as u8 * 10);
-            for shift_offset in 1..steps_to_max as u8 {
-                let at_time = current_time + (shift_offset as f64 * shift_length);
-                let volume = (original_volume + (shift_offset)) as f32 / 10.0;
-
-                match self.gain.gain().set_value_at_time(volume, at_time) {
-                    Ok(_v) => (),
-                    Err(_e) => (),
-                }
-            }
-        } else {
-            let steps_to_min = original_volume as u8 + 1;
-            for shift_offset in 1..steps_to_min {
-                let at_time = current_time + (shift_offset as f64 * shift_length);
-                let volume = (original_volume - (shift_offset)) as f32 / 10.0;
-
-                // info!(
-                //     "volume={:?} original_volume={:?} shift_offset={:?}",
-                //     volume, original_volume, shift_offset
-                // );
-
-                match self.gain.gain().set_value_at_time(volume, at_time) {
-                    Ok(_v) => (),
-                    Err(_e) => (),
-                }
-            }
-        }
-    }
-
-    /// This should be between 0 and 1, though higher values are accepted.
-    #[wasm_bindgen]
-    pub fn set_fm_frequency(&mut self, amt: f32) {
-        self.fm_freq_ratio = amt;
-        self.fm_osc
-            .frequency()
-            .set_value(self.fm_freq_ratio * self.primary.frequency().value());
-    }
-}
-
 #[wasm_bindgen]
 pub struct Channel {
     sweep_time: f32,
@@ -972,20 +251,7 @@ impl Registers {
         let mut flag_z = false;
         let flag_n = false;
 
-        let check_bits = match bit_idx {
-            0 => 0b00000001,
-            1 => 0b00000010,
-            2 => 0b00000100,
-            3 => 0b00001000,
-            4 => 0b00010000,
-            5 => 0b00100000,
-            6 => 0b01000000,
-            7 => 0b10000000,
-            _ => {
-                println!("Invalid bit index");
-                std::process::exit(1)
-            }
-        };
+        let check_bits = 1u8 << bit_idx;
 
         let bit = register & check_bits;
         if bit == 0 {
@@ -998,18 +264,16 @@ impl Registers {
 
     fn add_signed_number(&self, unsigned: u16, signed: i8) -> u16 {
         let is_minus = signed.signum() == -1;
-        let value = signed.abs() as u16;
+        let value = signed.unsigned_abs() as u16;
         if is_minus {
-            let result = unsigned - (value as u16);
-            result
+            unsigned - value
         } else {
-            let result = unsigned + value as u16;
-            result
+            unsigned + value
         }
     }
 
     fn inc_pc(&mut self) {
-        self.pc = self.pc + 1;
+        self.pc += 1;
     }
 
     fn check_half_carry_u16_plus_i8(&self, unsigned: u16, signed: i8, sum_value: u16) -> bool {
@@ -1029,11 +293,11 @@ impl Registers {
     }
 
     fn check_carry(&self, num_a: u8, num_b: u8) -> bool {
-        (num_a & 0x00ff) as u16 + (num_b & 0x00ff) as u16 & 0x100 == 0x100
+        (num_a as u16 + num_b as u16) & 0x100 == 0x100
     }
 
     fn check_half_carry(&self, num_a: u8, num_b: u8) -> bool {
-        (num_a & 0xf) + (num_b & 0xf) & 0x010 == 0x010
+        ((num_a & 0xf) + (num_b & 0xf)) & 0x010 == 0x010
     }
 
     fn check_half_carry_sub(&self, num_a: u8, num_b: u8) -> bool {
@@ -1041,16 +305,15 @@ impl Registers {
     }
 
     fn check_carry_two_bytes(&self, num_a: u16, num_b: u16) -> bool {
-        (num_a & 0xffff) as u32 + (num_b & 0xffff) as u32 & 0x10000 == 0x10000
+        (num_a as u32 + num_b as u32) & 0x10000 == 0x10000
     }
 
     fn check_half_carry_two_bytes(&self, num_a: u16, num_b: u16) -> bool {
-        (num_a & 0xfff) as u16 + (num_b & 0xfff) as u16 & 0x1000 == 0x1000
+        ((num_a & 0xfff) + (num_b & 0xfff)) & 0x1000 == 0x1000
     }
 
     fn combine_two_bytes(&self, first_b: u8, second_b: u8) -> u16 {
-        let two_bytes_value = ((first_b as u16) << 8) | second_b as u16;
-        two_bytes_value
+        ((first_b as u16) << 8) | second_b as u16
     }
 
     fn set_pc(&mut self, value: u16) {
@@ -1142,18 +405,19 @@ impl Registers {
             bv.set(3, true)
         }
 
-        let result = bv.to_bytes()[0];
-        result
+        bv.to_bytes()[0]
     }
 }
 
+// Tile data in the colours of the palette's background shades, taking the
+// colour numbers as shades
 #[wasm_bindgen]
-pub fn pixels_to_image_data(pixels_as_byte_vec: Vec<u8>) -> Vec<u8> {
+pub fn pixels_to_image_data(pixels_as_byte_vec: Vec<u8>, palette: &DisplayPalette) -> Vec<u8> {
     let new_image_data = {
         let len = pixels_as_byte_vec.len();
         let bpp = 4;
         let size = len * bpp;
-        let mut image_data: Vec<u8> = Vec::with_capacity(size as usize);
+        let mut image_data: Vec<u8> = Vec::with_capacity(size);
 
         for idx in (0..pixels_as_byte_vec.len()).step_by(2) {
             // Consume two bytes each iteration, and produce
@@ -1162,24 +426,15 @@ pub fn pixels_to_image_data(pixels_as_byte_vec: Vec<u8>) -> Vec<u8> {
             let high_bits = BitVec::from_bytes(&[pixels_as_byte_vec[idx + 1]]);
 
             for pixel_index in 0..8 {
-                let [r, g, b, a] = match (low_bits[pixel_index], high_bits[pixel_index]) {
-                    (false, false) => [255, 255, 255, 255],
-                    (false, true) => [191, 191, 191, 255],
-                    (true, false) => [64, 64, 64, 255],
-                    (true, true) => [0, 0, 0, 255],
-                };
-
-                image_data.push(r);
-                image_data.push(g);
-                image_data.push(b);
-                image_data.push(a);
+                let color = (high_bits[pixel_index] as u8) << 1 | low_bits[pixel_index] as u8;
+                image_data.extend_from_slice(&palette.bg_rgba(color));
             }
         }
 
         image_data
     };
 
-    return new_image_data.to_vec();
+    new_image_data.to_vec()
 }
 
 #[wasm_bindgen]
@@ -1188,11 +443,27 @@ pub struct SerializedGameboy {
     registers: Registers,
     total_cycle_num: usize,
     vram_cycle_num: u16,
-    timer_cycle_num: usize,
-    timer: usize,
-    cpu_clock: usize,
+    timer: Timer,
     break_points: Vec<u16>,
     memory: Vec<u8>,
+    #[serde(default)]
+    boot_rom: Option<Vec<u8>>,
+    #[serde(default)]
+    model: Model,
+    // The cartridge's battery save, in the .sav layout
+    #[serde(default)]
+    cartridge_save: Vec<u8>,
+    #[serde(default)]
+    mapper_state: Option<MapperState>,
+    #[serde(default)]
+    ppu_state: Option<ppu::State>,
+    // Saved mid HALT or right after an EI, older save states are neither
+    #[serde(default)]
+    is_halt: bool,
+    #[serde(default)]
+    is_halt_bug: bool,
+    #[serde(default)]
+    should_enable_ime: bool,
 }
 
 #[wasm_bindgen]
@@ -1203,27 +474,27 @@ pub struct Gameboy {
     screen_height: u32,
     image_data: Vec<u8>,
     registers: Registers,
-    fm_osc: FmOsc,
     total_cycle_num: usize,
-    vram_cycle_num: u16,
-    timer_cycle_num: usize,
-    timer: usize,
-    cpu_clock: usize,
+    timer: Timer,
     is_running: bool,
     is_halt: bool,
+    is_halt_bug: bool,
+    should_enable_ime: bool,
     should_draw: bool,
     break_points: Vec<u16>,
     memory: Vec<u8>,
+    boot_rom: Option<Vec<u8>>,
+    model: Model,
     cpu_paused: bool,
-    cartridge: Vec<u8>,
-    mbc: u8,
-    rom_bank: u8,
-    ram_bank: u8,
-    ram_bank_memory: Vec<u8>,
-    is_ram_enabled: bool,
-    is_rom_banking_enabled: bool,
+    cartridge_header: CartridgeHeader,
+    mapper: Box<dyn Mapper>,
     joypad_state: u8,
-    divide_register_cycle_counter: u16
+    serial_output: String,
+    is_square1_changed: bool,
+    instruction_pc: u16,
+    fault: Option<EmulationError>,
+    cheats: Cheats,
+    ppu: Ppu,
 }
 
 #[wasm_bindgen]
@@ -2647,7 +1918,7 @@ impl Gameboy {
                     .combine_two_bytes(self.registers.d, self.registers.e);
                 let value = self.read_memory(d_e);
 
-                self.registers.set_a(value as u8);
+                self.registers.set_a(value);
                 self.registers.inc_pc();
             }
 
@@ -2657,7 +1928,7 @@ impl Gameboy {
                     .registers
                     .combine_two_bytes(self.registers.h, self.registers.l);
                 self.write_memory(h_l, self.registers.a);
-                self.registers.set_hl(h_l.wrapping_sub(1) as u16);
+                self.registers.set_hl(h_l.wrapping_sub(1));
                 self.registers.inc_pc();
             }
             0x022 => {
@@ -2702,11 +1973,7 @@ impl Gameboy {
                         false => 0b00000000,
                     };
 
-                if self.registers.a & 0b10000000 == 0b10000000 {
-                    flag_c = true
-                } else {
-                    flag_c = false
-                }
+                flag_c = self.registers.a & 0b10000000 == 0b10000000;
                 self.registers.set_a(result);
                 self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
                 self.registers.inc_pc();
@@ -3004,11 +2271,7 @@ impl Gameboy {
                         false => 0b00000000,
                     };
 
-                if self.registers.a & 0b00000001 == 0b00000001 {
-                    flag_c = true
-                } else {
-                    flag_c = false
-                }
+                flag_c = self.registers.a & 0b00000001 == 0b00000001;
                 self.registers.set_a(result);
                 self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
                 self.registers.inc_pc();
@@ -3039,7 +2302,10 @@ impl Gameboy {
 
                 other => {
                     info!("Unknown instruction after 0x0DD: {:x}", other);
-                    std::process::exit(1)
+                    self.raise_fault(EmulationError::IllegalOpcode {
+                        opcode: other,
+                        pc: self.instruction_pc,
+                    });
                 }
             },
 
@@ -3551,9 +2817,6 @@ impl Gameboy {
                 {
                     flag_h = true
                 }
-                if self.registers.a < self.registers.a {
-                    flag_c = true;
-                }
                 self.registers.set_a(value);
                 self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
                 self.registers.inc_pc();
@@ -3715,7 +2978,7 @@ impl Gameboy {
                 //LD A, (nn) -> 16
                 let address = self.following_two_bytes(self.registers.pc as usize);
                 let value = self.read_memory(address);
-                self.registers.set_a(value as u8);
+                self.registers.set_a(value);
                 self.registers.inc_pc();
             }
 
@@ -3759,7 +3022,7 @@ impl Gameboy {
 
             0x0b7 => {
                 //OR A -> 4
-                let value = self.registers.a | self.registers.a;
+                let value = self.registers.a;
                 self.registers.set_a(value);
 
                 if value == 0 {
@@ -4073,7 +3336,7 @@ impl Gameboy {
                 ) {
                     flag_c = true;
                 }
-                self.registers.set_hl(value as u16);
+                self.registers.set_hl(value);
                 self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
                 self.registers.inc_pc();
             }
@@ -4240,7 +3503,7 @@ impl Gameboy {
                 }
             }
 
-            0x0Da => {
+            0x0da => {
                 //JP C,nn -> 12
                 let value = self.following_two_bytes(pointer);
                 if self.registers.f.c {
@@ -4307,7 +3570,7 @@ impl Gameboy {
                     .registers
                     .combine_two_bytes(self.registers.b, self.registers.c);
                 let value = self.read_memory(b_c);
-                self.registers.set_a(value as u8);
+                self.registers.set_a(value);
                 self.registers.inc_pc();
             }
 
@@ -4526,9 +3789,7 @@ impl Gameboy {
 
             0x0BF => {
                 //CP A
-                if self.registers.a == self.registers.a {
-                    flag_z = true
-                }
+                flag_z = true;
                 flag_n = true;
 
                 if self
@@ -4538,10 +3799,6 @@ impl Gameboy {
                     flag_h = true
                 }
 
-                if self.registers.a < self.registers.a {
-                    flag_c = true;
-                }
-
                 self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
                 self.registers.inc_pc();
             }
@@ -4837,11 +4094,7 @@ impl Gameboy {
                 let cf = self.registers.a << 7;
                 let result = self.registers.a >> 1 | cf;
 
-                if cf & 0b10000000 == 0b10000000 {
-                    flag_c = true
-                } else {
-                    flag_c = false
-                }
+                flag_c = cf & 0b10000000 == 0b10000000;
                 self.registers.set_a(result);
                 self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
                 self.registers.inc_pc();
@@ -4900,22 +4153,30 @@ impl Gameboy {
 
             0x0f3 => {
                 // DI
-                //Interrupts are disabled after instruction after DI is executed.
+                // Also cancels an EI that hasn't taken effect yet
                 self.registers.f.set_ime(false);
+                self.should_enable_ime = false;
                 self.registers.inc_pc();
             }
 
             0x0fb => {
                 // EI
+                // IME is only set once the following instruction is done, see step()
                 self.registers.inc_pc();
-                self.registers.f.set_ime(true);
+                self.should_enable_ime = true;
             }
 
             0x076 => {
                 //HALT Power down CPU until interrupt occurs -> 4
-                //Implementation escalated to Gameboy. Checking at fn execute_opcodes()
-                info!("NEED TO IMPLEMENT HALT FUNCTION FOR 0x076");
                 self.registers.inc_pc();
+                if !self.registers.f.ime && self.pending_interrupts() != 0 {
+                    // With an interrupt already pending and IME off the CPU
+                    // doesn't halt, and fails to increment PC after the next
+                    // opcode fetch
+                    self.is_halt_bug = true;
+                } else {
+                    self.is_halt = true;
+                }
             }
 
             0x010 => {
@@ -4946,7 +4207,10 @@ impl Gameboy {
 
             other => {
                 info!("No opcode found for {:x} at {:x}", other, pointer);
-                std::process::exit(1)
+                self.raise_fault(EmulationError::IllegalOpcode {
+                    opcode: other,
+                    pc: self.instruction_pc,
+                });
             }
         }
     }
@@ -4967,8 +4231,7 @@ impl Gameboy {
         self.write_memory(self.registers.sp, 0);
         self.registers.set_sp(self.registers.sp + 1);
 
-        let result = self.registers.combine_two_bytes(firt_byte, second_byte);
-        result
+        self.registers.combine_two_bytes(firt_byte, second_byte)
     }
 
     fn following_byte(&mut self, address: usize) -> u8 {
@@ -4992,6 +4255,23 @@ impl Gameboy {
         );
     }
 
+    fn write_register_by_name(&mut self, register_name: &str, hl_value: Option<u16>, value: u8) {
+        match (register_name, hl_value) {
+            ("a", _) => self.registers.set_a(value),
+            ("b", _) => self.registers.set_b(value),
+            ("c", _) => self.registers.set_c(value),
+            ("d", _) => self.registers.set_d(value),
+            ("e", _) => self.registers.set_e(value),
+            ("h", _) => self.registers.set_h(value),
+            ("l", _) => self.registers.set_l(value),
+            ("h_l", Some(address)) => self.write_memory(address, value),
+            _ => self.raise_fault(EmulationError::InvalidRegisterState {
+                description: format!("Invalid register name {:?}", register_name),
+                pc: self.instruction_pc,
+            }),
+        }
+    }
+
     fn res_b_r(
         &mut self,
         bit_idx: u8,
@@ -4999,36 +4279,10 @@ impl Gameboy {
         register_name: &str,
         hl_value: Option<u16>,
     ) {
-        let check_bits = match bit_idx {
-            0 => 0b11111110,
-            1 => 0b11111101,
-            2 => 0b11111011,
-            3 => 0b11110111,
-            4 => 0b11101111,
-            5 => 0b11011111,
-            6 => 0b10111111,
-            7 => 0b01111111,
-            _ => {
-                println!("Invalid bit index");
-                std::process::exit(1)
-            }
-        };
+        let check_bits = !(1u8 << bit_idx);
 
         let value = register_value & check_bits;
-        match register_name {
-            "a" => self.registers.set_a(value),
-            "b" => self.registers.set_b(value),
-            "c" => self.registers.set_c(value),
-            "d" => self.registers.set_d(value),
-            "e" => self.registers.set_e(value),
-            "h" => self.registers.set_h(value),
-            "l" => self.registers.set_l(value),
-            "h_l" => self.write_memory(hl_value.unwrap(), value),
-            _ => {
-                println!("Invalid register name");
-                std::process::exit(1)
-            }
-        }
+        self.write_register_by_name(register_name, hl_value, value);
     }
 
     fn set_b_r(
@@ -5038,36 +4292,10 @@ impl Gameboy {
         register_name: &str,
         hl_value: Option<u16>,
     ) {
-        let check_bits = match bit_idx {
-            0 => 0b00000001,
-            1 => 0b00000010,
-            2 => 0b00000100,
-            3 => 0b00001000,
-            4 => 0b00010000,
-            5 => 0b00100000,
-            6 => 0b01000000,
-            7 => 0b10000000,
-            _ => {
-                println!("Invalid bit index");
-                std::process::exit(1)
-            }
-        };
+        let check_bits = 1u8 << bit_idx;
 
         let value = register_value | check_bits;
-        match register_name {
-            "a" => self.registers.set_a(value),
-            "b" => self.registers.set_b(value),
-            "c" => self.registers.set_c(value),
-            "d" => self.registers.set_d(value),
-            "e" => self.registers.set_e(value),
-            "h" => self.registers.set_h(value),
-            "l" => self.registers.set_l(value),
-            "h_l" => self.write_memory(hl_value.unwrap(), value),
-            _ => {
-                println!("Invalid register name");
-                std::process::exit(1)
-            }
-        }
+        self.write_register_by_name(register_name, hl_value, value);
     }
 
     fn srl(&mut self, register_value: u8, register_name: &str, hl_value: Option<u16>) {
@@ -5085,20 +4313,7 @@ impl Gameboy {
             flag_c = true
         }
 
-        match register_name {
-            "a" => self.registers.set_a(result),
-            "b" => self.registers.set_b(result),
-            "c" => self.registers.set_c(result),
-            "d" => self.registers.set_d(result),
-            "e" => self.registers.set_e(result),
-            "h" => self.registers.set_h(result),
-            "l" => self.registers.set_l(result),
-            "h_l" => self.write_memory(hl_value.unwrap(), result),
-            _ => {
-                println!("Invalid register name");
-                std::process::exit(1)
-            }
-        }
+        self.write_register_by_name(register_name, hl_value, result);
 
         self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
     }
@@ -5117,20 +4332,7 @@ impl Gameboy {
         if register_value & 0b00000001 == 0b00000001 {
             flag_c = true
         }
-        match register_name {
-            "a" => self.registers.set_a(result),
-            "b" => self.registers.set_b(result),
-            "c" => self.registers.set_c(result),
-            "d" => self.registers.set_d(result),
-            "e" => self.registers.set_e(result),
-            "h" => self.registers.set_h(result),
-            "l" => self.registers.set_l(result),
-            "h_l" => self.write_memory(hl_value.unwrap(), result),
-            _ => {
-                println!("Invalid register name");
-                std::process::exit(1)
-            }
-        }
+        self.write_register_by_name(register_name, hl_value, result);
 
         self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
     }
@@ -5141,26 +4343,13 @@ impl Gameboy {
         let flag_h = false;
         let flag_c = false;
 
-        let result = register_value << 4 | register_value >> 4;
+        let result = register_value.rotate_right(4);
 
         if result == 0 {
             flag_z = true;
         }
 
-        match register_name {
-            "a" => self.registers.set_a(result),
-            "b" => self.registers.set_b(result),
-            "c" => self.registers.set_c(result),
-            "d" => self.registers.set_d(result),
-            "e" => self.registers.set_e(result),
-            "h" => self.registers.set_h(result),
-            "l" => self.registers.set_l(result),
-            "h_l" => self.write_memory(hl_value.unwrap(), result),
-            _ => {
-                println!("Invalid register name");
-                std::process::exit(1)
-            }
-        }
+        self.write_register_by_name(register_name, hl_value, result);
 
         self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
     }
@@ -5181,20 +4370,7 @@ impl Gameboy {
             flag_c = true
         }
 
-        match register_name {
-            "a" => self.registers.set_a(result),
-            "b" => self.registers.set_b(result),
-            "c" => self.registers.set_c(result),
-            "d" => self.registers.set_d(result),
-            "e" => self.registers.set_e(result),
-            "h" => self.registers.set_h(result),
-            "l" => self.registers.set_l(result),
-            "h_l" => self.write_memory(hl_value.unwrap(), result),
-            _ => {
-                println!("Invalid register name");
-                std::process::exit(1)
-            }
-        }
+        self.write_register_by_name(register_name, hl_value, result);
 
         self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
     }
@@ -5220,20 +4396,7 @@ impl Gameboy {
             flag_c = true
         }
 
-        match register_name {
-            "a" => self.registers.set_a(result),
-            "b" => self.registers.set_b(result),
-            "c" => self.registers.set_c(result),
-            "d" => self.registers.set_d(result),
-            "e" => self.registers.set_e(result),
-            "h" => self.registers.set_h(result),
-            "l" => self.registers.set_l(result),
-            "h_l" => self.write_memory(hl_value.unwrap(), result),
-            _ => {
-                println!("Invalid register name");
-                std::process::exit(1)
-            }
-        }
+        self.write_register_by_name(register_name, hl_value, result);
 
         self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
     }
@@ -5259,20 +4422,7 @@ impl Gameboy {
             flag_c = true
         }
 
-        match register_name {
-            "a" => self.registers.set_a(result),
-            "b" => self.registers.set_b(result),
-            "c" => self.registers.set_c(result),
-            "d" => self.registers.set_d(result),
-            "e" => self.registers.set_e(result),
-            "h" => self.registers.set_h(result),
-            "l" => self.registers.set_l(result),
-            "h_l" => self.write_memory(hl_value.unwrap(), result),
-            _ => {
-                println!("Invalid register name");
-                std::process::exit(1)
-            }
-        }
+        self.write_register_by_name(register_name, hl_value, result);
 
         self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
     }
@@ -5294,20 +4444,7 @@ impl Gameboy {
             flag_c = true
         }
 
-        match register_name {
-            "a" => self.registers.set_a(result),
-            "b" => self.registers.set_b(result),
-            "c" => self.registers.set_c(result),
-            "d" => self.registers.set_d(result),
-            "e" => self.registers.set_e(result),
-            "h" => self.registers.set_h(result),
-            "l" => self.registers.set_l(result),
-            "h_l" => self.write_memory(hl_value.unwrap(), result),
-            _ => {
-                println!("Invalid register name");
-                std::process::exit(1)
-            }
-        }
+        self.write_register_by_name(register_name, hl_value, result);
 
         self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
     }
@@ -5326,37 +4463,28 @@ impl Gameboy {
 
         let flag_c = cf == 1;
 
-        match register_name {
-            "a" => self.registers.set_a(result),
-            "b" => self.registers.set_b(result),
-            "c" => self.registers.set_c(result),
-            "d" => self.registers.set_d(result),
-            "e" => self.registers.set_e(result),
-            "h" => self.registers.set_h(result),
-            "l" => self.registers.set_l(result),
-            "h_l" => self.write_memory(hl_value.unwrap(), result),
-            _ => {
-                println!("Invalid register name");
-                std::process::exit(1)
-            }
-        }
+        self.write_register_by_name(register_name, hl_value, result);
 
         self.registers.f.set_flag(flag_z, flag_n, flag_h, flag_c);
     }
 
     pub fn to_serializable(&self) -> SerializedGameboy {
-        let serializable = SerializedGameboy {
+        SerializedGameboy {
             registers: self.registers.clone(),
             total_cycle_num: self.total_cycle_num,
-            vram_cycle_num: self.vram_cycle_num,
-            timer_cycle_num: self.timer_cycle_num,
-            timer: self.timer,
-            cpu_clock: self.cpu_clock,
+            vram_cycle_num: self.ppu.line_cycle(),
+            timer: self.timer.clone(),
             break_points: self.break_points.clone(),
             memory: self.memory.clone(),
-        };
-
-        serializable
+            boot_rom: self.boot_rom.clone(),
+            model: self.model,
+            cartridge_save: self.export_save(),
+            mapper_state: Some(self.mapper.save_state()),
+            ppu_state: Some(self.ppu.save_state()),
+            is_halt: self.is_halt,
+            is_halt_bug: self.is_halt_bug,
+            should_enable_ime: self.should_enable_ime,
+        }
     }
 
     pub fn background_width(&self) -> u32 {
@@ -5392,7 +4520,7 @@ impl Gameboy {
     }
 
     pub fn is_vblank(&self) -> bool {
-        self.memory[0xff44] >= 144
+        self.ppu.mode() == Mode::VBlank
     }
 
     pub fn toggle_is_running(&mut self) {
@@ -5433,179 +4561,158 @@ impl Gameboy {
 
     //MBC
 
-    pub fn get_mbc(&self) -> u8 {
-        self.mbc
+    /// The banks and whatever else the cartridge mapper is up to, for the
+    /// debugger.
+    pub fn mapper_status(&self) -> MapperStatus {
+        self.mapper.status()
     }
 
-    pub fn get_rom_bank(&self) -> u8 {
-        self.rom_bank
+    pub fn cartridge_header(&self) -> CartridgeHeader {
+        self.cartridge_header.clone()
     }
 
-    // pub fn get_rom_bank_memory(&self) -> Vec<u8> {
-    //     self.rom_bank_memory
-    // }
-
-    pub fn get_ram_bank(&self) -> u8 {
-        self.ram_bank
+    pub fn model(&self) -> Model {
+        self.model
     }
 
-    // pub fn get_ram_bank_memory(&self) -> Vec<u8> {
-    //     self.ram_bank_memory
-    // }
-
-    pub fn get_is_ram_enabled(&self) -> bool {
-        self.is_ram_enabled
+    /// The battery backed cartridge RAM in the common .sav layout, followed
+    /// by the clock for carts with one.
+    pub fn export_save(&self) -> Vec<u8> {
+        self.mapper.export_save()
     }
 
-    pub fn get_is_rom_banking_enabled(&self) -> bool {
-        self.is_rom_banking_enabled
+    /// Loads a .sav file as written by `export_save` or other emulators.
+    pub fn import_save(&mut self, save: &[u8]) -> Result<(), LoadError> {
+        self.mapper.import_save(save)
     }
 
-    fn get_mbc_from_memory(memory: &Vec<u8>) -> u8 {
-        let mbc = match memory[0x0147] {
-            0 => 0,
-            1 => 1,
-            2 => 1,
-            3 => 1,
-            5 => 2,
-            6 => 2,
-            0x13 => 3,
-            _ => {
-                info!("Invalid mbc value: {:x} at $0x147", memory[0x0147]);
-                panic!("");
-            }
-        };
-        mbc
+    /// Whether the save has changed since it was last imported or taken.
+    pub fn is_save_dirty(&self) -> bool {
+        self.mapper.memory().is_save_dirty()
     }
 
-    fn enable_ram_bank(&mut self, address: u16, data: u8) {
-        if self.mbc == 2 {
-            if address & 0b00010000 == 0b00010000 {
-                return;
-            }
+    /// Returns the save if it changed since the last call, so the frontend
+    /// only has to write it out when there's something new.
+    pub fn take_dirty_save(&mut self) -> Option<Vec<u8>> {
+        if self.is_save_dirty() {
+            self.mapper.memory_mut().clear_save_dirty();
+            Some(self.export_save())
+        } else {
+            None
         }
+    }
 
-        let lower_nibble = data & 0xF;
-        if lower_nibble == 0xA {
-            self.is_ram_enabled = true;
-        } else if lower_nibble == 0x0 {
-            self.is_ram_enabled = false;
-        }
+    /// Makes the cartridge clock follow the host clock, as passed to
+    /// `sync_rtc_host_time`, instead of counting emulated cycles.
+    pub fn set_rtc_host_clock(&mut self, is_host_clock: bool) {
+        self.mapper.set_host_clock(is_host_clock);
     }
 
-    fn change_lo_rom_bank(&mut self, data: u8) {
-        if self.mbc == 2 {
-            self.rom_bank = data & 0xF;
-            if self.rom_bank == 0 {
-                self.rom_bank += 1;
-            }
-            return;
-        }
+    pub fn is_rtc_host_clock(&self) -> bool {
+        self.mapper.is_host_clock()
+    }
 
-        let lower_5_bits = data & 31;
-        self.rom_bank &= 224; // turn off the lower 5
-        self.rom_bank |= lower_5_bits;
-        if self.rom_bank == 0 {
-            self.rom_bank += 1;
-        }
+    /// Brings the cartridge clock up to date with the host time, in seconds
+    /// since the Unix epoch. From JS that's `Date.now() / 1000`.
+    pub fn sync_rtc_host_time(&mut self, unix_seconds: f64) {
+        self.mapper.sync_host_time(unix_seconds as u64);
     }
 
-    fn change_hi_rom_bank(&mut self, data: u8) {
-        // turn off the upper 3 bits of the current rom
-        self.rom_bank &= 31;
+    /// Whether a HuC3 cart beeped its speaker since the last call.
+    pub fn take_cartridge_tone(&mut self) -> bool {
+        self.mapper.take_tone()
+    }
 
-        // turn off the lower 5 bits of the data
-        let new_data = data & 224;
-        self.rom_bank = self.rom_bank | new_data;
-        if self.rom_bank == 0 {
-            self.rom_bank += 1;
-        }
+    /// Parses and enables a Game Genie or GameShark code, returning an id to
+    /// toggle or remove it by.
+    pub fn add_cheat(&mut self, code: &str) -> Result<u32, CheatError> {
+        let cheat = Cheat::parse(code)?;
+        info!("Adding cheat {}", cheat.code());
+        Ok(self.cheats.add(cheat))
     }
 
-    fn change_ram_bank(&mut self, data: u8) {
-        self.ram_bank = data & 0x3;
+    pub fn remove_cheat(&mut self, id: u32) {
+        self.cheats.remove(id);
     }
 
-    fn change_rom_ram_mode(&mut self, data: u8) {
-        let new_data = data & 0x1;
-        self.is_rom_banking_enabled = if new_data == 0 { true } else { false };
-        if self.is_rom_banking_enabled {
-            self.ram_bank = 0
-        }
+    pub fn set_cheat_enabled(&mut self, id: u32, is_enabled: bool) {
+        self.cheats.set_enabled(id, is_enabled);
     }
 
-    fn write_memory(&mut self, address: u16, value: u8) {
-        let is_mbc_one_or_two_or_three = self.mbc == 1 || self.mbc == 2 || self.mbc == 3;
-        let is_mbc_one_or_two = self.mbc == 1 || self.mbc == 2;
-        // enable ram
-        if address < 0x2000 {
-            if is_mbc_one_or_two_or_three {
-                // DoRamBankEnable(address,data) ;
-                self.enable_ram_bank(address, value);
-            }
-        }
-        // change ROM bank
-        else if (address >= 0x2000) && (address < 0x4000) {
-            if is_mbc_one_or_two {
-                // DoChangeLoROMBank(data) ;
-                self.change_lo_rom_bank(value);
-            } else if self.mbc == 3 {
-                self.rom_bank = value;
-                if self.rom_bank == 0 {
-                    self.rom_bank += 1;
+    // GameShark codes are applied once a frame, like the real one does
+    // from the vblank interrupt. Banked codes write straight to that bank of
+    // the cartridge RAM, whatever's mapped in.
+    fn apply_gameshark_cheats(&mut self) {
+        for (bank, address, value) in self.cheats.gameshark_writes() {
+            match bank {
+                Some(bank) if (0xA000..0xC000).contains(&address) => {
+                    self.mapper.poke_ram(bank, address, value)
                 }
+                _ => self.write_memory(address, value),
             }
         }
-        // change ROM or RAM bank
-        else if (address >= 0x4000) && (address < 0x6000) {
-            // there is no rambank in mbc2 so always use rambank 0
-            if self.mbc == 1 {
-                if self.is_rom_banking_enabled {
-                    // DoChangeHiRomBank(data) ;
-                    self.change_hi_rom_bank(value);
-                } else {
-                    // DoRAMBankChange(data) ;
-                    self.change_ram_bank(value)
-                }
-            } else if self.mbc == 3 {
-                if value <= 0x07 {
-                    self.ram_bank = value & 0x7;
-                } else if value >= 0x08 && value <= 0x0c {
-                    panic!("do someting about mbc here!!!");
-                }
-            }
+    }
+
+    /// Sets the picture a Pocket Camera takes, 128 by 112 pixels from 0 for
+    /// black to 255 for white.
+    pub fn set_camera_image(&mut self, pixels: Vec<u8>) {
+        self.mapper
+            .set_image_source(Box::new(StaticImage::new(pixels)));
+    }
+
+    /// Records an error from the middle of an instruction. The stepping
+    /// functions report it once the instruction is done.
+    fn raise_fault(&mut self, error: EmulationError) {
+        if self.fault.is_none() {
+            info!("{}", error);
+            self.fault = Some(error);
         }
-        // this will change whether we are doing ROM banking
-        // or RAM banking with the above if statement
-        else if (address >= 0x6000) && (address < 0x8000) {
-            if self.mbc == 1 {
-                // DoChangeROMRAMMode(data) ;
-                self.change_rom_ram_mode(value);
-            } else if self.mbc == 3 {
-                if value == 0 || value == 1 {
-                    panic!("do RCT MBC!!");
-                }
+    }
+
+    fn check_fault(&mut self) -> Result<(), EmulationError> {
+        match self.fault.take() {
+            Some(error) => {
+                self.is_running = false;
+                Err(error)
             }
-        } else if (address >= 0xA000) && (address < 0xC000) {
-            if self.mbc == 1 {
-                if self.is_ram_enabled {
-                    let new_address = address - 0xA000;
-                    self.ram_bank_memory
-                        [(new_address + (self.ram_bank as u16 * 0x2000)) as usize] = value;
-                }
-            } else if self.mbc == 3 {
-                if value <= 0x03 && self.is_ram_enabled {
-                    let new_address = address - 0xA000;
-                    self.ram_bank_memory
-                        [(new_address + (self.ram_bank as u16 * 0x2000)) as usize] = value;
-                } else if value >= 08 && value <= 0x0c {
-                    panic!("RTC here")
-                }
+            None => Ok(()),
+        }
+    }
+
+    fn write_memory(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            self.mapper.write_control(address, value);
+        } else if (0xA000..0xC000).contains(&address) {
+            self.mapper.write_ram(address, value);
+            if let Some((address, value)) = self.mapper.take_unsupported_write() {
+                self.raise_fault(EmulationError::UnsupportedMapperFeature {
+                    mapper: self.mapper.name(),
+                    address,
+                    value,
+                    pc: self.instruction_pc,
+                });
             }
-        } else if (address >= 0xFEA0) && (address < 0xFEFF) {
+        } else if (0xFEA0..0xFEFF).contains(&address) {
             //Nothing happens
+        } else if (timer::DIV..=timer::TAC).contains(&address) {
+            self.timer.write(address, value);
+            self.sync_timer_registers();
+        } else if address == boot::BOOT_ROM_DISABLE {
+            if value & 0b1 == 0b1 && self.boot_rom.is_some() {
+                info!("Unmapping bootrom...");
+                self.boot_rom = None;
+            }
+            self.memory[address as usize] = value;
+        } else if address == 0xFF41 {
+            // The mode and coincidence flag are read only
+            self.memory[address as usize] =
+                (value & 0b01111000) | (self.memory[address as usize] & 0b10000111);
+            self.update_stat();
         } else if address == 0xFF44 {
-            self.memory[address as usize] = 0;
+            // LY is read only
+        } else if address == 0xFF45 {
+            self.memory[address as usize] = value;
+            self.update_stat();
         } else if address == 0xFF46 {
             self.execute_dma_transfer(value);
         } else {
@@ -5613,22 +4720,34 @@ impl Gameboy {
         }
     }
 
+    // Enabling a STAT source or changing LYC can raise the STAT line without
+    // the PPU moving on
+    fn update_stat(&mut self) {
+        if self.ppu.update_stat(&mut self.memory) {
+            self.request_lcd_interrupt();
+        }
+    }
+
     fn read_memory(&self, address: u16) -> u8 {
-        // Read from the rom memory bank
-        if (address >= 0x4000) && (address <= 0x7FFF) {
-            let new_address = address - 0x4000;
-            return self.cartridge[(new_address + (self.rom_bank as u16 * 0x4000)) as usize];
+        if let Some(boot_rom) = &self.boot_rom {
+            if boot::is_boot_rom_address(address, boot_rom.len()) {
+                return boot_rom[address as usize];
+            }
         }
-        // Reading from ram memory bank
-        else if (address >= 0xA000) && (address <= 0xBFFF) {
-            let new_address = address - 0xA000;
-            return self.ram_bank_memory[(new_address + (self.ram_bank as u16 * 0x2000)) as usize];
+
+        if address < 0x8000 {
+            let value = self.mapper.read_rom(address);
+            return self.cheats.patch_rom_read(address, value);
+        } else if (0xA000..=0xBFFF).contains(&address) {
+            return self.mapper.read_ram(address);
         } else if 0xFF00 == address {
             return self.get_joypad_state();
+        } else if (timer::DIV..=timer::TAC).contains(&address) {
+            return self.timer.read(address);
         }
 
         // else return memory
-        return self.memory[address as usize];
+        self.memory[address as usize]
     }
 
     fn get_joypad_state(&self) -> u8 {
@@ -5685,161 +4804,92 @@ impl Gameboy {
     }
 
     //Timer
-    fn update_timer(&mut self, instruction: u8) {
-        if self.is_timer_enabled() {
-            self.add_cycles(instruction, CycleRegister::TimerCycle);
-            let clock_count = self.timer_cycle_to_cpu_clock();
-
-            if self.timer_cycle_num >= clock_count {
-                self.add_time_counter();
-                self.set_timer_cycle(self.timer_cycle_num - clock_count);
-            };
-        } else {
-            self.set_timer_cycle(0);
+    fn update_timer(&mut self, cycles: u8) {
+        if self.timer.tick(cycles) {
+            self.request_timer_interrupt();
         }
+        self.sync_timer_registers();
     }
 
-    fn execute_interuption(&mut self) {
-        let do_v_blank = (self.memory[0xff0f] & 0b00000001 == 0b00000001)
-            && (self.memory[0xffff] & 0b00000001 == 0b00000001);
-
-        let do_lcd = (self.memory[0xff0f] & 0b00000010 == 0b00000010)
-            && (self.memory[0xffff] & 0b00000010 == 0b00000010);
-
-        let do_timer = (self.memory[0xff0f] & 0b00000100 == 0b00000100)
-            && (self.memory[0xffff] & 0b00000100 == 0b00000100);
-
-        let do_serial = (self.memory[0xff0f] & 0b00001000 == 0b00001000)
-            && (self.memory[0xffff] & 0b00001000 == 0b00001000);
-
-        let do_joypad = (self.memory[0xff0f] & 0b00010000 == 0b00010000)
-            && (self.memory[0xffff] & 0b00010000 == 0b00010000);
-
-        let any_interrupt = do_v_blank || do_lcd || do_timer || do_serial || do_joypad;
-
-        if self.registers.f.ime {
-            if do_v_blank {
-                info!("execute vblank interrupt");
-                self.is_halt = false;
-                self.registers.f.set_ime(false);
-
-                self.memory[0xff0f] = self.memory[0xff0f] ^ 0b00000001;
-                self.push_stack(self.registers.pc);
-                self.registers.set_pc(0x40);
-            }
-
-            if do_lcd {
-                info!("execute lcd interrupt");
-                self.is_halt = false;
-                self.registers.f.set_ime(false);
-
-                self.memory[0xff0f] = self.memory[0xff0f] ^ 0b00000010;
-                self.push_stack(self.registers.pc);
-                self.registers.set_pc(0x48);
-            }
-
-            if self.memory[0xff0f] & 0b00000010 == 0b00000010 {
-                info!("has lcd request")
-            }
-            if do_timer {
-                self.is_halt = false;
-                self.registers.f.set_ime(false);
-
-                self.memory[0xff0f] = self.memory[0xff0f] ^ 0b00000100;
-                self.push_stack(self.registers.pc);
-                self.registers.set_pc(0x50);
-            }
-
-            if do_serial {
-                self.is_halt = false;
-                self.registers.f.set_ime(false);
+    // Keeps the timer registers in the raw memory the debugger shows
+    fn sync_timer_registers(&mut self) {
+        for address in timer::DIV..=timer::TAC {
+            self.memory[address as usize] = self.timer.read(address);
+        }
+    }
 
-                self.memory[0xff0f] = self.memory[0xff0f] ^ 0b00001000;
-                self.push_stack(self.registers.pc);
-                self.registers.set_pc(0x58);
-            }
+    // Interrupts that are both requested in IF and enabled in IE
+    fn pending_interrupts(&self) -> u8 {
+        self.memory[0xff0f] & self.memory[0xffff] & 0b00011111
+    }
 
-            if do_joypad {
-                self.is_halt = false;
-                self.registers.f.set_ime(false);
+    // Services the highest priority pending interrupt, vblank first and
+    // joypad last. Returns whether one was dispatched.
+    fn execute_interuption(&mut self) -> bool {
+        let pending_interrupts = self.pending_interrupts();
 
-                self.memory[0xff0f] = self.memory[0xff0f] ^ 0b00010000;
-                self.push_stack(self.registers.pc);
-                self.registers.set_pc(0x60);
-            }
+        // Any pending interrupt ends HALT, even when IME is off
+        if pending_interrupts != 0 {
+            self.is_halt = false;
         }
 
-        if any_interrupt {
-            //info!("an_interrupt, halt is false");
-            self.is_halt = false
+        if !self.registers.f.ime || pending_interrupts == 0 {
+            return false;
         }
-    }
 
-    pub fn set_vram_cycle(&mut self, value: u16) {
-        self.vram_cycle_num = value
-    }
+        let interrupt_bit = pending_interrupts.trailing_zeros() as u16;
+        debug!("execute interrupt {}", interrupt_bit);
+
+        self.memory[0xff0f] &= !(1 << interrupt_bit);
+        self.registers.f.set_ime(false);
+        self.push_stack(self.registers.pc);
+        self.registers.set_pc(0x40 + interrupt_bit * 8);
 
-    pub fn set_timer_cycle(&mut self, value: usize) {
-        self.timer_cycle_num = value
+        true
     }
 
     pub fn request_vblank(&mut self) {
         self.should_draw = true;
-        self.memory[0xff0f] = self.memory[0xff0f] | 0b000000001;
+        self.memory[0xff0f] |= 0b000000001;
     }
 
     pub fn request_lcd_interrupt(&mut self) {
-        self.should_draw = true;
-        self.memory[0xff0f] = self.memory[0xff0f] | 0b000000010;
+        self.memory[0xff0f] |= 0b000000010;
     }
 
     pub fn request_timer_interrupt(&mut self) {
-        self.memory[0xff0f] = self.memory[0xff0f] | 0b000000100;
+        self.memory[0xff0f] |= 0b000000100;
     }
 
     pub fn request_joypad_interrupt(&mut self) {
         info!("requested");
-        self.memory[0xff0f] = self.memory[0xff0f] | 0b00010000;
-    }
-
-    pub fn inc_ly(&mut self) {
-        let ly_max = 153;
-        let vblank_start = 144;
-
-        if self.memory[0xff44] == ly_max {
-            self.memory[0xff44] = 0;
-        } else {
-            self.memory[0xff44] = self.memory[0xff44] + 1;
-            if self.memory[0xff44] == vblank_start {
-                self.request_vblank()
-            }
-        }
+        self.memory[0xff0f] |= 0b00010000;
     }
 
     pub fn total_cycle(&self) -> usize {
         self.total_cycle_num
     }
 
+    /// Dots into the current line.
     pub fn vram_cycle(&self) -> u16 {
-        self.vram_cycle_num
+        self.ppu.line_cycle()
     }
 
-    pub fn timer_cycle(&self) -> usize {
-        self.timer_cycle_num
+    /// The internal 16 bit counter that DIV is the upper byte of.
+    pub fn timer_cycle(&self) -> u16 {
+        self.timer.system_counter()
     }
 
     pub fn timer_counter_memory(&self) -> u8 {
-        self.memory[0xff05]
+        self.timer.read(timer::TIMA)
     }
 
-    pub fn timer(&mut self) -> usize {
-        self.timer = self.total_cycle_num / self.timer_frequency();
-        self.timer
+    pub fn timer(&self) -> usize {
+        self.total_cycle_num / self.timer_frequency()
     }
 
-    pub fn cpu_clock(&mut self) -> usize {
-        self.cpu_clock = self.timer / self.timer_cycle_to_cpu_clock();
-        self.cpu_clock
+    pub fn cpu_clock(&self) -> usize {
+        self.timer() / self.timer_cycle_to_cpu_clock()
     }
 
     pub fn timer_cycle_to_cpu_clock(&self) -> usize {
@@ -5850,379 +4900,49 @@ impl Gameboy {
     }
 
     pub fn is_timer_enabled(&self) -> bool {
-        self.memory[0xff07] & 0b00000100u8 == 0b00000100u8
+        self.timer.is_enabled()
     }
 
     pub fn timer_frequency(&self) -> usize {
-        let timer_frequency = match self.memory[0xff07] & 0b00000011u8 {
-            0 => 4096,
-            1 => 262144,
-            2 => 65536,
-            3 => 16384,
-            _ => 0,
-        };
-
-        timer_frequency
-    }
-
-    fn add_time_counter(&mut self) {
-        if self.memory[0xff05] == 255 {
-            self.request_timer_interrupt();
-            self.memory[0xff05] = self.memory[0xff06]
-        } else {
-            self.memory[0xff05] += 1
-        }
+        self.timer.frequency()
     }
 
     pub fn get_divide_register(&self) -> u8 {
-        self.memory[0xff04]
-    }
-
-    fn add_cycles(&mut self, instruction: u8, cycle_register: CycleRegister) {
-        let cycle = match instruction {
-            0x031 => 12,
-
-            0x0AF => 4,
-            0x021 => 12,
-            0x077 => 8,
-            0x011 => 12,
-            0x00E => 8,
-            0x03E => 8,
-            0x006 => 8,
-            0x002e => 8,
-            0x001e => 8,
-            0x0016 => 8,
-            0x07B => 4,
-            0x07C => 4,
-            0x07D => 4,
-            0x078 => 4,
-            0x01A => 8,
-            0x04F => 4,
-            0x067 => 4,
-            0x057 => 4,
-            0x032 => 8,
-            0x022 => 8,
-            0x0f0 => 12,
-            0x0E2 => 8,
-            0x0E0 => 12,
-            0x0CB => match self.read_memory(self.registers.pc + 1) {
-                0x006 => 16,
-                0x01E => 16,
-                0x02E => 16,
-                0x03E => 16,
-                0x04E => 16,
-                0x05E => 16,
-                0x06E => 16,
-                0x07E => 16,
-                0x08E => 16,
-                0x09E => 16,
-                0x0aE => 16,
-                0x0bE => 16,
-                0x0cE => 16,
-                0x0dE => 16,
-                0x0eE => 16,
-                0x0fE => 16,
-                0x016 => 16,
-                0x026 => 16,
-                0x036 => 16,
-                0x046 => 16,
-                0x056 => 16,
-                0x066 => 16,
-                0x076 => 16,
-                0x086 => 16,
-                0x096 => 16,
-                0x0a6 => 16,
-                0x0b6 => 16,
-                0x0c6 => 16,
-                0x0d6 => 16,
-                0x0e6 => 16,
-                0x0f6 => 16,
-                _other => 8,
-            },
-            0x017 => 4,
-            0x007 => 4,
-            0x020 => 8,
-            0x028 => 8,
-            0x018 => 8,
-            0x00C => 4,
-            0x004 => 4,
-            0x0CD => 12,
-            0x0C9 => 8,
-            0x0C5 => 16,
-            0x0C1 => 12,
-            0x005 => 4,
-            0x00D => 4,
-            0x01D => 4,
-            0x03D => 4,
-            0x015 => 4,
-            0x013 => 8,
-            0x023 => 8,
-            0x024 => 4,
-            0x0FE => 8,
-            0x0BE => 8,
-            0x0EA => 16,
-            0x090 => 4,
-            0x086 => 8,
-            0x000 => 4,
-            0x0CE => 8,
-            0x066 => 8,
-            0x0CC => {
-                if self.registers.f.z {
-                    24
-                } else {
-                    12
-                }
-            }
-            0x00B => 8,
-            0x003 => 8,
-            0x073 => 8,
-            0x083 => 4,
-            0x008 => 20,
-            0x01F => 4,
-            0x088 => 4,
-            0x089 => 4,
-            0x06E => 8,
-            0x0E6 => 8,
-            0x0dd => {
-                info!("Not sure what's the cycle for 0x0DD");
-                std::process::exit(1)
-            }
-            0x0C3 => 12,
-            0x0f3 => 4,
-            0x036 => 12,
-            0x02a => 8,
-            0x047 => 4,
-            0x002 => 8,
-            0x0fd => 0,
-            0x06d => 4,
-            0x06c => 4,
-            0x071 => 8,
-            0x03c => 4,
-            0x0e1 => 12,
-            0x055 => 4,
-            0x056 => 8,
-            0x05a => 4,
-            0x061 => 4,
-            0x05b => 4,
-            0x049 => 4,
-            0x05e => 8,
-            0x058 => 4,
-            0x05c => 4,
-            0x051 => 4,
-            0x050 => 4,
-            0x04C => 4,
-            0x04E => 8,
-            0x059 => 4,
-            0x053 => 4,
-            0x052 => 4,
-            0x04d => 4,
-            0x054 => 4,
-            0x0d3 => 0,
-            0x0a6 => 8,
-            0x05d => 4,
-            0x03a => 8,
-            0x040 => 4,
-            0x043 => 4,
-            0x038 => 8,
-            0x0c2 => 12,
-            0x0f4 => 0,
-            0x07f => 4,
-            0x074 => 8,
-            0x075 => 8,
-            0x072 => 8,
-            0x076 => 4,
-            0x079 => 4,
-            0x0f1 => 12,
-            0x085 => 4,
-            0x0b1 => 4,
-            0x03f => 4,
-            0x042 => 4,
-            0x081 => 4,
-            0x046 => 8,
-            0x0b5 => 4,
-            0x070 => 8,
-            0x048 => 4,
-            0x0C4 => {
-                if !self.registers.f.z {
-                    24
-                } else {
-                    12
-                }
-            }
-            0x069 => 4,
-            0x06a => 4,
-            0x06f => 4,
-            0x0D1 => 12,
-            0x05f => 4,
-            0x092 => 4,
-            0x097 => 4,
-            0x091 => 4,
-            0x093 => 4,
-            0x094 => 4,
-            0x095 => 4,
-            0x096 => 8,
-            0x041 => 4,
-            0x044 => 4,
-            0x0e5 => 16,
-            0x0f5 => 16,
-            0x0D5 => 16,
-            0x001 => 12,
-            0x0fa => 16,
-            0x02C => 4,
-            0x0A8 => 4,
-            0x0A9 => 4,
-            0x0AA => 4,
-            0x0AB => 4,
-            0x0AC => 4,
-            0x0AD => 4,
-            0x0C6 => 8,
-            0x0D6 => 8,
-            0x0b7 => 4,
-            0x0b0 => 4,
-            0x0b2 => 4,
-            0x0b3 => 4,
-            0x0b4 => 4,
-            0x02D => 4,
-            0x025 => 4,
-            0x0AE => 8,
-            0x0EE => 8,
-            0x026 => 8,
-            0x030 => 8,
-            0x07A => 4,
-            0x0D0 => 8,
-            0x0C0 => 8,
-            0x0C8 => 8,
-            0x0D8 => 8,
-            0x0B6 => 8,
-            0x0F6 => 8,
-            0x035 => 12,
-            0x009 => 8,
-            0x019 => 8,
-            0x029 => 8,
-            0x0E9 => 4,
-            0x0F8 => 12,
-            0x062 => 4,
-            0x06B => 4,
-            0x012 => 8,
-            0x01C => 4,
-            0x014 => 4,
-            0x07E => 8,
-            0x0f9 => 8,
-            0x033 => 8,
-            0x03B => 8,
-            0x039 => 8,
-            0x0E8 => 16,
-            0x0de => 8,
-            0x0BB => 4,
-            0x01B => 8,
-            0x02B => 8,
-            0x045 => 4,
-            0x04A => 4,
-            0x04B => 4,
-            0x060 => 4,
-            0x063 => 4,
-            0x064 => 4,
-            0x065 => 4,
-            0x068 => 4,
-            0x0ca => 12,
-            0x0D2 => 12,
-            0x0Da => 12,
-            0x0D4 => 12,
-            0x0DC => 12,
-            0x0D9 => 8,
-            0x0C7 => 32,
-            0x0CF => 32,
-            0x0f2 => 8,
-            0x02f => 4,
-            0x00A => 8,
-            0x08E => 8,
-            0x09e => 8,
-            0x034 => 12,
-            0x027 => 4,
-            0x037 => 4,
-            0x0B8 => 4,
-            0x0B9 => 4,
-            0x0BA => 4,
-            0x0BC => 4,
-            0x0BD => 4,
-            0x0BF => 4,
-            0x087 => 4,
-            0x080 => 4,
-            0x082 => 4,
-            0x084 => 4,
-            0x08f => 4,
-            0x08A => 4,
-            0x08B => 4,
-            0x08C => 4,
-            0x08D => 4,
-            0x09f => 4,
-            0x098 => 4,
-            0x099 => 4,
-            0x09A => 4,
-            0x09B => 4,
-            0x09C => 4,
-            0x09D => 4,
-            0x0A7 => 4,
-            0x0A0 => 4,
-            0x0A1 => 4,
-            0x0A2 => 4,
-            0x0A3 => 4,
-            0x0A4 => 4,
-            0x0A5 => 4,
-            0x00F => 4,
-            0x0d7 => 32,
-            0x0df => 32,
-            0x0e7 => 32,
-            0x0ef => 32,
-            0x0f7 => 32,
-            0x0ff => 32,
-            0x0fb => 4,
-            0x010 => 0,
-            other => {
-                info!("Cycle calc - No opcode found for {:x}", other);
-                std::process::exit(1)
-            }
-        };
+        self.timer.div()
+    }
 
-        // The divide register is incremented 16384/s, or 256 cycles
-        self.divide_register_cycle_counter += cycle as u16;
-        if self.divide_register_cycle_counter >= 256 {
-           self.memory[0xff04] += 1;
-           self.divide_register_cycle_counter -= 256
-        }
+    // Clock cycles the instruction at PC is going to take. This has to be
+    // worked out before it runs, since it can change the flags that decide
+    // whether a conditional branch is taken.
+    fn instruction_cycles(&self, instruction: u8) -> u8 {
+        let cb_opcode = self.read_memory(self.registers.pc.wrapping_add(1));
+        timing::instruction_cycles(
+            instruction,
+            cb_opcode,
+            self.registers.f.z,
+            self.registers.f.c,
+        )
+    }
 
+    fn add_cycles(&mut self, cycles: u8, cycle_register: CycleRegister) {
         match cycle_register {
-            CycleRegister::VramCycle => self.vram_cycle_num += cycle as u16,
-            CycleRegister::TimerCycle => self.timer_cycle_num += cycle as usize,
-            CycleRegister::CpuCycle => self.total_cycle_num += cycle as usize,
+            CycleRegister::Cpu => {
+                self.total_cycle_num += cycles as usize;
+                self.update_timer(cycles);
+                self.mapper.tick(cycles);
+            }
         }
     }
 
     pub fn square1(&self) -> Channel {
-        let sweep_time_raw = self.memory[0xff10] & 0b01110000u8;
-        let sweep_time = match sweep_time_raw {
-            0b00000000 => 0.0,
-            0b00010000 => 7.8,
-            0b00100000 => 15.6,
-            0b00110000 => 23.4,
-            0b01000000 => 31.3,
-            0b01010000 => 39.1,
-            0b01100000 => 46.9,
-            0b01110000 => 54.7,
-            _ => panic!("Improper sweep_time. Check memory 0xff10"),
-        };
+        let sweep_time_raw = (self.memory[0xff10] & 0b01110000u8) >> 4;
+        let sweep_time = SWEEP_TIMES_MS[sweep_time_raw as usize];
 
         let is_sweep_increase = self.memory[0xff10] & 0b00001000u8 == 0b00001000u8;
         let sweep_shift_num = self.memory[0xff10] & 0b00000111u8;
 
-        let _wave_duty_raw = self.memory[0xff11] & 0b11000000u8;
-        let wave_duty_pct = match sweep_time_raw {
-            0b00000000 => 12.5,
-            0b01000000 => 25.0,
-            0b10000000 => 50.0,
-            0b11000000 => 75.0,
-            _ => panic!("Improper wave_duty. Check memory 0xff11"),
-        };
+        let wave_duty_raw = (self.memory[0xff11] & 0b11000000u8) >> 6;
+        let wave_duty_pct = WAVE_DUTY_PCTS[wave_duty_raw as usize];
 
         let sound_length_raw = self.memory[0xff11] & 0b00111111u8;
         let sound_length_sec = (64.0 - sound_length_raw as f32) * (1.0 / 256.0);
@@ -6255,16 +4975,16 @@ impl Gameboy {
         self.memory[0xff26] & 0b10000000 == 0b10000000
     }
     pub fn is_sound_4_on(&self) -> bool {
-        self.memory[0xff26] & 0b00001000 == 0b0000
+        self.memory[0xff26] & 0b00001000 == 0b00001000
     }
     pub fn is_sound_3_on(&self) -> bool {
         self.memory[0xff26] & 0b00000100 == 0b00000100
     }
     pub fn is_sound_2_all_on(&self) -> bool {
-        self.memory[0xff26] & 0b00000010 == 0b10000010
+        self.memory[0xff26] & 0b00000010 == 0b00000010
     }
     pub fn is_sound_1_on(&self) -> bool {
-        self.memory[0xff26] & 0b00000001 == 0b10000001
+        self.memory[0xff26] & 0b00000001 == 0b00000001
     }
 
     // Sprites
@@ -6277,45 +4997,29 @@ impl Gameboy {
         }
     }
 
-    fn obj_char_map_bytes(&self) -> Vec<u8> {
-        self.memory[0x8000..0x9000].to_vec()
+    #[cfg(feature = "web")]
+    fn sprite_height(&self) -> usize {
+        ppu::sprite_height(self.memory[0xff40])
     }
 
-    fn get_oam(&self) -> Vec<u8> {
-        let oam_vec = self.memory[0xfe00..0xfea0].to_vec();
-        oam_vec
+    #[cfg(feature = "web")]
+    fn sprite_pixel(&self, sprite: &Sprite, row_address: usize, col: usize) -> u8 {
+        sprite.pixel(&self.memory, row_address, col)
     }
 
-    fn all_sprites(&self) -> Vec<Sprite> {
-        let oam_vec = self.get_oam();
-
-        let mut all_sprites = Vec::new();
-
-        for idx in (0..oam_vec.len()).step_by(BYTES_PER_SPRITE) {
-            let y = oam_vec[idx] - 16;
-            let x = oam_vec[idx + 1] - 8;
-            let pattern_num = oam_vec[idx + 2];
-            let attributes = oam_vec[idx + 3];
-            let priority = attributes & 0b10000000 == 0b10000000;
-            let y_flip = attributes & 0b01000000 == 0b01000000;
-            let x_flip = attributes & 0b00100000 == 0b00100000;
-            let palette_num = attributes & 0b00010000 == 0b00010000;
-
-            let sprite = Sprite {
-                y,
-                x,
-                pattern_num,
-                attributes,
-                priority,
-                y_flip,
-                x_flip,
-                palette_num,
-            };
+    #[cfg(feature = "web")]
+    fn sprite_shade(&self, sprite: &Sprite, color: u8) -> u8 {
+        sprite.shade(&self.memory, color)
+    }
 
-            all_sprites.push(sprite);
-        }
+    /// The shade BGP gives a background or window colour number.
+    pub fn bg_shade(&self, color: u8) -> u8 {
+        ppu::bg_shade(&self.memory, color)
+    }
 
-        all_sprites
+    #[cfg(feature = "web")]
+    fn all_sprites(&self) -> Vec<Sprite> {
+        ppu::all_sprites(&self.memory)
     }
 
     //##LCD Control Register $0xff40
@@ -6373,25 +5077,32 @@ impl Gameboy {
 
     pub fn window_map(&self) -> Vec<u8> {
         if self.memory[0xff40] & 0x40 == 0x40 {
-            return self.memory[0x9c00..0xa000].to_vec();
+            self.memory[0x9c00..0xa000].to_vec()
         } else {
-            return self.memory[0x9800..0x9c00].to_vec();
+            self.memory[0x9800..0x9c00].to_vec()
         }
     }
 
     pub fn bg_window_char_map_bytes(&self) -> Vec<u8> {
         if self.memory[0xff40] & 0x10 == 0x10 {
-            return self.memory[0x8000..0x9000].to_vec();
+            self.memory[0x8000..0x9000].to_vec()
         } else {
-            return self.memory[0x8800..0x9800].to_vec();
+            self.memory[0x8800..0x9800].to_vec()
         }
     }
 
+    /// The last finished frame, row by row. Each of the 160x144 pixels is a
+    /// shade from 0 (white) to 3 (black), plus 4 if it's from a sprite using
+    /// OBP0 or 8 for OBP1, which is also its index in a `DisplayPalette`.
+    pub fn frame_buffer(&self) -> Vec<u8> {
+        self.ppu.frame().to_vec()
+    }
+
     pub fn bg_map(&self) -> Vec<u8> {
         if self.memory[0xff40] & 0x08 == 0x08 {
-            return self.memory[0x9c00..0xa000].to_vec();
+            self.memory[0x9c00..0xa000].to_vec()
         } else {
-            return self.memory[0x9800..0x9c00].to_vec();
+            self.memory[0x9800..0x9c00].to_vec()
         }
     }
 
@@ -6430,100 +5141,6 @@ impl Gameboy {
     //     }
     // }
 
-    fn set_lcd_mode_with_gpu_cycle(&mut self, gpu_cycle: u16) {
-        // //##This function is GPU emulation. Mode Flag is read only for gameboy
-        let lcdc = self.memory[0xff41].clone();
-        let mut bv = BitVec::from_bytes(&[lcdc]);
-        if gpu_cycle < 80 {
-            bv.set(7, false);
-            bv.set(6, true);
-        } else if gpu_cycle >= 80 && gpu_cycle < 172 {
-            bv.set(7, true);
-            bv.set(6, true);
-        } else if gpu_cycle >= 172 {
-            bv.set(7, false);
-            bv.set(6, false);
-        }
-
-        self.memory[0xff41] = bv.to_bytes()[0];
-        // info!("LCD Status: {:b}", self.memory[0xff41]);
-    }
-
-    fn set_lcd_status(&mut self) {
-        let mut status = self.read_memory(0xFF41);
-        if false == self.is_lcd_display_enable() {
-            // set the mode to 1 during lcd disabled and reset scanline
-            self.vram_cycle_num = 456;
-            self.memory[0xFF44] = 0;
-            status = status & 252;
-            status = status | 0b00000001;
-            self.write_memory(0xFF41, status);
-            return;
-        }
-
-        let currentline = self.read_memory(0xFF44);
-        let currentmode = status & 0x3;
-
-        let mut mode = 0;
-        let mut req_int = false;
-
-        // in vblank so set mode to 1
-        if currentline >= 144 {
-            mode = 1;
-            status = status | 0b00000001;
-            status = status & 0b11111101;
-            req_int = status & 0b00010000 == 0b00010000;
-        } else {
-            let mode2bounds = 80;
-            let mode3bounds = mode2bounds + 172;
-
-            // mode 2
-            if self.vram_cycle_num >= mode2bounds {
-                mode = 2;
-                status = status | 0b00000010;
-                status = status & 0b11111110;
-                req_int = status & 0b00100000 == 0b00100000;
-            }
-            // mode 3
-            else if self.vram_cycle_num >= mode3bounds {
-                mode = 3;
-                status = status | 0b00000011;
-            }
-            // mode 0
-            else {
-                mode = 0;
-                status = status & 0b11111100;
-                req_int = status & 0b00001000 == 0b00001000;
-            }
-        }
-
-        // just entered a new mode so request interupt
-        if req_int && (mode != currentmode) {
-            self.request_lcd_interrupt();
-        }
-
-        // check the conincidence flag
-        if self.ly() == self.read_memory(0xFF45) {
-            status = status | 0b000000100;
-            if status & 0b01000000 == 0b01000000 {
-                self.request_lcd_interrupt();
-            }
-        } else {
-            status = status & 0b11111011;
-        }
-        self.write_memory(0xFF41, status);
-    }
-
-    fn set_lcd_mode_to_vblank(&mut self) {
-        //##This function is GPU emulation. Mode Flag is read only for gameboy
-        let lcdc = self.memory[0xff41].clone();
-        let mut bv = BitVec::from_bytes(&[lcdc]);
-        bv.set(7, true);
-        bv.set(6, false);
-
-        self.memory[0xff41] = bv.to_bytes()[0];
-    }
-
     pub fn is_sprite_display_enable(&self) -> bool {
         self.memory[0xff40] & 0x02 == 0x02
     }
@@ -6694,11 +5311,46 @@ impl Gameboy {
     }
 
     pub fn background_map_1(&self) -> Vec<u8> {
-        let background_map_1 = self.memory[0x9800..0x9c00].to_vec().clone();
-        background_map_1
+        self.memory[0x9800..0x9c00].to_vec().clone()
     }
 
-    pub fn execute_opcode(&mut self) {
+    // Services an interrupt, idles a machine cycle while halted or runs one
+    // instruction, then advances the rest of the hardware by the cycles spent
+    fn step(&mut self) {
+        self.instruction_pc = self.registers.pc;
+
+        let cycles = if self.execute_interuption() {
+            INTERRUPT_DISPATCH_CYCLES
+        } else if self.is_halt {
+            4
+        } else {
+            let should_enable_ime = self.should_enable_ime;
+            let instruction = self.read_memory(self.registers.pc);
+
+            if self.is_halt_bug {
+                // Stepping PC back makes the opcode byte get read again as
+                // the start of its operands
+                self.is_halt_bug = false;
+                self.registers.set_pc(self.registers.pc.wrapping_sub(1));
+            }
+
+            let cycles = self.instruction_cycles(instruction);
+            self.execute_instruction(instruction);
+
+            // EI takes effect after the instruction that follows it
+            if should_enable_ime && self.should_enable_ime {
+                self.should_enable_ime = false;
+                self.registers.f.set_ime(true);
+            }
+
+            cycles
+        };
+
+        self.cycle_based_gpu_operation(cycles);
+        self.add_cycles(cycles, CycleRegister::Cpu);
+    }
+
+    pub fn execute_opcode(&mut self) -> Result<(), EmulationError> {
         //ff10-ff14 is responsible for sound channel 1
         let pre_ff10 = self.memory[0xff10];
         let pre_ff11 = self.memory[0xff11];
@@ -6706,20 +5358,20 @@ impl Gameboy {
         let pre_ff13 = self.memory[0xff13];
         let pre_ff14 = self.memory[0xff14];
 
-        let instruction = self.memory[self.registers.pc as usize];
-        self.add_cycles(instruction, CycleRegister::CpuCycle);
-        self.execute_instruction(instruction);
-        self.cycle_based_gpu_operation(instruction);
+        self.step();
+        self.check_fault()?;
 
         if self.break_points.contains(&self.registers.pc) {
             self.is_running = false;
         }
 
-        if self.is_channel1_changed(pre_ff10, pre_ff11, pre_ff12, pre_ff13, pre_ff14) {
-            if self.sound_dirty_flag_check_s1() {
-                self.reset_fm_osc(self.square1());
-            }
+        if self.is_channel1_changed(pre_ff10, pre_ff11, pre_ff12, pre_ff13, pre_ff14)
+            && self.sound_dirty_flag_check_s1()
+        {
+            self.is_square1_changed = true;
         }
+
+        Ok(())
     }
 
     pub fn is_channel1_changed(
@@ -6743,25 +5395,24 @@ impl Gameboy {
             || pre_ff14 != after_ff14
     }
 
-    pub fn cycle_based_gpu_operation(&mut self, instruction: u8) {
-        let vram_cycle_per_ly_inc = 456;
+    pub fn cycle_based_gpu_operation(&mut self, cycles: u8) {
+        let interrupts = self.ppu.tick(&mut self.memory, cycles);
+        self.request_ppu_interrupts(interrupts);
+    }
 
-        if self.is_lcd_display_enable() {
-            self.add_cycles(instruction, CycleRegister::VramCycle);
-            self.set_lcd_status();
-            // self.set_lcd_mode_with_gpu_cycle(self.vram_cycle_num);
-            if self.vram_cycle_num >= vram_cycle_per_ly_inc {
-                self.inc_ly();
-                //Resetting vram cycle here
-                self.set_vram_cycle(self.vram_cycle_num - vram_cycle_per_ly_inc);
-            }
-        } else {
-            self.set_lcd_mode_to_vblank();
+    fn request_ppu_interrupts(&mut self, interrupts: u8) {
+        if interrupts & ppu::VBLANK_INTERRUPT != 0 {
+            self.request_vblank();
+        }
+        if interrupts & ppu::STAT_INTERRUPT != 0 {
+            self.request_lcd_interrupt();
         }
     }
 
-    pub fn execute_opcodes(&mut self, count: u8) {
-        let mut canvases = Canvases::new();
+    /// Runs `count` instructions and reports whether a frame became ready
+    /// to draw along the way.
+    pub fn execute_opcodes(&mut self, count: u8) -> Result<bool, EmulationError> {
+        let mut frame_ready = false;
 
         //ff10-ff14 is responsible for sound channel 1
         let pre_ff10 = self.memory[0xff10];
@@ -6771,51 +5422,65 @@ impl Gameboy {
         let pre_ff14 = self.memory[0xff14];
 
         for _ in 0..count {
-            let instruction = self.memory[self.registers.pc as usize];
-            self.add_cycles(instruction, CycleRegister::CpuCycle);
-            self.cycle_based_gpu_operation(instruction);
-            self.execute_instruction(instruction);
+            self.step();
+            self.check_fault()?;
 
             if self.is_lcd_display_enable() && self.should_draw {
-                canvases.update_char_map_canvas(self);
-                canvases.render_background_map_as_image_data(self);
-                canvases.draw_screen_from_memory(self);
+                self.should_draw = false;
+                self.apply_gameshark_cheats();
+                frame_ready = true;
             }
 
             if self.break_points.contains(&self.registers.pc) {
                 self.is_running = false;
             }
 
-            if self.is_channel1_changed(pre_ff10, pre_ff11, pre_ff12, pre_ff13, pre_ff14) {
-                if self.sound_dirty_flag_check_s1() {
-                    self.reset_fm_osc(self.square1());
-                }
+            if self.is_channel1_changed(pre_ff10, pre_ff11, pre_ff12, pre_ff13, pre_ff14)
+                && self.sound_dirty_flag_check_s1()
+            {
+                self.is_square1_changed = true;
             }
         }
+
+        Ok(frame_ready)
     }
 
-    pub fn debug_serial_value(&mut self) {
-        let character = (self.memory[0xff01] as char).to_string();
+    fn handle_serial(&mut self) {
+        self.serial_output.push(self.memory[0xff01] as char);
+    }
 
-        let document = web_sys::window().unwrap().document().unwrap();
-        let serial_debug_id = "serial-debug";
+    /// Drains the characters the game has written to the serial port since
+    /// the last call.
+    pub fn take_serial_output(&mut self) -> String {
+        std::mem::take(&mut self.serial_output)
+    }
 
-        let el = document.get_element_by_id(serial_debug_id).unwrap();
+    /// Returns the current state of sound channel 1 if its registers changed
+    /// since the last call.
+    pub fn take_square1_update(&mut self) -> Option<Channel> {
+        if self.is_square1_changed {
+            self.is_square1_changed = false;
+            Some(self.square1())
+        } else {
+            None
+        }
+    }
 
-        let current_html = el.inner_html().clone();
-        let new_html = format!("{}{}", current_html, character);
-        info!("{}", new_html);
-        el.set_inner_html(&new_html);
+    /// Returns whether the rumble motor is on if it was switched on or off
+    /// since the last call.
+    pub fn take_rumble_update(&mut self) -> Option<bool> {
+        self.mapper.take_rumble_update()
     }
 
-    pub fn execute_opcodes_no_stop(&mut self, count: u32) {
+    /// Runs until roughly `count` cycles have elapsed or a frame is ready to
+    /// draw, returning whether the frame is ready. An error stops the
+    /// gameboy and carries the address of the instruction that caused it.
+    pub fn execute_opcodes_no_stop(&mut self, count: u32) -> Result<bool, EmulationError> {
         info!("execute_opcodes_no_stop");
         if self.cpu_paused || !self.is_running {
-            return;
+            return Ok(false);
         }
 
-        let canvases = Canvases::new();
-
         //#ff10-ff14 is responsible for sound channel 1
         // let pre_ff10 = self.memory[0xff10];
         // let pre_ff11 = self.memory[0xff11];
@@ -6823,16 +5488,9 @@ impl Gameboy {
         // let pre_ff13 = self.memory[0xff13];
         // let pre_ff14 = self.memory[0xff14];
 
-        let window = web_sys::window().expect("should have a window in this context");
-        let performance = window
-            .performance()
-            .expect("performance should be available");
-
         let start_cycle_count = self.total_cycle();
         let mut last_cycle_count = 0;
         let cycle_log_target = 50_000;
-        let time_last_draw = performance.now();
-        let mut previsou_halt = false;
 
         loop {
             if self.cpu_paused || !self.is_running {
@@ -6843,59 +5501,11 @@ impl Gameboy {
                 break;
             }
 
-            let instruction = self.read_memory(self.registers.pc);
-
-            // FIXME: Only do this on first time through when the bootrom unmaps itself
-            if self.registers.pc == 0xfe {
-                info!("PC: 0xfe, instruction: {:x}", instruction);
-                if instruction == 0x00e0 {
-                    info!("PC: 0xfe, instruction: e0, reg a: {:?}", self.registers.a);
-                    // && self.registers.a == 1
-                    {
-                        info!("Unmapping bootrom...");
-                        for idx in 0x00..0xff {
-                            // info!("\t{:?} -> {:?}", idx, self.cartridge[idx]);
-                            self.memory[idx] = self.cartridge[idx];
-                        }
-                    }
-                }
-            }
-
             if self.total_cycle() - last_cycle_count > cycle_log_target {
                 last_cycle_count = self.total_cycle();
             }
 
-            if self.is_halt {
-                self.add_cycles(0x00, CycleRegister::TimerCycle);
-                self.cycle_based_gpu_operation(instruction);
-                self.execute_interuption();
-            } else {
-                self.cycle_based_gpu_operation(instruction);
-                self.execute_instruction(instruction);
-            }
-
-            self.add_cycles(instruction, CycleRegister::CpuCycle);
-            self.update_timer(instruction);
-            self.execute_interuption();
-
-            if instruction == 0x076 {
-                //HALT: Pause CPU Until Interrupt
-                self.is_halt = true;
-                if !self.ime() {
-                    let next_instruction = self.read_memory(self.registers.pc);
-                    self.registers.set_pc(self.registers.pc - 1);
-                    self.execute_instruction(next_instruction);
-                } else {
-                    // self.execute_instruction(0x00);
-                }
-                info!(
-                    "Update halt to true, pc:{:x}, instruction: {:x}",
-                    self.registers.pc,
-                    self.read_memory(self.registers.pc)
-                );
-
-                // break;
-            }
+            self.step();
 
             //quick find me
             if self.break_points.contains(&self.registers.pc)
@@ -6905,58 +5515,65 @@ impl Gameboy {
                 self.is_running = false;
             }
 
+            self.check_fault()?;
+
             let executed_cycles = self.total_cycle() - start_cycle_count;
             if executed_cycles as u32 > count {
-                break;
+                return Ok(false);
             }
 
             // TODO: Move this to a handle-serial-bus function
             if self.memory[0xff02] == 0x81 {
-                self.debug_serial_value();
+                self.handle_serial();
                 info!("PC: {:x}", self.registers.pc);
                 self.memory[0xff02] = 0x0;
             }
 
             if self.is_lcd_display_enable() && self.should_draw {
-                canvases.draw_screen_with_obj(self);
-                // canvases.draw_screen_from_memory(self);
                 self.should_draw = false;
-                // let now = performance.now();
-                // let elapsed = now - time_last_draw;
-                // let _time_to_sleep = 16.66 - elapsed;
-                // if time_to_sleep > 0.0 {
-                //     info!("Prepping timeout...!");
-                //     Timeout::new(time_to_sleep as u32, || {
-                //         info!("Timeout success!");
-                //         self.execute_opcodes_no_stop()
-                //     })
-                //     .forget();
-
-                //     // Timeout::new(time_to_sleep, || self.execute_opcodes_no_stop()).forget();
-                // }
-                break;
+                self.apply_gameshark_cheats();
+                return Ok(true);
             }
         }
-    }
 
-    pub fn reset_fm_osc(&mut self, square1: Channel) {
-        self.fm_osc.set_primary_frequency(square1.frequency());
-        self.fm_osc.set_gain_shift(
-            square1.volume() as f32 * 0.1,
-            square1.envelop_shift_num(),
-            square1.is_envelop_increase(),
-        );
+        Ok(false)
     }
 
     fn sound_dirty_flag_check_s1(&self) -> bool {
         let is_volume_non_zero = self.square1().volume() > 0;
         let is_frequency_non_zero = self.square1().fr() > 0;
-        return self.is_sound_all_on() && is_volume_non_zero && is_frequency_non_zero;
-    }
-
-    pub fn new() -> Gameboy {
+        self.is_sound_all_on() && is_volume_non_zero && is_frequency_non_zero
+    }
+
+    /// Builds a gameboy that boots the given cartridge ROM. From JS the ROM is
+    /// passed as a `Uint8Array`, and a bad header is thrown as an error.
+    ///
+    /// The boot ROM has to be the one for `model`. It's mapped in and run
+    /// first, until it unmaps itself through 0xFF50. Without one, the gameboy
+    /// starts at 0x100 in the state that model's boot ROM would have left it
+    /// in.
+    pub fn from_rom(
+        rom: &[u8],
+        model: Model,
+        boot_rom: Option<Vec<u8>>,
+    ) -> Result<Gameboy, LoadError> {
         info!("Starting a new gameboy!");
 
+        let cartridge_header = CartridgeHeader::parse(rom)?;
+        if let Err(err) = cartridge_header.verify_global_checksum() {
+            info!("{}", err);
+        }
+        let mapper = mapper::from_header(&cartridge_header, rom)?;
+
+        if let Some(boot_rom) = &boot_rom {
+            if boot_rom.len() != model.boot_rom_size() {
+                return Err(LoadError::InvalidBootRomSize {
+                    model,
+                    len: boot_rom.len(),
+                });
+            }
+        }
+
         let flag = Flag {
             z: false,
             n: false,
@@ -6965,7 +5582,7 @@ impl Gameboy {
             ime: true,
         };
 
-        let mut registers = Registers {
+        let registers = Registers {
             a: 0,
             b: 0,
             c: 0,
@@ -6978,139 +5595,65 @@ impl Gameboy {
             pc: 0x00,
         };
 
-        let boot_rom_content = include_bytes!("boot-rom.gb");
-        // let boot_rom_content = include_bytes!("test_rom.gb");
-        // let cartridge_content = include_bytes!("cpu_instrs.gb");
-        // let cartridge_content = include_bytes!("mario.gb");
-        let cartridge_content = include_bytes!("pokered.gbc");
-        // let cartridge_content = include_bytes!("tetris.gb");
-        // let cartridge_content = include_bytes!("02-interrupts.gb"); //Passed
-        // let cartridge_content = include_bytes!("01-special.gb"); //Passed
-        // let cartridge_content = include_bytes!("11-op a,(hl).gb"); //Passed
-        // let cartridge_content = include_bytes!("07-jr,jp,call,ret,rst.gb"); //Passed!
-        // let cartridge_content = include_bytes!("08-misc instrs.gb");//Passed!
-        // let cartridge_content = include_bytes!("03-op sp,hl.gb"); //Passed!
-        // let cartridge_content = include_bytes!("04-op r,imm.gb"); //Passed!
-        // let cartridge_content = include_bytes!("05-op rp.gb"); //Passed!
-        // let cartridge_content = include_bytes!("06-ld r,r.gb");//Passed!
-        // let cartridge_content = include_bytes!("09-op r,r.gb"); //Passed!
-        // let cartridge_content = include_bytes!("10-bit ops.gb"); //Passed!
-
-        let full_memory_capacity = 0x10000;
-
-        let head = boot_rom_content;
-        let body = &cartridge_content[0x100..0x8000// (cartridge_content.len())
-        ];
-
-        // let body = cartridge_content;
-
-        let mut full_memory: Vec<u8> = Vec::new();
-
-        full_memory.extend_from_slice(head);
-        full_memory.extend_from_slice(body);
-
-        full_memory.resize_with(full_memory_capacity, || 0);
+        let mut full_memory = rom[..rom.len().min(0x8000)].to_vec();
+        full_memory.resize_with(MEMORY_SIZE, || 0);
 
         // Vblank
         // full_memory[0xff44] = 0x90;
 
         let pixel_byte_vec = full_memory[0x8000..0x8800].to_vec();
-        let image_data = pixels_to_image_data(pixel_byte_vec.clone());
-
-        //FmOsc Here
-
-        let fm_osc = match Gameboy::initialize_fm_osc() {
-            Ok(something) => something,
-            _ => panic!("Failed initialize FmOsc"),
-        };
-
-        let mut ram_bank_memory = Vec::new();
-        ram_bank_memory.resize(0x8000, 0);
+        let image_data = pixels_to_image_data(pixel_byte_vec.clone(), &DisplayPalette::default());
 
-        Gameboy {
+        let mut gameboy = Gameboy {
             background_width: BACKGROUND_WIDTH,
             background_height: BACKGROUND_HEIGHT,
             screen_width: SCREEN_WIDTH,
             screen_height: SCREEN_HEIGHT,
             registers,
-            fm_osc,
             image_data,
             total_cycle_num: 0,
-            vram_cycle_num: 0,
-            timer_cycle_num: 0,
-            timer: 0,
+            timer: Timer::default(),
             is_running: false,
             is_halt: false,
+            is_halt_bug: false,
+            should_enable_ime: false,
             break_points: vec![],
-            cpu_clock: 0,
             cpu_paused: false,
             should_draw: false,
-            cartridge: cartridge_content.to_vec(),
-            mbc: Gameboy::get_mbc_from_memory(&full_memory),
-            rom_bank: 1,
-            ram_bank: 0,
-            ram_bank_memory,
-            is_ram_enabled: false,
-            is_rom_banking_enabled: false,
+            cartridge_header,
+            mapper,
             memory: full_memory,
+            boot_rom,
+            model,
             joypad_state: 0xff,
-            divide_register_cycle_counter: 0
-        }
-    }
-
-    fn initialize_fm_osc() -> Result<FmOsc, JsValue> {
-        let ctx = web_sys::AudioContext::new()?;
-
-        // Create our web audio objects.
-        let primary = ctx.create_oscillator()?;
-        let fm_osc = ctx.create_oscillator()?;
-        let gain = ctx.create_gain()?;
-        let fm_gain = ctx.create_gain()?;
-
-        // Some initial settings:
-        primary.set_type(OscillatorType::Square);
-        primary.frequency().set_value(0.0);
-        gain.gain().set_value(0.0); // starts muted
-        fm_gain.gain().set_value(0.0); // no initial frequency modulation
-        fm_osc.set_type(OscillatorType::Square);
-        fm_osc.frequency().set_value(0.0);
-
-        // Connect the nodes up!
-
-        // The primary oscillator is routed through the gain node, so that
-        // it can control the overall output volume.
-        primary.connect_with_audio_node(&gain)?;
-
-        // Then connect the gain node to the AudioContext destination (aka
-        // your speakers).
-        gain.connect_with_audio_node(&ctx.destination())?;
-
-        // The FM oscillator is connected to its own gain node, so it can
-        // control the amount of modulation.
-        fm_osc.connect_with_audio_node(&fm_gain)?;
+            serial_output: String::new(),
+            is_square1_changed: false,
+            instruction_pc: 0,
+            fault: None,
+            cheats: Cheats::default(),
+            ppu: Ppu::default(),
+        };
 
-        // Connect the FM oscillator to the frequency parameter of the main
-        // oscillator, so that the FM node can modulate its frequency.
-        fm_gain.connect_with_audio_param(&primary.frequency())?;
+        if gameboy.boot_rom.is_none() {
+            gameboy.skip_boot_rom();
+        }
 
-        // Start the oscillators!
-        primary.start()?;
-        fm_osc.start()?;
+        Ok(gameboy)
+    }
 
-        Ok(FmOsc {
-            ctx,
-            primary,
-            gain,
-            fm_gain,
-            fm_osc,
-            fm_freq_ratio: 0.0,
-            fm_gain_ratio: 0.0,
-        })
+    fn skip_boot_rom(&mut self) {
+        let header_checksum = self.cartridge_header.header_checksum();
+        self.registers = boot::post_boot_registers(self.model, header_checksum);
+        self.timer = Timer::with_system_counter(boot::post_boot_system_counter(self.model));
+        for (address, value) in boot::post_boot_io_registers(self.model) {
+            self.memory[address as usize] = value;
+        }
+        self.sync_timer_registers();
     }
 
-    pub fn char_map_to_image_data(&mut self) -> Vec<u8> {
+    pub fn char_map_to_image_data(&mut self, palette: &DisplayPalette) -> Vec<u8> {
         let pixels_vec = self.bg_window_char_map_bytes();
-        let new_image_data = pixels_to_image_data(pixels_vec);
+        let new_image_data = pixels_to_image_data(pixels_vec, palette);
 
         self.image_data = new_image_data.clone();
 
@@ -7118,85 +5661,78 @@ impl Gameboy {
     }
 }
 
-pub fn gameboy_from_serializable(serializeable: SerializedGameboy) -> Gameboy {
-    let fm_osc = match Gameboy::initialize_fm_osc() {
-        Ok(something) => something,
-        _ => panic!("Failed initialize FmOsc"),
-    };
+impl Gameboy {
+    /// Replaces where a Pocket Camera gets its pictures from, like a webcam
+    /// or a fixed picture for tests.
+    pub fn set_camera_image_source(&mut self, image_source: Box<dyn ImageSource>) {
+        self.mapper.set_image_source(image_source);
+    }
+}
 
+/// Restores a save state. Save states don't carry the ROM, so the same
+/// cartridge has to be supplied again.
+pub fn gameboy_from_serializable(
+    serializeable: SerializedGameboy,
+    rom: &[u8],
+) -> Result<Gameboy, LoadError> {
+    if serializeable.memory.len() != MEMORY_SIZE {
+        return Err(LoadError::InvalidSaveStateMemory {
+            len: serializeable.memory.len(),
+        });
+    }
+
+    let cartridge_header = CartridgeHeader::parse(rom)?;
+    let mut mapper = mapper::from_header(&cartridge_header, rom)?;
     let full_memory = serializeable.memory.clone();
 
     let pixel_byte_vec = full_memory[0x8000..0x8800].to_vec();
-    let image_data = pixels_to_image_data(pixel_byte_vec.clone());
-    let cartridge_content = include_bytes!("cpu_instrs.gb");
-    let cartridge = &cartridge_content[0x0..(cartridge_content.len())];
+    let image_data = pixels_to_image_data(pixel_byte_vec.clone(), &DisplayPalette::default());
 
-    let mut ram_bank_memory = Vec::new();
-    ram_bank_memory.resize(0x8000, 0);
+    // Older save states don't carry the cartridge RAM
+    if !serializeable.cartridge_save.is_empty() {
+        mapper.import_save(&serializeable.cartridge_save)?;
+    }
+    if let Some(mapper_state) = serializeable.mapper_state.clone() {
+        mapper.load_state(mapper_state)?;
+    }
+
+    let mut ppu = Ppu::default();
+    ppu.load_state(match serializeable.ppu_state.clone() {
+        Some(ppu_state) => ppu_state,
+        None => ppu::State::resume(&full_memory, serializeable.vram_cycle_num),
+    });
 
-    let gameboy = Gameboy {
+    Ok(Gameboy {
         // From serialized
         registers: serializeable.registers.clone(),
         total_cycle_num: serializeable.total_cycle_num,
-        vram_cycle_num: serializeable.vram_cycle_num,
-        timer_cycle_num: serializeable.timer_cycle_num,
-        timer: serializeable.timer,
-        cpu_clock: serializeable.cpu_clock,
+        timer: serializeable.timer.clone(),
         break_points: serializeable.break_points.clone(),
+        is_halt: serializeable.is_halt,
+        is_halt_bug: serializeable.is_halt_bug,
+        should_enable_ime: serializeable.should_enable_ime,
         // Default, non-serializable values
         background_width: BACKGROUND_WIDTH,
         background_height: BACKGROUND_HEIGHT,
         screen_width: SCREEN_WIDTH,
         screen_height: SCREEN_HEIGHT,
-        fm_osc,
         image_data,
         should_draw: false,
         is_running: false,
-        is_halt: false,
         cpu_paused: false,
-        cartridge: cartridge.to_vec(),
-        mbc: Gameboy::get_mbc_from_memory(&full_memory),
-        rom_bank: 1,
-        ram_bank: 0,
-        ram_bank_memory,
-        is_ram_enabled: false,
-        is_rom_banking_enabled: false,
+        cartridge_header,
+        mapper,
         memory: full_memory,
+        boot_rom: serializeable.boot_rom.clone(),
+        model: serializeable.model,
         joypad_state: 0xff,
-        divide_register_cycle_counter: 0
-    };
-
-    gameboy
-}
-
-impl SerializedGameboy {
-    pub fn to_json(&self) -> JsValue {
-        JsValue::from_serde(&self).unwrap()
-    }
-
-    pub fn from_json(val: &JsValue) -> Gameboy {
-        let serialized: SerializedGameboy = val.into_serde().unwrap();
-        let gameboy: Gameboy = gameboy_from_serializable(serialized);
-        gameboy
-    }
-}
-
-#[wasm_bindgen]
-pub fn to_save_state(gameboy: Gameboy) -> JsValue {
-    gameboy.to_serializable().to_json()
-}
-
-#[wasm_bindgen]
-pub fn load_state(val: &JsValue) -> Gameboy {
-    SerializedGameboy::from_json(val)
-}
-
-#[wasm_bindgen]
-pub fn init() {
-    match console_log::init_with_level(Level::Debug) {
-        Ok(_value) => info!("WASM Gameboy Emulator initialized"),
-        Err(_err) => println!("Failed to initialize console logger"),
-    }
+        serial_output: String::new(),
+        is_square1_changed: false,
+        instruction_pc: 0,
+        fault: None,
+        cheats: Cheats::default(),
+        ppu,
+    })
 }
 
 #[wasm_bindgen]
@@ -7440,3 +5976,60 @@ pub fn opcode_name(opcode: u8) -> String {
 pub fn init_panic_hook() {
     utils::set_panic_hook();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cartridge::test_rom;
+
+    const HUC3: u8 = 0xfe;
+
+    // Jumps from the entry point to `program` at $0150
+    fn rom_with_program(cartridge_type: u8, program: &[u8]) -> Vec<u8> {
+        let mut rom = test_rom::build(cartridge_type, 0x00, 0x00);
+        // nop; jp $0150
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+        rom[0x150..0x150 + program.len()].copy_from_slice(program);
+        test_rom::fix_checksums(&mut rom);
+        rom
+    }
+
+    #[test]
+    fn rejects_save_states_with_truncated_memory() {
+        let rom = test_rom::build(0x00, 0x00, 0x00);
+        let gameboy = Gameboy::from_rom(&rom, Model::Dmg, None).unwrap();
+
+        let mut state = gameboy.to_serializable();
+        state.memory.truncate(0x8000);
+        assert_eq!(
+            gameboy_from_serializable(state, &rom).err(),
+            Some(LoadError::InvalidSaveStateMemory { len: 0x8000 })
+        );
+
+        let state = gameboy.to_serializable();
+        assert!(gameboy_from_serializable(state, &rom).is_ok());
+    }
+
+    #[test]
+    fn stops_on_mapper_features_that_arent_emulated() {
+        #[rustfmt::skip]
+        let program = [
+            0x3e, 0x0b, 0xea, 0x00, 0x00, // ld a, $0b; ld ($0000), a
+            0x3e, 0x70, 0xea, 0x00, 0xa0, // ld a, $70; ld ($a000), a
+            0x18, 0xfe,                   // jr @
+        ];
+        let rom = rom_with_program(HUC3, &program);
+        let mut gameboy = Gameboy::from_rom(&rom, Model::Dmg, None).unwrap();
+        gameboy.start_running();
+
+        assert_eq!(
+            gameboy.execute_opcodes_no_stop(1000),
+            Err(EmulationError::UnsupportedMapperFeature {
+                mapper: "HuC3",
+                address: 0xa000,
+                value: 0x70,
+                pc: 0x0157,
+            })
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! The Pocket Camera (Game Boy Camera). Its mapper works like an MBC5 with
+//! 1MB of ROM and 128KB of RAM, and has a bank of registers driving the
+//! image sensor mapped in place of the RAM by setting bit 4 of the RAM bank.
+//!
+//! A capture takes a picture from the image source, dithers it down to 4
+//! shades with the threshold matrix the game wrote to the registers and
+//! stores it as tiles at the start of RAM, where the game copies it from.
+//! The sensor's edge enhancement and gain aren't emulated, the picture is
+//! taken as already processed.
+
+use super::{is_ram_enable_value, CartridgeMemory, Mapper, MapperState};
+use crate::LoadError;
+
+pub const RAM_SIZE: usize = 0x20000;
+
+pub const CAMERA_IMAGE_WIDTH: usize = 128;
+pub const CAMERA_IMAGE_HEIGHT: usize = 112;
+
+const REGISTER_COUNT: usize = 0x36;
+// Selects the registers instead of a RAM bank
+const REGISTER_BANK: u8 = 0b10000;
+
+// Bits of register 0, which starts a capture and reads as busy until done
+const CAPTURE: u8 = 0b1;
+// Register 1 bit 7 turns off the sensor's negative output, which shortens
+// the capture
+const NO_NEGATIVE: u8 = 0b10000000;
+// Registers 2 and 3 are the exposure time, big endian
+const EXPOSURE_HIGH: usize = 2;
+const EXPOSURE_LOW: usize = 3;
+// A threshold for each of the 3 darker shades, for each pixel of a 4x4 grid
+const DITHER_MATRIX: usize = 6;
+
+// Where the captured picture is written to in RAM
+const IMAGE_ADDRESS: usize = 0x100;
+
+/// Where the camera gets its pictures from.
+pub trait ImageSource {
+    /// A picture of `CAMERA_IMAGE_WIDTH` by `CAMERA_IMAGE_HEIGHT` pixels, row
+    /// by row, 0 being black and 255 white.
+    fn capture(&mut self) -> Vec<u8>;
+}
+
+/// The same picture on every capture.
+pub struct StaticImage {
+    pixels: Vec<u8>,
+}
+
+impl StaticImage {
+    /// Pictures of the wrong size are cut off or padded with white.
+    pub fn new(mut pixels: Vec<u8>) -> StaticImage {
+        pixels.resize(CAMERA_IMAGE_WIDTH * CAMERA_IMAGE_HEIGHT, 0xff);
+        StaticImage { pixels }
+    }
+}
+
+impl ImageSource for StaticImage {
+    fn capture(&mut self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct State {
+    rom_bank: u8,
+    ram_bank: u8,
+    is_ram_enabled: bool,
+    registers: Vec<u8>,
+    // Cycles left until the capture in progress is done
+    capture_cycles: u32,
+}
+
+pub struct Camera {
+    memory: CartridgeMemory,
+    state: State,
+    // A blank white picture is taken without one
+    image_source: Option<Box<dyn ImageSource>>,
+}
+
+impl Camera {
+    pub fn new(memory: CartridgeMemory) -> Camera {
+        Camera {
+            memory,
+            state: State {
+                rom_bank: 1,
+                ram_bank: 0,
+                is_ram_enabled: false,
+                registers: vec![0; REGISTER_COUNT],
+                capture_cycles: 0,
+            },
+            image_source: None,
+        }
+    }
+
+    fn is_register_selected(&self) -> bool {
+        self.state.ram_bank & REGISTER_BANK == REGISTER_BANK
+    }
+
+    fn start_capture(&mut self) {
+        let exposure = (self.state.registers[EXPOSURE_HIGH] as u32) << 8
+            | self.state.registers[EXPOSURE_LOW] as u32;
+        let negative_cycles = if self.state.registers[1] & NO_NEGATIVE == NO_NEGATIVE {
+            0
+        } else {
+            512
+        };
+        // The timing is in CPU cycles at 1MHz
+        self.state.capture_cycles = (32446 + negative_cycles + 16 * exposure) * 4;
+    }
+
+    fn finish_capture(&mut self) {
+        let pixels = match &mut self.image_source {
+            Some(image_source) => image_source.capture(),
+            None => vec![0xff; CAMERA_IMAGE_WIDTH * CAMERA_IMAGE_HEIGHT],
+        };
+
+        let tiles_per_row = CAMERA_IMAGE_WIDTH / 8;
+        for y in 0..CAMERA_IMAGE_HEIGHT {
+            for x in 0..CAMERA_IMAGE_WIDTH {
+                let brightness = pixels
+                    .get(y * CAMERA_IMAGE_WIDTH + x)
+                    .copied()
+                    .unwrap_or(0xff);
+                let shade = self.dither(x, y, brightness);
+
+                let tile = (y / 8) * tiles_per_row + x / 8;
+                let address = IMAGE_ADDRESS + tile * 16 + (y % 8) * 2;
+                let bit = 0b10000000 >> (x % 8);
+                self.set_image_bit(address, bit, shade & 0b01 != 0);
+                self.set_image_bit(address + 1, bit, shade & 0b10 != 0);
+            }
+        }
+
+        self.state.registers[0] &= !CAPTURE;
+        self.memory.mark_save_dirty();
+    }
+
+    // Shade 3 is black, each threshold the pixel is under darkens it a step
+    fn dither(&self, x: usize, y: usize, brightness: u8) -> u8 {
+        let matrix_idx = DITHER_MATRIX + ((y % 4) * 4 + x % 4) * 3;
+        let thresholds = &self.state.registers[matrix_idx..matrix_idx + 3];
+
+        if brightness < thresholds[0] {
+            3
+        } else if brightness < thresholds[1] {
+            2
+        } else if brightness < thresholds[2] {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn set_image_bit(&mut self, address: usize, bit: u8, is_set: bool) {
+        let byte = &mut self.memory.ram[address];
+        if is_set {
+            *byte |= bit;
+        } else {
+            *byte &= !bit;
+        }
+    }
+}
+
+impl Mapper for Camera {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.state.rom_bank
+        };
+        self.memory.read_rom(bank as usize, address)
+    }
+
+    fn write_control(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.state.is_ram_enabled = is_ram_enable_value(value),
+            0x2000..=0x3FFF => self.state.rom_bank = value & 0b00111111,
+            0x4000..=0x5FFF => self.state.ram_bank = value & 0b11111,
+            _ => {}
+        }
+    }
+
+    // Only the first register can be read back, the rest read as 0. RAM can
+    // be read without being enabled, except during a capture.
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.is_register_selected() {
+            let register = address as usize & 0x7f;
+            return if register == 0 {
+                self.state.registers[0]
+            } else {
+                0
+            };
+        }
+
+        if self.state.capture_cycles > 0 {
+            return 0;
+        }
+        self.memory.read_ram(self.state.ram_bank as usize, address)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.is_register_selected() {
+            let register = address as usize & 0x7f;
+            if register == 0 {
+                self.state.registers[0] = value & 0b111;
+                if value & CAPTURE == CAPTURE {
+                    self.start_capture();
+                } else {
+                    self.state.capture_cycles = 0;
+                }
+            } else if register < REGISTER_COUNT {
+                self.state.registers[register] = value;
+            }
+            return;
+        }
+
+        if self.state.is_ram_enabled && self.state.capture_cycles == 0 {
+            self.memory
+                .write_ram(self.state.ram_bank as usize, address, value);
+        }
+    }
+
+    fn memory(&self) -> &CartridgeMemory {
+        &self.memory
+    }
+
+    fn memory_mut(&mut self) -> &mut CartridgeMemory {
+        &mut self.memory
+    }
+
+    fn name(&self) -> &'static str {
+        "POCKET CAMERA"
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Camera(self.state.clone())
+    }
+
+    fn load_state(&mut self, state: MapperState) -> Result<(), LoadError> {
+        match state {
+            MapperState::Camera(mut state) => {
+                state.registers.resize(REGISTER_COUNT, 0);
+                self.state = state;
+                Ok(())
+            }
+            _ => Err(LoadError::MapperStateMismatch {
+                mapper: self.name(),
+            }),
+        }
+    }
+
+    fn debug_description(&self) -> String {
+        let mapped = if self.is_register_selected() {
+            "Camera registers mapped"
+        } else {
+            "RAM mapped"
+        };
+        if self.state.capture_cycles > 0 {
+            format!("{}, capturing", mapped)
+        } else {
+            mapped.to_string()
+        }
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        if self.state.capture_cycles == 0 {
+            return;
+        }
+
+        self.state.capture_cycles = self.state.capture_cycles.saturating_sub(cycles as u32);
+        if self.state.capture_cycles == 0 {
+            self.finish_capture();
+        }
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.state.rom_bank as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.state.ram_bank
+    }
+
+    fn is_ram_enabled(&self) -> bool {
+        self.state.is_ram_enabled
+    }
+
+    fn set_image_source(&mut self, image_source: Box<dyn ImageSource>) {
+        self.image_source = Some(image_source);
+    }
+}
@@ -0,0 +1,123 @@
+//! Hudson's HuC1, up to 1MB of ROM and 32KB of RAM, with an infrared LED and
+//! receiver that can be mapped in place of the RAM.
+
+use super::{CartridgeMemory, Mapper, MapperState};
+use crate::LoadError;
+
+// Written to 0x0000-0x1FFF to map the IR register, anything else maps RAM
+const IR_SELECT: u8 = 0x0e;
+
+// Reading the receiver with no light coming in
+const IR_NO_LIGHT: u8 = 0xc0;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct State {
+    rom_bank: u8,
+    ram_bank: u8,
+    is_ir_selected: bool,
+    is_ir_led_on: bool,
+}
+
+pub struct Huc1 {
+    memory: CartridgeMemory,
+    state: State,
+}
+
+impl Huc1 {
+    pub fn new(memory: CartridgeMemory) -> Huc1 {
+        Huc1 {
+            memory,
+            state: State {
+                rom_bank: 1,
+                ram_bank: 0,
+                is_ir_selected: false,
+                is_ir_led_on: false,
+            },
+        }
+    }
+}
+
+impl Mapper for Huc1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.state.rom_bank
+        };
+        self.memory.read_rom(bank as usize, address)
+    }
+
+    fn write_control(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.state.is_ir_selected = value == IR_SELECT,
+            0x2000..=0x3FFF => self.state.rom_bank = value & 0b00111111,
+            0x4000..=0x5FFF => self.state.ram_bank = value & 0b11,
+            _ => {}
+        }
+    }
+
+    // There's nobody else to talk to, so the receiver never sees any light
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.state.is_ir_selected {
+            return IR_NO_LIGHT;
+        }
+        self.memory.read_ram(self.state.ram_bank as usize, address)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.state.is_ir_selected {
+            self.state.is_ir_led_on = value & 0b1 == 0b1;
+        } else {
+            self.memory
+                .write_ram(self.state.ram_bank as usize, address, value);
+        }
+    }
+
+    fn memory(&self) -> &CartridgeMemory {
+        &self.memory
+    }
+
+    fn memory_mut(&mut self) -> &mut CartridgeMemory {
+        &mut self.memory
+    }
+
+    fn name(&self) -> &'static str {
+        "HuC1"
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Huc1(self.state.clone())
+    }
+
+    fn load_state(&mut self, state: MapperState) -> Result<(), LoadError> {
+        match state {
+            MapperState::Huc1(state) => {
+                self.state = state;
+                Ok(())
+            }
+            _ => Err(LoadError::MapperStateMismatch {
+                mapper: self.name(),
+            }),
+        }
+    }
+
+    fn debug_description(&self) -> String {
+        if !self.state.is_ir_selected {
+            return String::new();
+        }
+        let led = if self.state.is_ir_led_on { "on" } else { "off" };
+        format!("IR mapped, LED {}", led)
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.state.rom_bank as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.state.ram_bank
+    }
+
+    fn is_ram_enabled(&self) -> bool {
+        !self.state.is_ir_selected
+    }
+}
@@ -0,0 +1,360 @@
+//! Hudson's HuC3, up to 2MB of ROM and 128KB of RAM, plus an infrared port,
+//! a clock and a piezo speaker. The clock and speaker sit behind a small
+//! controller that takes 4 bit commands through 0xA000-0xBFFF and has 256
+//! nibbles of its own memory, the time being copied in and out of it.
+//!
+//! Like the MBC3 clock, this one either counts emulated cycles or follows the
+//! host clock.
+//!
+//! Commands other than the ones below are reported as unsupported, which
+//! stops emulation rather than leaving the game waiting on a missing answer.
+
+use super::{CartridgeMemory, Mapper, MapperState};
+use crate::LoadError;
+
+const CYCLES_PER_MINUTE: u32 = 4_194_304 * 60;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+// What 0xA000-0xBFFF is mapped to, selected by writing to 0x0000-0x1FFF
+const MODE_RAM_READ: u8 = 0x0;
+const MODE_RAM: u8 = 0xa;
+const MODE_COMMAND: u8 = 0xb;
+const MODE_RESPONSE: u8 = 0xc;
+const MODE_SEMAPHORE: u8 = 0xd;
+const MODE_IR: u8 = 0xe;
+
+// Commands, in the upper nibble of writes in MODE_COMMAND
+const COMMAND_READ: u8 = 0x1;
+const COMMAND_WRITE: u8 = 0x3;
+const COMMAND_ADDRESS_LOW: u8 = 0x4;
+const COMMAND_ADDRESS_HIGH: u8 = 0x5;
+const COMMAND_EXTENDED: u8 = 0x6;
+
+// Arguments to COMMAND_EXTENDED
+const EXTENDED_READ_TIME: u8 = 0x0;
+const EXTENDED_WRITE_TIME: u8 = 0x1;
+const EXTENDED_TONE: u8 = 0xe;
+
+// Reading the receiver with no light coming in
+const IR_NO_LIGHT: u8 = 0xc0;
+
+// The clock is appended to .sav files as the minute of the day and the day
+// as little endian u32s, followed by the host time they were saved at as a
+// u64. There's no common layout for this one, so it's this emulator's own
+// and saves from elsewhere are taken without it.
+const SAVE_SIZE: usize = 16;
+
+// Addressed by a byte
+const RTC_MEMORY_SIZE: usize = 0x100;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct State {
+    rom_bank: u8,
+    ram_bank: u8,
+    mode: u8,
+    // The controller's memory, a nibble per byte
+    rtc_memory: Vec<u8>,
+    rtc_address: u8,
+    // The last command and the nibble it returned, read back in MODE_RESPONSE
+    command: u8,
+    response: u8,
+    // Minute of the day and 12 bit day counter
+    minutes: u16,
+    days: u16,
+    // Cycles counted towards the next minute
+    cycle_num: u32,
+    is_host_clock: bool,
+    // The host time the clock was last brought up to date with, in seconds
+    host_time: Option<u64>,
+    // Seconds counted towards the next minute when following the host clock
+    host_seconds: u64,
+    is_tone_pending: bool,
+}
+
+pub struct Huc3 {
+    memory: CartridgeMemory,
+    state: State,
+    unsupported_write: Option<(u16, u8)>,
+}
+
+impl Huc3 {
+    pub fn new(memory: CartridgeMemory) -> Huc3 {
+        Huc3 {
+            memory,
+            state: State {
+                rom_bank: 1,
+                ram_bank: 0,
+                mode: MODE_RAM_READ,
+                rtc_memory: vec![0; RTC_MEMORY_SIZE],
+                rtc_address: 0,
+                command: 0,
+                response: 0,
+                minutes: 0,
+                days: 0,
+                cycle_num: 0,
+                is_host_clock: false,
+                host_time: None,
+                host_seconds: 0,
+                is_tone_pending: false,
+            },
+            unsupported_write: None,
+        }
+    }
+
+    fn advance_minutes(&mut self, minutes: u64) {
+        let total = self.state.minutes as u64 + minutes;
+        self.state.minutes = (total % MINUTES_PER_DAY as u64) as u16;
+        self.state.days =
+            ((self.state.days as u64 + total / MINUTES_PER_DAY as u64) & 0xfff) as u16;
+    }
+
+    fn run_command(&mut self, address: u16, value: u8) {
+        self.state.command = (value >> 4) & 0b111;
+        let argument = value & 0xf;
+
+        match self.state.command {
+            COMMAND_READ => {
+                self.state.response = self.state.rtc_memory[self.state.rtc_address as usize];
+                self.state.rtc_address = self.state.rtc_address.wrapping_add(1);
+            }
+            COMMAND_WRITE => {
+                self.state.rtc_memory[self.state.rtc_address as usize] = argument;
+                self.state.rtc_address = self.state.rtc_address.wrapping_add(1);
+            }
+            COMMAND_ADDRESS_LOW => {
+                self.state.rtc_address = (self.state.rtc_address & 0xf0) | argument
+            }
+            COMMAND_ADDRESS_HIGH => {
+                self.state.rtc_address = (self.state.rtc_address & 0x0f) | argument << 4;
+            }
+            COMMAND_EXTENDED => match argument {
+                EXTENDED_READ_TIME => {
+                    // Minutes in 0x00-0x02 and days in 0x03-0x05, low nibble
+                    // first
+                    for idx in 0..3 {
+                        self.state.rtc_memory[idx] = (self.state.minutes >> (idx * 4)) as u8 & 0xf;
+                        self.state.rtc_memory[idx + 3] = (self.state.days >> (idx * 4)) as u8 & 0xf;
+                    }
+                }
+                EXTENDED_WRITE_TIME => {
+                    let mut minutes = 0;
+                    let mut days = 0;
+                    for idx in 0..3 {
+                        minutes |= (self.state.rtc_memory[idx] as u16) << (idx * 4);
+                        days |= (self.state.rtc_memory[idx + 3] as u16) << (idx * 4);
+                    }
+                    self.state.minutes = minutes % MINUTES_PER_DAY;
+                    self.state.days = days;
+                    self.state.cycle_num = 0;
+                    self.memory.mark_save_dirty();
+                }
+                EXTENDED_TONE => self.state.is_tone_pending = true,
+                _ => self.unsupported_write = Some((address, value)),
+            },
+            _ => self.unsupported_write = Some((address, value)),
+        }
+    }
+}
+
+impl Mapper for Huc3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.state.rom_bank
+        };
+        self.memory.read_rom(bank as usize, address)
+    }
+
+    fn write_control(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.state.mode = value & 0xf,
+            0x2000..=0x3FFF => self.state.rom_bank = value & 0b01111111,
+            0x4000..=0x5FFF => self.state.ram_bank = value & 0b11,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.state.mode {
+            MODE_RAM_READ | MODE_RAM => self.memory.read_ram(self.state.ram_bank as usize, address),
+            MODE_RESPONSE => self.state.command << 4 | self.state.response,
+            // Commands complete straight away
+            MODE_SEMAPHORE => 0x01,
+            MODE_IR => IR_NO_LIGHT,
+            _ => 0xff,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        match self.state.mode {
+            MODE_RAM => self
+                .memory
+                .write_ram(self.state.ram_bank as usize, address, value),
+            MODE_COMMAND => self.run_command(address, value),
+            _ => {}
+        }
+    }
+
+    fn memory(&self) -> &CartridgeMemory {
+        &self.memory
+    }
+
+    fn memory_mut(&mut self) -> &mut CartridgeMemory {
+        &mut self.memory
+    }
+
+    fn name(&self) -> &'static str {
+        "HuC3"
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Huc3(self.state.clone())
+    }
+
+    fn load_state(&mut self, state: MapperState) -> Result<(), LoadError> {
+        match state {
+            MapperState::Huc3(mut state) => {
+                state.rtc_memory.resize(RTC_MEMORY_SIZE, 0);
+                self.state = state;
+                Ok(())
+            }
+            _ => Err(LoadError::MapperStateMismatch {
+                mapper: self.name(),
+            }),
+        }
+    }
+
+    fn debug_description(&self) -> String {
+        let mode = match self.state.mode {
+            MODE_RAM_READ => "RAM read only",
+            MODE_RAM => "RAM",
+            MODE_COMMAND => "Command",
+            MODE_RESPONSE => "Response",
+            MODE_SEMAPHORE => "Semaphore",
+            MODE_IR => "IR",
+            _ => "Nothing",
+        };
+        format!(
+            "{} mapped, day {} {:02}:{:02}",
+            mode,
+            self.state.days,
+            self.state.minutes / 60,
+            self.state.minutes % 60
+        )
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        if self.state.is_host_clock {
+            return;
+        }
+
+        self.state.cycle_num += cycles as u32;
+        if self.state.cycle_num >= CYCLES_PER_MINUTE {
+            self.state.cycle_num -= CYCLES_PER_MINUTE;
+            self.advance_minutes(1);
+        }
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.state.rom_bank as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.state.ram_bank
+    }
+
+    fn is_ram_enabled(&self) -> bool {
+        self.state.mode == MODE_RAM
+    }
+
+    fn save_trailer(&self) -> Vec<u8> {
+        let mut save = Vec::with_capacity(SAVE_SIZE);
+        save.extend_from_slice(&(self.state.minutes as u32).to_le_bytes());
+        save.extend_from_slice(&(self.state.days as u32).to_le_bytes());
+        save.extend_from_slice(&self.state.host_time.unwrap_or(0).to_le_bytes());
+        save
+    }
+
+    fn save_trailer_sizes(&self) -> &'static [usize] {
+        &[SAVE_SIZE, 0]
+    }
+
+    fn load_save_trailer(&mut self, trailer: &[u8]) {
+        if trailer.len() < SAVE_SIZE {
+            return;
+        }
+
+        let mut word = [0; 4];
+        word.copy_from_slice(&trailer[0..4]);
+        self.state.minutes = (u32::from_le_bytes(word) % MINUTES_PER_DAY as u32) as u16;
+        word.copy_from_slice(&trailer[4..8]);
+        self.state.days = (u32::from_le_bytes(word) & 0xfff) as u16;
+
+        let mut host_time = [0; 8];
+        host_time.copy_from_slice(&trailer[8..16]);
+        let host_time = u64::from_le_bytes(host_time);
+        if self.state.is_host_clock && host_time != 0 {
+            self.state.host_time = Some(host_time);
+        }
+    }
+
+    fn set_host_clock(&mut self, is_host_clock: bool) {
+        self.state.is_host_clock = is_host_clock;
+        self.state.host_time = None;
+    }
+
+    fn is_host_clock(&self) -> bool {
+        self.state.is_host_clock
+    }
+
+    fn sync_host_time(&mut self, unix_seconds: u64) {
+        if !self.state.is_host_clock {
+            return;
+        }
+
+        if let Some(host_time) = self.state.host_time {
+            self.state.host_seconds += unix_seconds.saturating_sub(host_time);
+            self.advance_minutes(self.state.host_seconds / 60);
+            self.state.host_seconds %= 60;
+        }
+        self.state.host_time = Some(unix_seconds);
+    }
+
+    fn take_tone(&mut self) -> bool {
+        let is_tone_pending = self.state.is_tone_pending;
+        self.state.is_tone_pending = false;
+        is_tone_pending
+    }
+
+    fn take_unsupported_write(&mut self) -> Option<(u16, u8)> {
+        self.unsupported_write.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+    use crate::{mapper, CartridgeHeader};
+
+    const HUC3: u8 = 0xfe;
+
+    #[test]
+    fn reports_commands_it_doesnt_emulate() {
+        let rom = test_rom::build(HUC3, 0x00, 0x00);
+        let mut huc3 = mapper::from_header(&CartridgeHeader::parse(&rom).unwrap(), &rom).unwrap();
+        huc3.write_control(0x0000, MODE_COMMAND);
+
+        // Reading the time and beeping are emulated
+        huc3.write_ram(0xa000, COMMAND_EXTENDED << 4 | EXTENDED_READ_TIME);
+        huc3.write_ram(0xa000, COMMAND_EXTENDED << 4 | EXTENDED_TONE);
+        assert_eq!(huc3.take_unsupported_write(), None);
+
+        huc3.write_ram(0xa000, COMMAND_EXTENDED << 4 | 0x2);
+        assert_eq!(huc3.take_unsupported_write(), Some((0xa000, 0x62)));
+        huc3.write_ram(0xb000, 0x70);
+        assert_eq!(huc3.take_unsupported_write(), Some((0xb000, 0x70)));
+        assert_eq!(huc3.take_unsupported_write(), None);
+    }
+}
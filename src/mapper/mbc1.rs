@@ -0,0 +1,203 @@
+//! MBC1, up to 2MB of ROM and 32KB of RAM. A 5 bit ROM bank register and a
+//! 2 bit register above it, which in RAM banking mode also picks the RAM bank
+//! and the ROM bank at 0x0000-0x3FFF.
+
+use super::{is_ram_enable_value, CartridgeMemory, Mapper, MapperState};
+use crate::LoadError;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct State {
+    bank1: u8,
+    bank2: u8,
+    is_rom_banking_enabled: bool,
+    is_ram_enabled: bool,
+}
+
+pub struct Mbc1 {
+    memory: CartridgeMemory,
+    state: State,
+    // MBC1M multicarts don't connect the top bit of the ROM bank register,
+    // putting the upper register right above the other four
+    is_multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn new(memory: CartridgeMemory, is_multicart: bool) -> Mbc1 {
+        Mbc1 {
+            memory,
+            state: State {
+                bank1: 1,
+                bank2: 0,
+                is_rom_banking_enabled: true,
+                is_ram_enabled: false,
+            },
+            is_multicart,
+        }
+    }
+
+    fn upper_rom_bank(&self) -> u16 {
+        let bank2_shift = if self.is_multicart { 4 } else { 5 };
+        (self.state.bank2 as u16) << bank2_shift
+    }
+
+    fn lower_rom_bank(&self) -> u16 {
+        if self.state.is_rom_banking_enabled {
+            0
+        } else {
+            self.upper_rom_bank()
+        }
+    }
+}
+
+impl Mapper for Mbc1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            self.lower_rom_bank()
+        } else {
+            self.rom_bank()
+        };
+        self.memory.read_rom(bank as usize, address)
+    }
+
+    fn write_control(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.state.is_ram_enabled = is_ram_enable_value(value),
+            0x2000..=0x3FFF => {
+                // Only the 5 bits themselves being 0 bumps them to 1, so
+                // banks 0x20, 0x40 and 0x60 can't be mapped to 0x4000-0x7FFF
+                self.state.bank1 = value & 0b11111;
+                if self.state.bank1 == 0 {
+                    self.state.bank1 = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.state.bank2 = value & 0b11,
+            _ => self.state.is_rom_banking_enabled = value & 0b1 == 0,
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.state.is_ram_enabled {
+            return 0xff;
+        }
+        self.memory.read_ram(self.ram_bank() as usize, address)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.state.is_ram_enabled {
+            self.memory
+                .write_ram(self.ram_bank() as usize, address, value);
+        }
+    }
+
+    fn memory(&self) -> &CartridgeMemory {
+        &self.memory
+    }
+
+    fn memory_mut(&mut self) -> &mut CartridgeMemory {
+        &mut self.memory
+    }
+
+    fn name(&self) -> &'static str {
+        if self.is_multicart {
+            "MBC1M"
+        } else {
+            "MBC1"
+        }
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Mbc1(self.state.clone())
+    }
+
+    fn load_state(&mut self, state: MapperState) -> Result<(), LoadError> {
+        match state {
+            MapperState::Mbc1(state) => {
+                self.state = state;
+                Ok(())
+            }
+            _ => Err(LoadError::MapperStateMismatch {
+                mapper: self.name(),
+            }),
+        }
+    }
+
+    fn rom_bank(&self) -> u16 {
+        let bank1 = if self.is_multicart {
+            self.state.bank1 & 0b1111
+        } else {
+            self.state.bank1
+        };
+        self.upper_rom_bank() | bank1 as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        if self.state.is_rom_banking_enabled {
+            0
+        } else {
+            self.state.bank2
+        }
+    }
+
+    fn is_ram_enabled(&self) -> bool {
+        self.state.is_ram_enabled
+    }
+
+    fn is_rom_banking_enabled(&self) -> bool {
+        self.state.is_rom_banking_enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+    use crate::mapper;
+    use crate::CartridgeHeader;
+
+    const MBC1: u8 = 0x01;
+
+    // Every bank starts with its own number
+    fn mbc1(rom_size_code: u8) -> Box<dyn Mapper> {
+        let mut rom = test_rom::build(MBC1, rom_size_code, 0x00);
+        for (bank, data) in rom.chunks_mut(0x4000).enumerate() {
+            data[0] = bank as u8;
+        }
+        mapper::from_header(&CartridgeHeader::parse(&rom).unwrap(), &rom).unwrap()
+    }
+
+    #[test]
+    fn maps_the_upper_bank_bits_to_0x0000_in_mode_1() {
+        // 2MB, the upper register picks one of four 512KB quarters
+        let mut mapper = mbc1(0x06);
+        mapper.write_control(0x4000, 0b10);
+        mapper.write_control(0x2000, 0x01);
+        assert_eq!(mapper.read_rom(0x0000), 0x00);
+        assert_eq!(mapper.read_rom(0x4000), 0x41);
+
+        mapper.write_control(0x6000, 1);
+        assert_eq!(mapper.read_rom(0x0000), 0x40);
+        assert_eq!(mapper.read_rom(0x4000), 0x41);
+
+        mapper.write_control(0x6000, 0);
+        assert_eq!(mapper.read_rom(0x0000), 0x00);
+    }
+
+    #[test]
+    fn multicarts_shift_the_upper_bank_bits_down_one() {
+        // 1MB with the same logo at the start of each quarter
+        let mut mapper = mbc1(0x05);
+        assert_eq!(mapper.name(), "MBC1M");
+
+        mapper.write_control(0x4000, 0b01);
+        // Bit 4 of the lower register isn't connected
+        mapper.write_control(0x2000, 0x12);
+        assert_eq!(mapper.read_rom(0x4000), 0x12);
+
+        mapper.write_control(0x6000, 1);
+        assert_eq!(mapper.read_rom(0x0000), 0x10);
+
+        mapper.write_control(0x4000, 0b11);
+        assert_eq!(mapper.read_rom(0x0000), 0x30);
+        assert_eq!(mapper.read_rom(0x4000), 0x32);
+    }
+}
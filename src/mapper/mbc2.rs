@@ -0,0 +1,109 @@
+//! MBC2, up to 256KB of ROM and 512 half bytes of RAM built into the mapper.
+
+use super::{is_ram_enable_value, CartridgeMemory, Mapper, MapperState};
+use crate::LoadError;
+
+// Stored a byte each
+pub const RAM_SIZE: usize = 0x200;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct State {
+    rom_bank: u8,
+    is_ram_enabled: bool,
+}
+
+pub struct Mbc2 {
+    memory: CartridgeMemory,
+    state: State,
+}
+
+impl Mbc2 {
+    pub fn new(memory: CartridgeMemory) -> Mbc2 {
+        Mbc2 {
+            memory,
+            state: State {
+                rom_bank: 1,
+                is_ram_enabled: false,
+            },
+        }
+    }
+}
+
+impl Mapper for Mbc2 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.state.rom_bank
+        };
+        self.memory.read_rom(bank as usize, address)
+    }
+
+    // The two registers are told apart by address bit 8 rather than by
+    // range, so both repeat across all of 0x0000-0x3FFF
+    fn write_control(&mut self, address: u16, value: u8) {
+        if address >= 0x4000 {
+            return;
+        }
+
+        if address & 0x100 == 0 {
+            self.state.is_ram_enabled = is_ram_enable_value(value);
+        } else {
+            self.state.rom_bank = value & 0xF;
+            if self.state.rom_bank == 0 {
+                self.state.rom_bank = 1;
+            }
+        }
+    }
+
+    // The 512 bytes repeat across 0xA000-0xBFFF, and the upper nibble isn't
+    // connected so reads back as 1s
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.state.is_ram_enabled {
+            return 0xff;
+        }
+        self.memory.read_ram(0, address) | 0xF0
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.state.is_ram_enabled {
+            self.memory.write_ram(0, address, value & 0xF);
+        }
+    }
+
+    fn memory(&self) -> &CartridgeMemory {
+        &self.memory
+    }
+
+    fn memory_mut(&mut self) -> &mut CartridgeMemory {
+        &mut self.memory
+    }
+
+    fn name(&self) -> &'static str {
+        "MBC2"
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Mbc2(self.state.clone())
+    }
+
+    fn load_state(&mut self, state: MapperState) -> Result<(), LoadError> {
+        match state {
+            MapperState::Mbc2(state) => {
+                self.state = state;
+                Ok(())
+            }
+            _ => Err(LoadError::MapperStateMismatch {
+                mapper: self.name(),
+            }),
+        }
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.state.rom_bank as u16
+    }
+
+    fn is_ram_enabled(&self) -> bool {
+        self.state.is_ram_enabled
+    }
+}
@@ -0,0 +1,196 @@
+//! MBC3, up to 2MB of ROM, 32KB of RAM and optionally a real-time clock,
+//! whose registers are selected through the RAM bank register.
+
+use super::rtc::{self, Rtc};
+use super::{is_ram_enable_value, CartridgeMemory, Mapper, MapperState};
+use crate::LoadError;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct State {
+    rom_bank: u8,
+    ram_bank: u8,
+    // The clock register mapped to 0xA000-0xBFFF in place of RAM
+    rtc_register: Option<u8>,
+    rtc: Rtc,
+    is_ram_enabled: bool,
+}
+
+pub struct Mbc3 {
+    memory: CartridgeMemory,
+    state: State,
+    has_timer: bool,
+}
+
+impl Mbc3 {
+    pub fn new(memory: CartridgeMemory, has_timer: bool) -> Mbc3 {
+        Mbc3 {
+            memory,
+            state: State {
+                rom_bank: 1,
+                ram_bank: 0,
+                rtc_register: None,
+                rtc: Rtc::default(),
+                is_ram_enabled: false,
+            },
+            has_timer,
+        }
+    }
+}
+
+impl Mapper for Mbc3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.state.rom_bank
+        };
+        self.memory.read_rom(bank as usize, address)
+    }
+
+    fn write_control(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.state.is_ram_enabled = is_ram_enable_value(value),
+            0x2000..=0x3FFF => {
+                self.state.rom_bank = value;
+                if self.state.rom_bank == 0 {
+                    self.state.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5FFF => {
+                if value <= 0x03 {
+                    self.state.ram_bank = value;
+                    self.state.rtc_register = None;
+                } else if (rtc::SECONDS..=rtc::DAY_HIGH).contains(&value) {
+                    self.state.rtc_register = Some(value);
+                }
+            }
+            _ => self.state.rtc.write_latch(value),
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.state.is_ram_enabled {
+            return 0xff;
+        }
+
+        match self.state.rtc_register {
+            Some(rtc_register) => self.state.rtc.read(rtc_register),
+            None => self.memory.read_ram(self.state.ram_bank as usize, address),
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.state.is_ram_enabled {
+            return;
+        }
+
+        match self.state.rtc_register {
+            Some(rtc_register) => {
+                self.state.rtc.write(rtc_register, value);
+                self.memory.mark_save_dirty();
+            }
+            None => self
+                .memory
+                .write_ram(self.state.ram_bank as usize, address, value),
+        }
+    }
+
+    fn memory(&self) -> &CartridgeMemory {
+        &self.memory
+    }
+
+    fn memory_mut(&mut self) -> &mut CartridgeMemory {
+        &mut self.memory
+    }
+
+    fn name(&self) -> &'static str {
+        "MBC3"
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Mbc3(self.state.clone())
+    }
+
+    fn load_state(&mut self, state: MapperState) -> Result<(), LoadError> {
+        match state {
+            MapperState::Mbc3(state) => {
+                self.state = state;
+                Ok(())
+            }
+            _ => Err(LoadError::MapperStateMismatch {
+                mapper: self.name(),
+            }),
+        }
+    }
+
+    fn debug_description(&self) -> String {
+        if !self.has_timer {
+            return String::new();
+        }
+
+        let clock = if self.state.rtc.is_host_clock() {
+            "host clock"
+        } else {
+            "emulated clock"
+        };
+        match self.state.rtc_register {
+            Some(rtc_register) => format!(
+                "RTC on the {}, register {:#04x} mapped",
+                clock, rtc_register
+            ),
+            None => format!("RTC on the {}", clock),
+        }
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        if self.has_timer {
+            self.state.rtc.tick(cycles);
+        }
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.state.rom_bank as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.state.ram_bank
+    }
+
+    fn is_ram_enabled(&self) -> bool {
+        self.state.is_ram_enabled
+    }
+
+    fn save_trailer(&self) -> Vec<u8> {
+        if self.has_timer {
+            self.state.rtc.to_save()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn save_trailer_sizes(&self) -> &'static [usize] {
+        if self.has_timer {
+            &[rtc::SAVE_SIZE, rtc::SHORT_SAVE_SIZE]
+        } else {
+            &[0]
+        }
+    }
+
+    fn load_save_trailer(&mut self, trailer: &[u8]) {
+        if self.has_timer {
+            self.state.rtc.load_save(trailer);
+        }
+    }
+
+    fn set_host_clock(&mut self, is_host_clock: bool) {
+        self.state.rtc.set_host_clock(is_host_clock);
+    }
+
+    fn is_host_clock(&self) -> bool {
+        self.state.rtc.is_host_clock()
+    }
+
+    fn sync_host_time(&mut self, unix_seconds: u64) {
+        self.state.rtc.sync_host_time(unix_seconds);
+    }
+}
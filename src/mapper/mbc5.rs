@@ -0,0 +1,238 @@
+//! MBC5, up to 8MB of ROM and 128KB of RAM. Unlike the earlier MBCs bank 0
+//! can be mapped to 0x4000-0x7FFF too. Some carts use the top bit of the RAM
+//! bank register to drive a rumble motor.
+
+use super::{is_ram_enable_value, CartridgeMemory, Mapper, MapperState};
+use crate::LoadError;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct State {
+    // 9 bits, split across two registers
+    rom_bank: u16,
+    ram_bank: u8,
+    is_ram_enabled: bool,
+    is_rumble_on: bool,
+    is_rumble_changed: bool,
+}
+
+pub struct Mbc5 {
+    memory: CartridgeMemory,
+    state: State,
+    has_rumble: bool,
+}
+
+impl Mbc5 {
+    pub fn new(memory: CartridgeMemory, has_rumble: bool) -> Mbc5 {
+        Mbc5 {
+            memory,
+            state: State {
+                rom_bank: 1,
+                ram_bank: 0,
+                is_ram_enabled: false,
+                is_rumble_on: false,
+                is_rumble_changed: false,
+            },
+            has_rumble,
+        }
+    }
+
+    fn set_rumble(&mut self, is_rumble_on: bool) {
+        if is_rumble_on != self.state.is_rumble_on {
+            self.state.is_rumble_on = is_rumble_on;
+            self.state.is_rumble_changed = true;
+        }
+    }
+}
+
+impl Mapper for Mbc5 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.state.rom_bank
+        };
+        self.memory.read_rom(bank as usize, address)
+    }
+
+    fn write_control(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.state.is_ram_enabled = is_ram_enable_value(value),
+            0x2000..=0x2FFF => self.state.rom_bank = (self.state.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.state.rom_bank = (self.state.rom_bank & 0xff) | ((value & 0b1) as u16) << 8;
+            }
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    // Bit 3 drives the rumble motor instead of selecting RAM
+                    self.state.ram_bank = value & 0b0111;
+                    self.set_rumble(value & 0b1000 == 0b1000);
+                } else {
+                    self.state.ram_bank = value & 0b1111;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.state.is_ram_enabled {
+            return 0xff;
+        }
+        self.memory.read_ram(self.state.ram_bank as usize, address)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.state.is_ram_enabled {
+            self.memory
+                .write_ram(self.state.ram_bank as usize, address, value);
+        }
+    }
+
+    fn memory(&self) -> &CartridgeMemory {
+        &self.memory
+    }
+
+    fn memory_mut(&mut self) -> &mut CartridgeMemory {
+        &mut self.memory
+    }
+
+    fn name(&self) -> &'static str {
+        "MBC5"
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Mbc5(self.state.clone())
+    }
+
+    fn load_state(&mut self, state: MapperState) -> Result<(), LoadError> {
+        match state {
+            MapperState::Mbc5(state) => {
+                self.state = state;
+                Ok(())
+            }
+            _ => Err(LoadError::MapperStateMismatch {
+                mapper: self.name(),
+            }),
+        }
+    }
+
+    fn debug_description(&self) -> String {
+        if !self.has_rumble {
+            return String::new();
+        }
+        let rumble = if self.state.is_rumble_on { "on" } else { "off" };
+        format!("Rumble {}", rumble)
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.state.rom_bank
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.state.ram_bank
+    }
+
+    fn is_ram_enabled(&self) -> bool {
+        self.state.is_ram_enabled
+    }
+
+    fn take_rumble_update(&mut self) -> Option<bool> {
+        if self.state.is_rumble_changed {
+            self.state.is_rumble_changed = false;
+            Some(self.state.is_rumble_on)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+    use crate::mapper;
+    use crate::CartridgeHeader;
+
+    const MBC5_RAM_BATTERY: u8 = 0x1b;
+    const MBC5_RUMBLE_RAM_BATTERY: u8 = 0x1e;
+
+    // Every bank starts with its own 9-bit number, 128KB of RAM
+    fn mbc5(cartridge_type: u8, rom_size_code: u8) -> Box<dyn Mapper> {
+        let mut rom = test_rom::build(cartridge_type, rom_size_code, 0x04);
+        for (bank, data) in rom.chunks_mut(0x4000).enumerate() {
+            data[..2].copy_from_slice(&(bank as u16).to_le_bytes());
+        }
+        mapper::from_header(&CartridgeHeader::parse(&rom).unwrap(), &rom).unwrap()
+    }
+
+    fn rom_bank_number(mapper: &dyn Mapper) -> u16 {
+        u16::from_le_bytes([mapper.read_rom(0x4000), mapper.read_rom(0x4001)])
+    }
+
+    #[test]
+    fn takes_bit_8_of_the_rom_bank_from_0x3000() {
+        // 8MB, all 512 banks
+        let mut mapper = mbc5(MBC5_RAM_BATTERY, 0x08);
+        mapper.write_control(0x2000, 0x23);
+        assert_eq!(rom_bank_number(mapper.as_ref()), 0x023);
+
+        mapper.write_control(0x3000, 0x01);
+        assert_eq!(rom_bank_number(mapper.as_ref()), 0x123);
+
+        // Only bit 0 of the upper register is connected
+        mapper.write_control(0x3000, 0xfe);
+        assert_eq!(rom_bank_number(mapper.as_ref()), 0x023);
+
+        mapper.write_control(0x3000, 0x01);
+        mapper.write_control(0x2fff, 0xff);
+        assert_eq!(rom_bank_number(mapper.as_ref()), 0x1ff);
+    }
+
+    #[test]
+    fn maps_bank_0_to_0x4000() {
+        let mut mapper = mbc5(MBC5_RAM_BATTERY, 0x02);
+        assert_eq!(rom_bank_number(mapper.as_ref()), 1);
+
+        mapper.write_control(0x2000, 0x00);
+        assert_eq!(rom_bank_number(mapper.as_ref()), 0);
+        assert_eq!(mapper.read_rom(0x0000), 0);
+    }
+
+    #[test]
+    fn switches_between_16_ram_banks() {
+        let mut mapper = mbc5(MBC5_RAM_BATTERY, 0x00);
+        mapper.write_control(0x0000, 0x0a);
+        for bank in 0..16 {
+            mapper.write_control(0x4000, bank);
+            mapper.write_ram(0xa000, 0x80 | bank);
+        }
+        for bank in 0..16 {
+            mapper.write_control(0x4000, bank);
+            assert_eq!(mapper.read_ram(0xa000), 0x80 | bank, "bank {}", bank);
+        }
+    }
+
+    #[test]
+    fn rumble_carts_use_ram_bank_bit_3_for_the_motor() {
+        let mut mapper = mbc5(MBC5_RUMBLE_RAM_BATTERY, 0x00);
+        mapper.write_control(0x0000, 0x0a);
+        mapper.write_control(0x4000, 0x03);
+        mapper.write_ram(0xa000, 0x33);
+        assert_eq!(mapper.take_rumble_update(), None);
+
+        mapper.write_control(0x4000, 0x0b);
+        assert_eq!(mapper.ram_bank(), 0x03);
+        assert_eq!(mapper.read_ram(0xa000), 0x33);
+        assert_eq!(mapper.take_rumble_update(), Some(true));
+        assert_eq!(mapper.take_rumble_update(), None);
+
+        mapper.write_control(0x4000, 0x03);
+        assert_eq!(mapper.take_rumble_update(), Some(false));
+
+        // Without a motor bit 3 is just part of the RAM bank
+        let mut mapper = mbc5(MBC5_RAM_BATTERY, 0x00);
+        mapper.write_control(0x4000, 0x0b);
+        assert_eq!(mapper.ram_bank(), 0x0b);
+        assert_eq!(mapper.take_rumble_update(), None);
+    }
+}
@@ -0,0 +1,257 @@
+//! MMM01, the multicart mapper. It starts out unmapped with the last 32KB of
+//! the ROM, holding the menu, at 0x0000-0x7FFF. The menu sets up the outer
+//! bank bits and masks for the chosen game and then maps it, which locks them
+//! until power off. From then on it mostly behaves like an MBC1.
+//!
+//! Multiplexing the ROM and RAM bank lines is not emulated, no known
+//! multicart relies on it.
+
+use super::{is_ram_enable_value, CartridgeMemory, Mapper, MapperState};
+use crate::{CartridgeHeader, LoadError};
+
+// The menu and its header are in the last 32KB of the ROM
+const MENU_SIZE: usize = 0x8000;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct State {
+    is_mapped: bool,
+    is_ram_enabled: bool,
+    // Bits 0-4, 5-6 and 7-8 of the ROM bank
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    // Bits of rom_bank_low the game can't change once mapped
+    rom_bank_mask: u8,
+    // Bits 0-1 and 2-3 of the RAM bank
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    // Bits of ram_bank_low the game can't change once mapped
+    ram_bank_mask: u8,
+    is_mbc1_mode: bool,
+    is_mbc1_mode_locked: bool,
+}
+
+pub struct Mmm01 {
+    memory: CartridgeMemory,
+    state: State,
+}
+
+/// The header of the menu, if the ROM is an MMM01 multicart. The header at
+/// 0x100 belongs to the first game and usually names its own mapper.
+pub fn menu_header(rom: &[u8]) -> Option<CartridgeHeader> {
+    let menu_start = rom.len().checked_sub(MENU_SIZE)?;
+    CartridgeHeader::parse(&rom[menu_start..])
+        .ok()
+        .filter(|header| (0x0b..=0x0d).contains(&header.cartridge_type()))
+}
+
+impl Mmm01 {
+    pub fn new(memory: CartridgeMemory) -> Mmm01 {
+        Mmm01 {
+            memory,
+            state: State {
+                is_mapped: false,
+                is_ram_enabled: false,
+                rom_bank_low: 0,
+                rom_bank_mid: 0,
+                rom_bank_high: 0,
+                rom_bank_mask: 0,
+                ram_bank_low: 0,
+                ram_bank_high: 0,
+                ram_bank_mask: 0,
+                is_mbc1_mode: false,
+                is_mbc1_mode_locked: false,
+            },
+        }
+    }
+
+    fn outer_rom_bank(&self) -> u16 {
+        (self.state.rom_bank_high as u16) << 7 | (self.state.rom_bank_mid as u16) << 5
+    }
+
+    // Bank 0 of the selected game, the masked bits stay as the menu left them
+    fn lower_rom_bank(&self) -> u16 {
+        if !self.state.is_mapped {
+            return 0x1fe;
+        }
+        self.outer_rom_bank() | (self.state.rom_bank_low & self.state.rom_bank_mask) as u16
+    }
+
+    // Writes to the masked bits are dropped once mapped
+    fn write_masked(current: u8, value: u8, mask: u8) -> u8 {
+        (current & mask) | (value & !mask)
+    }
+}
+
+impl Mapper for Mmm01 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            self.lower_rom_bank()
+        } else {
+            self.rom_bank()
+        };
+        self.memory.read_rom(bank as usize, address)
+    }
+
+    fn write_control(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => {
+                self.state.is_ram_enabled = is_ram_enable_value(value);
+                if !self.state.is_mapped {
+                    self.state.ram_bank_mask = (value >> 4) & 0b11;
+                    self.state.is_mapped = value & 0b01000000 != 0;
+                }
+            }
+            0x2000..=0x3FFF => {
+                if self.state.is_mapped {
+                    self.state.rom_bank_low = Mmm01::write_masked(
+                        self.state.rom_bank_low,
+                        value & 0b11111,
+                        self.state.rom_bank_mask,
+                    );
+                } else {
+                    self.state.rom_bank_low = value & 0b11111;
+                    self.state.rom_bank_mid = (value >> 5) & 0b11;
+                }
+            }
+            0x4000..=0x5FFF => {
+                if self.state.is_mapped {
+                    self.state.ram_bank_low = Mmm01::write_masked(
+                        self.state.ram_bank_low,
+                        value & 0b11,
+                        self.state.ram_bank_mask,
+                    );
+                } else {
+                    self.state.ram_bank_low = value & 0b11;
+                    self.state.ram_bank_high = (value >> 2) & 0b11;
+                    self.state.rom_bank_high = (value >> 4) & 0b11;
+                    self.state.is_mbc1_mode_locked = value & 0b01000000 != 0;
+                }
+            }
+            _ => {
+                if !self.state.is_mbc1_mode_locked {
+                    self.state.is_mbc1_mode = value & 0b1 != 0;
+                }
+                if !self.state.is_mapped {
+                    // Bits 2-5 mask bits 1-4 of the ROM bank
+                    self.state.rom_bank_mask = (value & 0b00111100) >> 1;
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.state.is_ram_enabled {
+            return 0xff;
+        }
+        self.memory.read_ram(self.ram_bank() as usize, address)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.state.is_ram_enabled {
+            self.memory
+                .write_ram(self.ram_bank() as usize, address, value);
+        }
+    }
+
+    fn memory(&self) -> &CartridgeMemory {
+        &self.memory
+    }
+
+    fn memory_mut(&mut self) -> &mut CartridgeMemory {
+        &mut self.memory
+    }
+
+    fn name(&self) -> &'static str {
+        "MMM01"
+    }
+
+    fn save_state(&self) -> MapperState {
+        MapperState::Mmm01(self.state.clone())
+    }
+
+    fn load_state(&mut self, state: MapperState) -> Result<(), LoadError> {
+        match state {
+            MapperState::Mmm01(state) => {
+                self.state = state;
+                Ok(())
+            }
+            _ => Err(LoadError::MapperStateMismatch {
+                mapper: self.name(),
+            }),
+        }
+    }
+
+    fn debug_description(&self) -> String {
+        if !self.state.is_mapped {
+            return "Unmapped, showing the menu".to_string();
+        }
+        format!(
+            "Mapped from bank {:#x}, ROM bank mask {:#04x}",
+            self.lower_rom_bank(),
+            self.state.rom_bank_mask
+        )
+    }
+
+    fn rom_bank(&self) -> u16 {
+        if !self.state.is_mapped {
+            return 0x1ff;
+        }
+
+        // Like the MBC1, only the unmasked bits all being 0 selects bank 1
+        let mut rom_bank_low = self.state.rom_bank_low;
+        if rom_bank_low & !self.state.rom_bank_mask & 0b11111 == 0 {
+            rom_bank_low |= 1;
+        }
+        self.outer_rom_bank() | rom_bank_low as u16
+    }
+
+    fn ram_bank(&self) -> u8 {
+        let ram_bank_low = if self.state.is_mbc1_mode || !self.state.is_mapped {
+            self.state.ram_bank_low
+        } else {
+            self.state.ram_bank_low & self.state.ram_bank_mask
+        };
+        self.state.ram_bank_high << 2 | ram_bank_low
+    }
+
+    fn is_ram_enabled(&self) -> bool {
+        self.state.is_ram_enabled
+    }
+
+    fn is_rom_banking_enabled(&self) -> bool {
+        !self.state.is_mbc1_mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom::write_header;
+    use crate::mapper;
+
+    #[test]
+    fn detects_the_menu_header_in_the_last_32kb() {
+        // A 64KB multicart whose first game says it's a 32KB MBC1 cartridge
+        let mut rom = vec![0; 0x10000];
+        write_header(&mut rom, 0x01, 0x00, 0x00);
+        write_header(&mut rom[MENU_SIZE..], 0x0b, 0x01, 0x00);
+        rom[MENU_SIZE] = 0xaa;
+
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        let mapper = mapper::from_header(&header, &rom).unwrap();
+        assert_eq!(mapper.name(), "MMM01");
+        // Unmapped, the menu is at 0x0000
+        assert_eq!(mapper.read_rom(0x0000), 0xaa);
+    }
+
+    #[test]
+    fn falls_back_to_the_header_at_0x100() {
+        let mut rom = vec![0; 0x8000];
+        write_header(&mut rom, 0x0b, 0x00, 0x00);
+        assert!(menu_header(&rom).is_some());
+
+        write_header(&mut rom, 0x01, 0x00, 0x00);
+        assert!(menu_header(&rom).is_none());
+    }
+}
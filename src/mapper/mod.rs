@@ -0,0 +1,388 @@
+//! Cartridge mappers. The bus hands every access to 0x0000-0x7FFF and
+//! 0xA000-0xBFFF to the mapper picked from the cartridge header, which owns
+//! the ROM, the cartridge RAM and whatever else is on the cartridge.
+
+mod camera;
+mod huc1;
+mod huc3;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod mmm01;
+mod no_mbc;
+mod rtc;
+
+pub use camera::{ImageSource, StaticImage, CAMERA_IMAGE_HEIGHT, CAMERA_IMAGE_WIDTH};
+
+use log::info;
+use wasm_bindgen::prelude::*;
+
+use crate::{CartridgeHeader, HeaderError, LoadError};
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+
+// Every mapper but MBC2 has RAM enabled by writing 0xA to the lower nibble
+// of its RAM enable register, anything else disables it
+fn is_ram_enable_value(value: u8) -> bool {
+    value & 0xF == 0xA
+}
+
+/// The registers of a mapper, kept in save states. The cartridge RAM goes in
+/// separately, in the .sav layout.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum MapperState {
+    NoMbc,
+    Mbc1(mbc1::State),
+    Mbc2(mbc2::State),
+    Mbc3(mbc3::State),
+    Mbc5(mbc5::State),
+    Mmm01(mmm01::State),
+    Huc1(huc1::State),
+    Huc3(huc3::State),
+    Camera(camera::State),
+}
+
+/// A snapshot of a mapper for the debugger.
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapperStatus {
+    name: String,
+    rom_bank: u16,
+    ram_bank: u8,
+    is_ram_enabled: bool,
+    is_rom_banking_enabled: bool,
+    description: String,
+}
+
+#[wasm_bindgen]
+impl MapperStatus {
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// The bank mapped to 0x4000-0x7FFF.
+    pub fn rom_bank(&self) -> u16 {
+        self.rom_bank
+    }
+
+    pub fn ram_bank(&self) -> u8 {
+        self.ram_bank
+    }
+
+    pub fn is_ram_enabled(&self) -> bool {
+        self.is_ram_enabled
+    }
+
+    pub fn is_rom_banking_enabled(&self) -> bool {
+        self.is_rom_banking_enabled
+    }
+
+    /// Whatever else is going on in this kind of mapper, like the selected
+    /// clock register. Empty if there's nothing more to it.
+    pub fn description(&self) -> String {
+        self.description.clone()
+    }
+}
+
+pub trait Mapper {
+    /// Reads from 0x0000-0x7FFF.
+    fn read_rom(&self, address: u16) -> u8;
+
+    /// Writes to 0x0000-0x7FFF, which land in the mapper's registers.
+    fn write_control(&mut self, address: u16, value: u8);
+
+    /// Reads from 0xA000-0xBFFF.
+    fn read_ram(&self, address: u16) -> u8;
+
+    /// Writes to 0xA000-0xBFFF.
+    fn write_ram(&mut self, address: u16, value: u8);
+
+    /// Writes to a bank of the cartridge RAM, whatever's mapped and whether
+    /// or not it's enabled.
+    fn poke_ram(&mut self, bank: u8, address: u16, value: u8) {
+        self.memory_mut().write_ram(bank as usize, address, value);
+    }
+
+    fn memory(&self) -> &CartridgeMemory;
+
+    fn memory_mut(&mut self) -> &mut CartridgeMemory;
+
+    fn name(&self) -> &'static str;
+
+    fn save_state(&self) -> MapperState;
+
+    /// Restores the registers from a save state, which has to be from the
+    /// same kind of mapper.
+    fn load_state(&mut self, state: MapperState) -> Result<(), LoadError>;
+
+    /// Advances anything on the cartridge that runs on its own, like a
+    /// clock, by `cycles` clock cycles.
+    fn tick(&mut self, _cycles: u8) {}
+
+    fn rom_bank(&self) -> u16 {
+        1
+    }
+
+    fn ram_bank(&self) -> u8 {
+        0
+    }
+
+    fn is_ram_enabled(&self) -> bool {
+        !self.memory().ram.is_empty()
+    }
+
+    fn is_rom_banking_enabled(&self) -> bool {
+        true
+    }
+
+    /// Mapper specific state for the debugger, beyond the banks.
+    fn debug_description(&self) -> String {
+        String::new()
+    }
+
+    fn status(&self) -> MapperStatus {
+        MapperStatus {
+            name: self.name().to_string(),
+            rom_bank: self.rom_bank(),
+            ram_bank: self.ram_bank(),
+            is_ram_enabled: self.is_ram_enabled(),
+            is_rom_banking_enabled: self.is_rom_banking_enabled(),
+            description: self.debug_description(),
+        }
+    }
+
+    /// Extra state, like a clock, written to .sav files after the RAM.
+    fn save_trailer(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// The trailer sizes `load_save_trailer` accepts, the first being the
+    /// one `save_trailer` writes.
+    fn save_trailer_sizes(&self) -> &'static [usize] {
+        &[0]
+    }
+
+    fn load_save_trailer(&mut self, _trailer: &[u8]) {}
+
+    /// The battery backed RAM in the common .sav layout, followed by the
+    /// mapper's trailer if it has one.
+    fn export_save(&self) -> Vec<u8> {
+        let mut save = self.memory().ram.clone();
+        save.extend(self.save_trailer());
+        save
+    }
+
+    fn import_save(&mut self, save: &[u8]) -> Result<(), LoadError> {
+        let ram_size = self.memory().ram.len();
+        let trailer_sizes = self.save_trailer_sizes();
+
+        if save.len() < ram_size || !trailer_sizes.contains(&(save.len() - ram_size)) {
+            return Err(LoadError::InvalidSaveSize {
+                expected: ram_size + trailer_sizes[0],
+                len: save.len(),
+            });
+        }
+
+        self.memory_mut().ram.copy_from_slice(&save[..ram_size]);
+        self.load_save_trailer(&save[ram_size..]);
+        self.memory_mut().is_save_dirty = false;
+        Ok(())
+    }
+
+    /// Switches a cartridge clock between counting emulated cycles and
+    /// following the host clock.
+    fn set_host_clock(&mut self, _is_host_clock: bool) {}
+
+    fn is_host_clock(&self) -> bool {
+        false
+    }
+
+    fn sync_host_time(&mut self, _unix_seconds: u64) {}
+
+    /// Whether a rumble motor is on, if it was switched on or off since the
+    /// last call.
+    fn take_rumble_update(&mut self) -> Option<bool> {
+        None
+    }
+
+    /// Whether a speaker on the cartridge beeped since the last call.
+    fn take_tone(&mut self) -> bool {
+        false
+    }
+
+    /// A write asking for something the mapper doesn't emulate, as the
+    /// address and value, if there was one since the last call.
+    fn take_unsupported_write(&mut self) -> Option<(u16, u8)> {
+        None
+    }
+
+    /// Where a camera on the cartridge gets its pictures from.
+    fn set_image_source(&mut self, _image_source: Box<dyn ImageSource>) {}
+}
+
+/// Picks the mapper for a cartridge from its header.
+pub fn from_header(header: &CartridgeHeader, rom: &[u8]) -> Result<Box<dyn Mapper>, HeaderError> {
+    let memory = |ram_size| CartridgeMemory::new(rom, header, ram_size);
+
+    if let Some(menu_header) = mmm01::menu_header(rom) {
+        let memory = CartridgeMemory::new(rom, &menu_header, menu_header.ram_size());
+        return Ok(Box::new(mmm01::Mmm01::new(memory)));
+    }
+
+    let mapper: Box<dyn Mapper> = match header.cartridge_type() {
+        0x00 | 0x08 | 0x09 => Box::new(no_mbc::NoMbc::new(memory(header.ram_size()))),
+        0x01..=0x03 => Box::new(mbc1::Mbc1::new(
+            memory(header.ram_size()),
+            crate::cartridge::is_mbc1_multicart(rom),
+        )),
+        0x05 | 0x06 => Box::new(mbc2::Mbc2::new(memory(mbc2::RAM_SIZE))),
+        0x0b..=0x0d => Box::new(mmm01::Mmm01::new(memory(header.ram_size()))),
+        0x0f..=0x13 => Box::new(mbc3::Mbc3::new(
+            memory(header.ram_size()),
+            header.has_timer(),
+        )),
+        0x19..=0x1e => Box::new(mbc5::Mbc5::new(
+            memory(header.ram_size()),
+            header.has_rumble(),
+        )),
+        0xfc => Box::new(camera::Camera::new(memory(camera::RAM_SIZE))),
+        0xfe => Box::new(huc3::Huc3::new(memory(header.ram_size()))),
+        0xff => Box::new(huc1::Huc1::new(memory(header.ram_size()))),
+        other => {
+            info!("Invalid mbc value: {:x} at $0x147", other);
+            return Err(HeaderError::UnsupportedCartridgeType(other));
+        }
+    };
+    Ok(mapper)
+}
+
+/// The ROM and RAM on a cartridge, addressed by bank.
+pub struct CartridgeMemory {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    has_battery: bool,
+    // Battery backed RAM or other saved state changed since the last save
+    is_save_dirty: bool,
+}
+
+impl CartridgeMemory {
+    /// Short dumps are padded to the ROM size the header declares, and
+    /// anything past it can't be banked in on real hardware either.
+    fn new(rom: &[u8], header: &CartridgeHeader, ram_size: usize) -> CartridgeMemory {
+        let mut rom = rom.to_vec();
+        rom.resize(header.rom_size(), 0xff);
+
+        CartridgeMemory {
+            rom,
+            ram: vec![0; ram_size],
+            has_battery: header.has_battery(),
+            is_save_dirty: false,
+        }
+    }
+
+    // Banks past the end of the ROM wrap around, the same as the unconnected
+    // upper bank bits would on a real cartridge
+    fn read_rom(&self, bank: usize, address: u16) -> u8 {
+        let bank_count = self.rom.len() / ROM_BANK_SIZE;
+        let bank = bank & (bank_count.next_power_of_two() - 1);
+        let address = bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1));
+        self.rom[address % self.rom.len()]
+    }
+
+    // RAM smaller than a bank, or fewer banks than selectable, is mirrored.
+    // Carts without any read as an open bus.
+    fn ram_address(&self, bank: usize, address: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+
+        let address = bank * RAM_BANK_SIZE + (address as usize & (RAM_BANK_SIZE - 1));
+        Some(address % self.ram.len())
+    }
+
+    fn read_ram(&self, bank: usize, address: u16) -> u8 {
+        match self.ram_address(bank, address) {
+            Some(ram_address) => self.ram[ram_address],
+            None => 0xff,
+        }
+    }
+
+    fn write_ram(&mut self, bank: usize, address: u16, value: u8) {
+        if let Some(ram_address) = self.ram_address(bank, address) {
+            self.ram[ram_address] = value;
+            self.mark_save_dirty();
+        }
+    }
+
+    fn mark_save_dirty(&mut self) {
+        self.is_save_dirty |= self.has_battery;
+    }
+
+    pub fn is_save_dirty(&self) -> bool {
+        self.is_save_dirty
+    }
+
+    pub fn clear_save_dirty(&mut self) {
+        self.is_save_dirty = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    // A dump of `bank_count` banks, each filled with its own number, for a
+    // header declaring `rom_size_code`
+    fn cartridge_memory(rom_size_code: u8, bank_count: usize, ram_size: usize) -> CartridgeMemory {
+        let mut rom = vec![0; bank_count * ROM_BANK_SIZE];
+        for (bank, data) in rom.chunks_mut(ROM_BANK_SIZE).enumerate() {
+            data.iter_mut().for_each(|byte| *byte = bank as u8);
+        }
+        test_rom::write_header(&mut rom, 0x00, rom_size_code, 0x00);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        CartridgeMemory::new(&rom, &header, ram_size)
+    }
+
+    #[test]
+    fn wraps_banks_past_the_end_of_the_rom() {
+        let memory = cartridge_memory(0x02, 8, 0);
+        assert_eq!(memory.read_rom(3, 0x4001), 3);
+        assert_eq!(memory.read_rom(8, 0x4001), 0);
+        assert_eq!(memory.read_rom(13, 0x7fff), 5);
+
+        // 72 banks, which the bank bits reach past before wrapping
+        let memory = cartridge_memory(0x52, 72, 0);
+        assert_eq!(memory.read_rom(71, 0x4001), 71);
+        assert_eq!(memory.read_rom(100, 0x4001), 28);
+        assert_eq!(memory.read_rom(128 + 5, 0x4001), 5);
+    }
+
+    #[test]
+    fn pads_short_dumps_to_the_header_rom_size() {
+        // 3 of the 4 banks the header declares
+        let memory = cartridge_memory(0x01, 3, 0);
+        assert_eq!(memory.read_rom(2, 0x4001), 2);
+        assert_eq!(memory.read_rom(3, 0x4001), 0xff);
+        assert_eq!(memory.read_rom(3, 0x7fff), 0xff);
+        assert_eq!(memory.read_rom(6, 0x4001), 2);
+    }
+
+    #[test]
+    fn mirrors_ram_smaller_than_a_bank() {
+        let mut memory = cartridge_memory(0x00, 2, 0x800);
+        memory.write_ram(0, 0xa123, 0x42);
+        for address in (0xa123..0xc000).step_by(0x800) {
+            assert_eq!(memory.read_ram(0, address), 0x42, "{:#06x}", address);
+            assert_eq!(memory.read_ram(1, address), 0x42, "{:#06x}", address);
+        }
+
+        memory.write_ram(3, 0xbfff, 0x24);
+        assert_eq!(memory.read_ram(0, 0xa7ff), 0x24);
+
+        let memory = cartridge_memory(0x00, 2, 0);
+        assert_eq!(memory.read_ram(0, 0xa000), 0xff);
+    }
+}
@@ -0,0 +1,57 @@
+//! Cartridges without a mapper: 32KB of ROM, and maybe 8KB of RAM that's
+//! always accessible.
+
+use super::{CartridgeMemory, Mapper, MapperState};
+use crate::LoadError;
+
+pub struct NoMbc {
+    memory: CartridgeMemory,
+}
+
+impl NoMbc {
+    pub fn new(memory: CartridgeMemory) -> NoMbc {
+        NoMbc { memory }
+    }
+}
+
+impl Mapper for NoMbc {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.memory.read_rom((address >> 14) as usize, address)
+    }
+
+    fn write_control(&mut self, _address: u16, _value: u8) {}
+
+    fn read_ram(&self, address: u16) -> u8 {
+        self.memory.read_ram(0, address)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        self.memory.write_ram(0, address, value);
+    }
+
+    fn memory(&self) -> &CartridgeMemory {
+        &self.memory
+    }
+
+    fn memory_mut(&mut self) -> &mut CartridgeMemory {
+        &mut self.memory
+    }
+
+    fn name(&self) -> &'static str {
+        "ROM ONLY"
+    }
+
+    // Nothing to keep besides the RAM
+    fn save_state(&self) -> MapperState {
+        MapperState::NoMbc
+    }
+
+    fn load_state(&mut self, state: MapperState) -> Result<(), LoadError> {
+        match state {
+            MapperState::NoMbc => Ok(()),
+            _ => Err(LoadError::MapperStateMismatch {
+                mapper: self.name(),
+            }),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! The MBC3 real-time clock. Games select one of the clock registers through
+//! the RAM bank register and read and write it in place of cartridge RAM.
+//! Reads see a latched copy, taken by writing 0 then 1 to 0x6000-0x7FFF.
+//!
+//! The clock either counts emulated cycles, so it runs at whatever speed the
+//! emulation does, or follows the host clock passed in by the frontend.
+
+pub const SECONDS: u8 = 0x08;
+pub const MINUTES: u8 = 0x09;
+pub const HOURS: u8 = 0x0a;
+pub const DAY_LOW: u8 = 0x0b;
+pub const DAY_HIGH: u8 = 0x0c;
+
+const CYCLES_PER_SECOND: u32 = 4_194_304;
+
+// The clock is appended to .sav files as the live and latched registers, each
+// as a little endian u32, followed by the host time they were saved at as a
+// u64. Some emulators write that last one as a u32.
+pub const SAVE_SIZE: usize = 48;
+pub const SHORT_SAVE_SIZE: usize = 44;
+
+// Bits of the day high register
+const DAY_BIT_8: u8 = 0b00000001;
+const HALT: u8 = 0b01000000;
+const DAY_CARRY: u8 = 0b10000000;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    // 9 bit day counter
+    days: u16,
+    is_halted: bool,
+    is_day_carry: bool,
+    // Seconds, minutes, hours, day low and day high as of the last latch
+    latched: [u8; 5],
+    // The last value written to the latch register, the latch happens on a
+    // 0 to 1 change
+    latch_write: u8,
+    // Cycles counted towards the next second
+    cycle_num: u32,
+    is_host_clock: bool,
+    // The host time the clock was last brought up to date with, in seconds
+    host_time: Option<u64>,
+}
+
+impl Rtc {
+    /// Advances the clock by `cycles` emulated clock cycles, unless it
+    /// follows the host clock instead.
+    pub fn tick(&mut self, cycles: u8) {
+        if self.is_host_clock || self.is_halted {
+            return;
+        }
+
+        self.cycle_num += cycles as u32;
+        while self.cycle_num >= CYCLES_PER_SECOND {
+            self.cycle_num -= CYCLES_PER_SECOND;
+            self.add_seconds(1);
+        }
+    }
+
+    pub fn set_host_clock(&mut self, is_host_clock: bool) {
+        self.is_host_clock = is_host_clock;
+        self.host_time = None;
+    }
+
+    pub fn is_host_clock(&self) -> bool {
+        self.is_host_clock
+    }
+
+    /// Advances the clock by however many whole seconds the host clock has
+    /// moved on since the last call. The first call only records the time.
+    pub fn sync_host_time(&mut self, unix_seconds: u64) {
+        if !self.is_host_clock {
+            return;
+        }
+
+        if let Some(host_time) = self.host_time {
+            if !self.is_halted {
+                self.add_seconds(unix_seconds.saturating_sub(host_time));
+            }
+        }
+        self.host_time = Some(unix_seconds);
+    }
+
+    /// The clock in the layout appended to .sav files.
+    pub fn to_save(&self) -> Vec<u8> {
+        let registers = [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            self.day_high(),
+        ];
+
+        let mut save = Vec::with_capacity(SAVE_SIZE);
+        for register in registers.iter().chain(self.latched.iter()) {
+            save.extend_from_slice(&(*register as u32).to_le_bytes());
+        }
+        save.extend_from_slice(&self.host_time.unwrap_or(0).to_le_bytes());
+        save
+    }
+
+    /// Restores the clock from a .sav trailer of `SAVE_SIZE` or
+    /// `SHORT_SAVE_SIZE` bytes. When following the host clock, the time that
+    /// passed since it was saved is caught up on the next sync.
+    pub fn load_save(&mut self, save: &[u8]) {
+        let register = |idx: usize| save[idx * 4];
+
+        for (idx, address) in (SECONDS..=DAY_HIGH).enumerate() {
+            self.write(address, register(idx));
+        }
+        for idx in 0..self.latched.len() {
+            self.latched[idx] = register(idx + 5);
+        }
+
+        let mut host_time = [0; 8];
+        let time_bytes = &save[40..];
+        host_time[..time_bytes.len()].copy_from_slice(time_bytes);
+        let host_time = u64::from_le_bytes(host_time);
+        if self.is_host_clock && host_time != 0 {
+            self.host_time = Some(host_time);
+        }
+    }
+
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_write == 0 && value == 1 {
+            self.latched = [
+                self.seconds,
+                self.minutes,
+                self.hours,
+                self.days as u8,
+                self.day_high(),
+            ];
+        }
+        self.latch_write = value;
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            SECONDS..=DAY_HIGH => self.latched[(register - SECONDS) as usize],
+            _ => 0xff,
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            SECONDS => {
+                self.seconds = value & 0b00111111;
+                // Writing the seconds restarts the current second
+                self.cycle_num = 0;
+            }
+            MINUTES => self.minutes = value & 0b00111111,
+            HOURS => self.hours = value & 0b00011111,
+            DAY_LOW => self.days = (self.days & 0x100) | value as u16,
+            DAY_HIGH => {
+                self.days = (self.days & 0xff) | ((value & DAY_BIT_8) as u16) << 8;
+                self.is_halted = value & HALT == HALT;
+                self.is_day_carry = value & DAY_CARRY == DAY_CARRY;
+            }
+            _ => {}
+        }
+    }
+
+    fn day_high(&self) -> u8 {
+        let mut day_high = (self.days >> 8) as u8 & DAY_BIT_8;
+        if self.is_halted {
+            day_high |= HALT;
+        }
+        if self.is_day_carry {
+            day_high |= DAY_CARRY;
+        }
+        day_high
+    }
+
+    fn add_seconds(&mut self, seconds: u64) {
+        let minutes = add_to_register(&mut self.seconds, seconds, 60, 0b00111111);
+        let hours = add_to_register(&mut self.minutes, minutes, 60, 0b00111111);
+        let days = add_to_register(&mut self.hours, hours, 24, 0b00011111);
+
+        let days = self.days as u64 + days;
+        if days >= 0x200 {
+            self.is_day_carry = true;
+        }
+        self.days = (days % 0x200) as u16;
+    }
+}
+
+// Adds `amount` to a register that wraps at `limit` and returns how many
+// times it carried into the next one. Out of range values, which can only be
+// written by the game, count up to the top of their bits and wrap to 0
+// without carrying.
+fn add_to_register(register: &mut u8, amount: u64, limit: u8, mask: u8) -> u64 {
+    let mut amount = amount;
+    if *register >= limit {
+        let to_wrap = (mask - *register) as u64 + 1;
+        if amount < to_wrap {
+            *register += amount as u8;
+            return 0;
+        }
+        amount -= to_wrap;
+        *register = 0;
+    }
+
+    let total = *register as u64 + amount;
+    *register = (total % limit as u64) as u8;
+    total / limit as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(seconds: u8, minutes: u8, hours: u8, days: u16) -> Rtc {
+        let mut rtc = Rtc::default();
+        rtc.write(SECONDS, seconds);
+        rtc.write(MINUTES, minutes);
+        rtc.write(HOURS, hours);
+        rtc.write(DAY_LOW, days as u8);
+        rtc.write(DAY_HIGH, (days >> 8) as u8);
+        rtc
+    }
+
+    fn latched(rtc: &mut Rtc) -> [u8; 5] {
+        rtc.write_latch(0);
+        rtc.write_latch(1);
+        [
+            rtc.read(SECONDS),
+            rtc.read(MINUTES),
+            rtc.read(HOURS),
+            rtc.read(DAY_LOW),
+            rtc.read(DAY_HIGH),
+        ]
+    }
+
+    fn host_clock(rtc: &mut Rtc, elapsed: u64) {
+        rtc.set_host_clock(true);
+        rtc.sync_host_time(1_000_000);
+        rtc.sync_host_time(1_000_000 + elapsed);
+    }
+
+    #[test]
+    fn reads_only_change_on_a_latch() {
+        let mut rtc = clock(10, 0, 0, 0);
+        rtc.write_latch(0);
+        rtc.write_latch(1);
+        rtc.write(SECONDS, 20);
+        assert_eq!(rtc.read(SECONDS), 10);
+
+        // Writing 1 again without a 0 first doesn't latch
+        rtc.write_latch(1);
+        assert_eq!(rtc.read(SECONDS), 10);
+
+        rtc.write_latch(0);
+        rtc.write_latch(1);
+        assert_eq!(rtc.read(SECONDS), 20);
+    }
+
+    #[test]
+    fn counts_emulated_seconds() {
+        let mut rtc = clock(59, 59, 0, 0);
+        for _ in 0..CYCLES_PER_SECOND / 128 {
+            rtc.tick(128);
+        }
+        assert_eq!(latched(&mut rtc), [0, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn catches_up_with_the_host_clock() {
+        let mut rtc = clock(0, 0, 0, 0);
+        // A day, an hour, a minute and a second
+        host_clock(&mut rtc, 86400 + 3600 + 60 + 1);
+        assert_eq!(latched(&mut rtc), [1, 1, 1, 1, 0]);
+
+        let mut rtc = clock(30, 59, 23, 0xff);
+        host_clock(&mut rtc, 30);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0x00, DAY_BIT_8]);
+    }
+
+    #[test]
+    fn stops_while_halted() {
+        let mut rtc = clock(0, 0, 0, 0);
+        rtc.write(DAY_HIGH, HALT);
+        for _ in 0..CYCLES_PER_SECOND / 128 {
+            rtc.tick(128);
+        }
+        host_clock(&mut rtc, 1000);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, HALT]);
+
+        // The time spent halted isn't caught up on after restarting
+        rtc.write(DAY_HIGH, 0);
+        rtc.sync_host_time(1_000_000 + 1005);
+        assert_eq!(latched(&mut rtc), [5, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn sets_the_day_carry_when_the_day_counter_overflows() {
+        let mut rtc = clock(59, 59, 23, 0x1ff);
+        host_clock(&mut rtc, 1);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, DAY_CARRY]);
+
+        // It stays set until the game clears it
+        host_clock(&mut rtc, 86400);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 1, DAY_CARRY]);
+        rtc.write(DAY_HIGH, 0);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 1, 0]);
+
+        // Past several overflows the counter wraps all the same
+        let mut rtc = clock(0, 0, 0, 0);
+        host_clock(&mut rtc, 86400 * (0x200 * 3 + 5));
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 5, DAY_CARRY]);
+    }
+
+    #[test]
+    fn out_of_range_registers_wrap_without_carrying() {
+        let mut rtc = clock(62, 0, 0, 0);
+        host_clock(&mut rtc, 3);
+        assert_eq!(latched(&mut rtc), [1, 0, 0, 0, 0]);
+
+        let mut rtc = clock(59, 59, 30, 0);
+        host_clock(&mut rtc, 1);
+        assert_eq!(latched(&mut rtc), [0, 0, 31, 0, 0]);
+        host_clock(&mut rtc, 3600);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,65 @@
+//! Which console is being emulated. Only monochrome games are supported, so
+//! a Game Boy Color always runs in its DMG compatibility mode.
+
+use std::fmt;
+use std::str::FromStr;
+
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Model {
+    // The original Game Boy
+    #[default]
+    Dmg,
+    // Game Boy Pocket and Light
+    Mgb,
+    // Super Game Boy
+    Sgb,
+    // Game Boy Color in DMG mode
+    Cgb,
+}
+
+impl Model {
+    /// Size of the boot ROM this model maps in at power on. The CGB one is
+    /// mapped over 0x0000-0x00FF and 0x0200-0x08FF, leaving the cartridge
+    /// header visible in between.
+    pub fn boot_rom_size(self) -> usize {
+        match self {
+            Model::Dmg | Model::Mgb | Model::Sgb => 0x100,
+            Model::Cgb => 0x900,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Model::Dmg => "DMG",
+            Model::Mgb => "MGB",
+            Model::Sgb => "SGB",
+            Model::Cgb => "CGB",
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Model, String> {
+        match name.to_lowercase().as_str() {
+            "dmg" => Ok(Model::Dmg),
+            "mgb" => Ok(Model::Mgb),
+            "sgb" => Ok(Model::Sgb),
+            "cgb" => Ok(Model::Cgb),
+            _ => Err(format!(
+                "Unknown model {}, expected dmg, mgb, sgb or cgb",
+                name
+            )),
+        }
+    }
+}
@@ -0,0 +1,896 @@
+//! The picture processing unit. Each visible line is drawn into the screen
+//! buffer at the end of its pixel transfer, with the registers as they are at
+//! that point, so games changing the scroll or LCDC between lines (raster
+//! effects) show up like on hardware. The buffer holds a shade from 0 (white)
+//! to 3 (black) per pixel, with the palette it went through above it, and is
+//! handed over as a finished frame at vblank.
+//!
+//! The PPU steps a machine cycle (4 dots) at a time through the modes of
+//! each line: OAM scan for 80 dots, a pixel transfer that gets longer with
+//! the fine scroll, the window and sprites, then HBlank until the line's 456
+//! dots are up. Lines 144 to 153 are VBlank. The STAT interrupt is requested
+//! when any of its enabled sources turns the shared STAT line on, so a
+//! source can't fire while another one is keeping the line up.
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+pub const SCREEN_PIXEL_NUM: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+
+// The IF bits the PPU requests
+pub const VBLANK_INTERRUPT: u8 = 0b00000001;
+pub const STAT_INTERRUPT: u8 = 0b00000010;
+
+const DOTS_PER_M_CYCLE: u16 = 4;
+const DOTS_PER_LINE: u16 = 456;
+const OAM_SCAN_DOTS: u16 = 80;
+// The shortest pixel transfer, with no fine scroll, window or sprites
+const PIXEL_TRANSFER_DOTS: u16 = 172;
+const WINDOW_PENALTY_DOTS: u16 = 6;
+const SPRITE_PENALTY_DOTS: u16 = 6;
+const VBLANK_START: u8 = 144;
+const LAST_LINE: u8 = 153;
+
+const LCDC: usize = 0xff40;
+const STAT: usize = 0xff41;
+const SCY: usize = 0xff42;
+const SCX: usize = 0xff43;
+const LY: usize = 0xff44;
+const LYC: usize = 0xff45;
+const BGP: usize = 0xff47;
+const OBP0: usize = 0xff48;
+const OBP1: usize = 0xff49;
+const WY: usize = 0xff4a;
+const WX: usize = 0xff4b;
+
+const OAM_START: usize = 0xfe00;
+const OAM_SPRITE_NUM: usize = 40;
+const BYTES_PER_SPRITE: usize = 4;
+// The OAM scan picks at most this many sprites for a line
+const SPRITES_PER_LINE: usize = 10;
+
+// Bits of LCDC
+const LCD_ENABLE: u8 = 0b10000000;
+const BG_ENABLE: u8 = 0b00000001;
+const OBJ_ENABLE: u8 = 0b00000010;
+const OBJ_SIZE: u8 = 0b00000100;
+const BG_TILE_MAP: u8 = 0b00001000;
+const TILE_DATA: u8 = 0b00010000;
+const WINDOW_ENABLE: u8 = 0b00100000;
+const WINDOW_TILE_MAP: u8 = 0b01000000;
+
+// Bits of STAT. The mode and coincidence flag are read only and bit 7
+// always reads 1.
+const STAT_UNUSED: u8 = 0b10000000;
+const LYC_INTERRUPT: u8 = 0b01000000;
+const OAM_INTERRUPT: u8 = 0b00100000;
+const VBLANK_STAT_INTERRUPT: u8 = 0b00010000;
+const HBLANK_INTERRUPT: u8 = 0b00001000;
+const STAT_WRITABLE: u8 = 0b01111000;
+const COINCIDENCE_FLAG: u8 = 0b00000100;
+
+// WX is the window's left edge plus 7
+const WINDOW_X_OFFSET: isize = 7;
+
+// The palette bits of a frame pixel, the background's being 0
+const OBP0_PIXEL: u8 = 1 << 2;
+const OBP1_PIXEL: u8 = 2 << 2;
+
+// Bits of a sprite's attributes
+const BEHIND_BG: u8 = 0b10000000;
+const Y_FLIP: u8 = 0b01000000;
+const X_FLIP: u8 = 0b00100000;
+const PALETTE: u8 = 0b00010000;
+
+const BYTES_PER_TILE: usize = 16;
+const TILE_MAP_WIDTH: usize = 32;
+
+/// An OAM entry. The position is the sprite's top left corner on screen,
+/// which is negative while it's partly off the top or left.
+#[allow(dead_code)]
+pub struct Sprite {
+    pub y: i16,
+    pub x: i16,
+    pub pattern_num: u8,
+    pub attributes: u8,
+    pub priority: bool,
+    pub y_flip: bool,
+    pub x_flip: bool,
+    pub palette_num: bool,
+}
+
+impl Sprite {
+    pub fn from_oam(entry: &[u8]) -> Sprite {
+        let attributes = entry[3];
+        Sprite {
+            // OAM positions are offset so sprites can be partly off screen
+            y: entry[0] as i16 - 16,
+            x: entry[1] as i16 - 8,
+            pattern_num: entry[2],
+            attributes,
+            priority: attributes & BEHIND_BG == BEHIND_BG,
+            y_flip: attributes & Y_FLIP == Y_FLIP,
+            x_flip: attributes & X_FLIP == X_FLIP,
+            palette_num: attributes & PALETTE == PALETTE,
+        }
+    }
+
+    /// The address of the row'th line of the sprite's tile data, flips
+    /// applied, for sprites `height` lines tall. 8x16 sprites use an even and
+    /// odd tile pair, whatever bit 0 of the tile number is.
+    pub fn row_address(&self, row: usize, height: usize) -> usize {
+        let row = if self.y_flip { height - 1 - row } else { row };
+        let tile_num = if height == 16 {
+            self.pattern_num & 0xfe
+        } else {
+            self.pattern_num
+        };
+        0x8000 + tile_num as usize * BYTES_PER_TILE + row * 2
+    }
+
+    /// The shade of one of the sprite's colour numbers, through OBP0 or OBP1
+    /// as its attributes pick.
+    pub fn shade(&self, memory: &[u8], color: u8) -> u8 {
+        let palette = if self.palette_num { OBP1 } else { OBP0 };
+        palette_shade(memory[palette], color)
+    }
+
+    // A frame pixel for one of the sprite's colour numbers
+    fn frame_pixel(&self, memory: &[u8], color: u8) -> u8 {
+        let palette_bits = if self.palette_num {
+            OBP1_PIXEL
+        } else {
+            OBP0_PIXEL
+        };
+        palette_bits | self.shade(memory, color)
+    }
+
+    /// The colour number of pixel `col` of the line at `row_address`.
+    pub fn pixel(&self, memory: &[u8], row_address: usize, col: usize) -> u8 {
+        let col = if self.x_flip { 7 - col } else { col };
+        tile_pixel(memory, row_address, 0, col)
+    }
+}
+
+/// The shade, 0 (white) to 3 (black), a palette register maps colour
+/// number `color` to. Each colour takes two bits, colour 0 the lowest.
+pub fn palette_shade(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0b11
+}
+
+pub fn bg_shade(memory: &[u8], color: u8) -> u8 {
+    palette_shade(memory[BGP], color)
+}
+
+/// How tall sprites are with the size LCDC selects.
+pub fn sprite_height(lcdc: u8) -> usize {
+    if lcdc & OBJ_SIZE == OBJ_SIZE {
+        16
+    } else {
+        8
+    }
+}
+
+pub fn all_sprites(memory: &[u8]) -> Vec<Sprite> {
+    memory[OAM_START..OAM_START + OAM_SPRITE_NUM * BYTES_PER_SPRITE]
+        .chunks(BYTES_PER_SPRITE)
+        .map(Sprite::from_oam)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    PixelTransfer = 3,
+}
+
+// Everything about the PPU that isn't in memory, for save states
+#[derive(Clone, Serialize, Deserialize)]
+pub struct State {
+    is_lcd_on: bool,
+    mode: Mode,
+    // The line being drawn, which LY doesn't show for most of line 153
+    ly: u8,
+    // Dots into the line
+    line_cycle: u16,
+    pixel_transfer_end: u16,
+    // Whether any enabled STAT source is on
+    stat_line: bool,
+    // The window's own line counter, only advanced on lines it's drawn on
+    window_line: usize,
+}
+
+impl Default for State {
+    fn default() -> State {
+        State {
+            is_lcd_on: false,
+            mode: Mode::HBlank,
+            ly: 0,
+            line_cycle: 0,
+            pixel_transfer_end: OAM_SCAN_DOTS + PIXEL_TRANSFER_DOTS,
+            stat_line: false,
+            window_line: 0,
+        }
+    }
+}
+
+impl State {
+    /// The state of a PPU `line_cycle` dots into the line LY shows, for save
+    /// states from before the PPU had its own.
+    pub fn resume(memory: &[u8], line_cycle: u16) -> State {
+        let ly = memory[LY];
+        let line_cycle = line_cycle - line_cycle % DOTS_PER_M_CYCLE;
+        let pixel_transfer_end = OAM_SCAN_DOTS + PIXEL_TRANSFER_DOTS;
+        let mode = if ly >= VBLANK_START {
+            Mode::VBlank
+        } else if line_cycle < OAM_SCAN_DOTS {
+            Mode::OamScan
+        } else if line_cycle < pixel_transfer_end {
+            Mode::PixelTransfer
+        } else {
+            Mode::HBlank
+        };
+
+        State {
+            is_lcd_on: memory[LCDC] & LCD_ENABLE == LCD_ENABLE,
+            mode,
+            ly,
+            line_cycle: line_cycle.min(DOTS_PER_LINE - DOTS_PER_M_CYCLE),
+            pixel_transfer_end,
+            stat_line: false,
+            window_line: 0,
+        }
+    }
+}
+
+pub struct Ppu {
+    // The frame being drawn
+    screen: Box<[u8; SCREEN_PIXEL_NUM]>,
+    // The last finished frame
+    frame: Box<[u8; SCREEN_PIXEL_NUM]>,
+    state: State,
+}
+
+impl Default for Ppu {
+    fn default() -> Ppu {
+        Ppu {
+            screen: Box::new([0; SCREEN_PIXEL_NUM]),
+            frame: Box::new([0; SCREEN_PIXEL_NUM]),
+            state: State::default(),
+        }
+    }
+}
+
+impl Ppu {
+    pub fn save_state(&self) -> State {
+        self.state.clone()
+    }
+
+    pub fn load_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.state.mode
+    }
+
+    pub fn line_cycle(&self) -> u16 {
+        self.state.line_cycle
+    }
+
+    /// Runs the PPU for `cycles` clock cycles, following LCDC bit 7 turning
+    /// the LCD on and off, and returns the interrupts it requests as IF bits.
+    pub fn tick(&mut self, memory: &mut [u8], cycles: u8) -> u8 {
+        let is_lcd_on = memory[LCDC] & LCD_ENABLE == LCD_ENABLE;
+        if is_lcd_on != self.state.is_lcd_on {
+            if is_lcd_on {
+                self.turn_on(memory);
+            } else {
+                self.turn_off(memory);
+            }
+        }
+        if !is_lcd_on {
+            return 0;
+        }
+
+        let mut interrupts = 0;
+        for _ in 0..cycles as u16 / DOTS_PER_M_CYCLE {
+            interrupts |= self.step(memory);
+        }
+        interrupts
+    }
+
+    // LY and the mode stay at 0 while the LCD is off
+    fn turn_off(&mut self, memory: &mut [u8]) {
+        self.state = State::default();
+        memory[LY] = 0;
+        memory[STAT] &= !(COINCIDENCE_FLAG | 0b11);
+        memory[STAT] |= STAT_UNUSED;
+    }
+
+    // The first line after the LCD comes on skips the OAM scan, staying in
+    // HBlank until the pixel transfer starts
+    fn turn_on(&mut self, memory: &mut [u8]) {
+        self.state = State {
+            is_lcd_on: true,
+            ..State::default()
+        };
+        memory[LY] = 0;
+        self.update_stat(memory);
+    }
+
+    // Advances by one machine cycle
+    fn step(&mut self, memory: &mut [u8]) -> u8 {
+        let mut interrupts = 0;
+        self.state.line_cycle += DOTS_PER_M_CYCLE;
+
+        if self.state.line_cycle >= DOTS_PER_LINE {
+            self.state.line_cycle = 0;
+            self.state.ly = if self.state.ly == LAST_LINE {
+                0
+            } else {
+                self.state.ly + 1
+            };
+            memory[LY] = self.state.ly;
+
+            if self.state.ly == VBLANK_START {
+                self.state.mode = Mode::VBlank;
+                self.finish_frame();
+                interrupts |= VBLANK_INTERRUPT;
+            } else if self.state.ly < VBLANK_START {
+                self.state.mode = Mode::OamScan;
+            }
+        } else if self.state.ly < VBLANK_START {
+            if self.state.line_cycle == OAM_SCAN_DOTS {
+                self.state.mode = Mode::PixelTransfer;
+                self.state.pixel_transfer_end =
+                    OAM_SCAN_DOTS + pixel_transfer_length(memory, self.state.ly as usize);
+            } else if self.state.mode == Mode::PixelTransfer
+                && self.state.line_cycle >= self.state.pixel_transfer_end
+            {
+                self.render_line(memory, self.state.ly);
+                self.state.mode = Mode::HBlank;
+            }
+        } else if self.state.ly == LAST_LINE && self.state.line_cycle == DOTS_PER_M_CYCLE {
+            // LY goes back to 0 a machine cycle into the last line, so LYC 0
+            // matches for the rest of it
+            memory[LY] = 0;
+        }
+
+        if self.update_stat(memory) {
+            interrupts |= STAT_INTERRUPT;
+        }
+        interrupts
+    }
+
+    /// Brings STAT's mode and coincidence flag up to date and works out the
+    /// STAT line, returning whether it went up. Also called when STAT or LYC
+    /// is written, since that can turn the line on by itself.
+    pub fn update_stat(&mut self, memory: &mut [u8]) -> bool {
+        if !self.state.is_lcd_on {
+            return false;
+        }
+
+        let is_coincidence = memory[LY] == memory[LYC];
+        let stat = memory[STAT] & STAT_WRITABLE;
+        memory[STAT] = STAT_UNUSED
+            | stat
+            | if is_coincidence { COINCIDENCE_FLAG } else { 0 }
+            | self.state.mode as u8;
+
+        let mode = self.state.mode;
+        // The OAM source also fires as VBlank starts
+        let is_oam_scan =
+            mode == Mode::OamScan || (self.state.ly == VBLANK_START && self.state.line_cycle == 0);
+        let stat_line = (mode == Mode::HBlank && stat & HBLANK_INTERRUPT != 0)
+            || (mode == Mode::VBlank && stat & VBLANK_STAT_INTERRUPT != 0)
+            || (is_oam_scan && stat & OAM_INTERRUPT != 0)
+            || (is_coincidence && stat & LYC_INTERRUPT != 0);
+
+        let is_rising = stat_line && !self.state.stat_line;
+        self.state.stat_line = stat_line;
+        is_rising
+    }
+
+    // Draws line `ly` from VRAM, OAM and the LCD registers in `memory`
+    fn render_line(&mut self, memory: &[u8], ly: u8) {
+        let ly = ly as usize;
+        if ly >= SCREEN_HEIGHT {
+            return;
+        }
+
+        // Sprites need the background's colour numbers for their priority
+        let mut bg_line = [0; SCREEN_WIDTH];
+        render_background_line(memory, ly, &mut bg_line);
+        if render_window_line(memory, ly, self.state.window_line, &mut bg_line) {
+            self.state.window_line += 1;
+        }
+
+        // A disabled background is blank white whatever BGP maps colour 0
+        // to, but still counts as colour 0 for sprite priority
+        let is_bg_enabled = memory[LCDC] & BG_ENABLE == BG_ENABLE;
+        let line = &mut self.screen[ly * SCREEN_WIDTH..(ly + 1) * SCREEN_WIDTH];
+        for (pixel, color) in line.iter_mut().zip(bg_line.iter()) {
+            *pixel = if is_bg_enabled {
+                bg_shade(memory, *color)
+            } else {
+                0
+            };
+        }
+        render_sprite_line(memory, ly, &bg_line, line);
+    }
+
+    // Hands the screen over as the finished frame, at the start of vblank
+    fn finish_frame(&mut self) {
+        self.frame.copy_from_slice(&self.screen[..]);
+        self.state.window_line = 0;
+    }
+
+    pub fn frame(&self) -> &[u8] {
+        &self.frame[..]
+    }
+}
+
+// The colour number (0-3) of a pixel of the tile whose data starts at
+// `tile_address`
+fn tile_pixel(memory: &[u8], tile_address: usize, row: usize, col: usize) -> u8 {
+    let low = memory[tile_address + row * 2];
+    let high = memory[tile_address + row * 2 + 1];
+    let bit = 7 - col;
+    ((high >> bit) & 0b1) << 1 | (low >> bit) & 0b1
+}
+
+// With LCDC bit 4 clear, BG tiles are numbered -128 to 127 around 0x9000
+fn bg_tile_address(lcdc: u8, tile_num: u8) -> usize {
+    if lcdc & TILE_DATA == TILE_DATA {
+        0x8000 + tile_num as usize * BYTES_PER_TILE
+    } else {
+        (0x9000 + (tile_num as i8) as isize * BYTES_PER_TILE as isize) as usize
+    }
+}
+
+fn tile_map(lcdc: u8, map_select: u8) -> usize {
+    if lcdc & map_select == map_select {
+        0x9c00
+    } else {
+        0x9800
+    }
+}
+
+fn render_background_line(memory: &[u8], ly: usize, line: &mut [u8]) {
+    let lcdc = memory[LCDC];
+    if lcdc & BG_ENABLE == 0 {
+        line.iter_mut().for_each(|pixel| *pixel = 0);
+        return;
+    }
+
+    let tile_map = tile_map(lcdc, BG_TILE_MAP);
+    // The background is 256x256 and wraps around in both directions
+    let y = (ly as u8).wrapping_add(memory[SCY]) as usize;
+
+    for (x, pixel) in line.iter_mut().enumerate() {
+        let bg_x = (x as u8).wrapping_add(memory[SCX]) as usize;
+        let tile_num = memory[tile_map + (y / 8) * TILE_MAP_WIDTH + bg_x / 8];
+        *pixel = tile_pixel(memory, bg_tile_address(lcdc, tile_num), y % 8, bg_x % 8);
+    }
+}
+
+fn is_window_on_line(memory: &[u8], ly: usize) -> bool {
+    let lcdc = memory[LCDC];
+    let window_x = memory[WX] as isize - WINDOW_X_OFFSET;
+    lcdc & WINDOW_ENABLE != 0
+        && lcdc & BG_ENABLE != 0
+        && ly >= memory[WY] as usize
+        && window_x < SCREEN_WIDTH as isize
+}
+
+// How many dots the pixel transfer of line `ly` takes. The fine scroll
+// discards pixels at the start of the line, fetching the window restarts the
+// background fetcher and each sprite stalls it for 6 to 11 dots, depending
+// on where it sits in the background tile it's over.
+fn pixel_transfer_length(memory: &[u8], ly: usize) -> u16 {
+    let scx = memory[SCX] as u16;
+    let mut length = PIXEL_TRANSFER_DOTS + scx % 8;
+    if is_window_on_line(memory, ly) {
+        length += WINDOW_PENALTY_DOTS;
+    }
+
+    if memory[LCDC] & OBJ_ENABLE == OBJ_ENABLE {
+        for sprite in line_sprites(memory, ly) {
+            // Sprites past the right edge don't stall the fetcher
+            let oam_x = (sprite.x + 8) as u16;
+            if oam_x >= SCREEN_WIDTH as u16 + 8 {
+                continue;
+            }
+            length += SPRITE_PENALTY_DOTS + 5u16.saturating_sub((oam_x + scx) % 8);
+        }
+    }
+    length
+}
+
+// Draws line `window_line` of the window over the background, if the window
+// covers line `ly`, and returns whether it did. On DMG clearing LCDC bit 0
+// hides the window along with the background.
+fn render_window_line(memory: &[u8], ly: usize, window_line: usize, line: &mut [u8]) -> bool {
+    if !is_window_on_line(memory, ly) {
+        return false;
+    }
+
+    let lcdc = memory[LCDC];
+    let window_x = memory[WX] as isize - WINDOW_X_OFFSET;
+    let tile_map = tile_map(lcdc, WINDOW_TILE_MAP);
+    for (x, pixel) in line.iter_mut().enumerate() {
+        let col = x as isize - window_x;
+        if col < 0 {
+            continue;
+        }
+        let col = col as usize;
+        let tile_num = memory[tile_map + (window_line / 8) * TILE_MAP_WIDTH + col / 8];
+        *pixel = tile_pixel(
+            memory,
+            bg_tile_address(lcdc, tile_num),
+            window_line % 8,
+            col % 8,
+        );
+    }
+    true
+}
+
+// The sprites on line `ly`: the first 10 in OAM that it crosses, whether or
+// not they're on screen horizontally, in drawing priority. On DMG the sprite
+// further left wins and the earlier one in OAM breaks ties.
+fn line_sprites(memory: &[u8], ly: usize) -> Vec<Sprite> {
+    let height = sprite_height(memory[LCDC]) as i16;
+    let ly = ly as i16;
+    let mut sprites: Vec<Sprite> = all_sprites(memory)
+        .into_iter()
+        .filter(|sprite| sprite.y <= ly && ly < sprite.y + height)
+        .take(SPRITES_PER_LINE)
+        .collect();
+    // A stable sort keeps OAM order among sprites with the same X
+    sprites.sort_by_key(|sprite| sprite.x);
+    sprites
+}
+
+// Each pixel takes the first opaque sprite pixel in priority order, colour 0
+// being transparent whatever the palette maps it to. A sprite with the BG
+// priority bit is then hidden behind background colours 1-3, even where a
+// lower priority sprite would show.
+fn render_sprite_line(memory: &[u8], ly: usize, bg_line: &[u8], line: &mut [u8]) {
+    let lcdc = memory[LCDC];
+    if lcdc & OBJ_ENABLE == 0 {
+        return;
+    }
+
+    let height = sprite_height(lcdc);
+    let mut is_taken = [false; SCREEN_WIDTH];
+    for sprite in line_sprites(memory, ly) {
+        let row_address = sprite.row_address((ly as i16 - sprite.y) as usize, height);
+        for col in 0..8 {
+            let screen_x = sprite.x + col as i16;
+            if !(0..SCREEN_WIDTH as i16).contains(&screen_x) || is_taken[screen_x as usize] {
+                continue;
+            }
+            let screen_x = screen_x as usize;
+
+            let color = sprite.pixel(memory, row_address, col);
+            if color == 0 {
+                continue;
+            }
+            is_taken[screen_x] = true;
+            if !(sprite.priority && bg_line[screen_x] != 0) {
+                line[screen_x] = sprite.frame_pixel(memory, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    const CYCLES_PER_FRAME: u32 = DOTS_PER_LINE as u32 * (LAST_LINE as u32 + 1);
+
+    fn lcd_on(stat: u8) -> (Ppu, Vec<u8>) {
+        let mut memory = vec![0; 0x10000];
+        memory[LCDC] = LCD_ENABLE | BG_ENABLE;
+        memory[STAT] = stat;
+        let mut ppu = Ppu::default();
+        ppu.tick(&mut memory, 0);
+        (ppu, memory)
+    }
+
+    fn run_to(ppu: &mut Ppu, memory: &mut [u8], ly: u8, line_cycle: u16) {
+        while ppu.state.ly != ly || ppu.state.line_cycle != line_cycle {
+            ppu.tick(memory, DOTS_PER_M_CYCLE as u8);
+        }
+    }
+
+    // STAT interrupts requested over a frame, once the first has gone by
+    fn stat_interrupts_per_frame(stat: u8, lyc: u8) -> usize {
+        let (mut ppu, mut memory) = lcd_on(stat);
+        memory[LYC] = lyc;
+        for _ in 0..CYCLES_PER_FRAME / DOTS_PER_M_CYCLE as u32 {
+            ppu.tick(&mut memory, DOTS_PER_M_CYCLE as u8);
+        }
+        (0..CYCLES_PER_FRAME / DOTS_PER_M_CYCLE as u32)
+            .filter(|_| ppu.tick(&mut memory, DOTS_PER_M_CYCLE as u8) & STAT_INTERRUPT != 0)
+            .count()
+    }
+
+    fn rendered_line(memory: &[u8], ly: u8) -> Vec<u8> {
+        let mut ppu = Ppu::default();
+        ppu.render_line(memory, ly);
+        let ly = ly as usize;
+        ppu.screen[ly * SCREEN_WIDTH..(ly + 1) * SCREEN_WIDTH].to_vec()
+    }
+
+    #[test]
+    fn draws_a_disabled_background_white_whatever_bgp_says() {
+        let mut memory = vec![0; 0x10000];
+        memory[BGP] = 0xff;
+
+        memory[LCDC] = LCD_ENABLE;
+        assert_eq!(rendered_line(&memory, 0), vec![0; SCREEN_WIDTH]);
+
+        memory[LCDC] = LCD_ENABLE | BG_ENABLE;
+        assert_eq!(rendered_line(&memory, 0), vec![3; SCREEN_WIDTH]);
+    }
+
+    // Sets the low and high bytes of rows `rows` of tile `tile` at 0x8000
+    fn fill_tile(memory: &mut [u8], tile: usize, rows: Range<usize>, bytes: [u8; 2]) {
+        for row in rows {
+            let address = 0x8000 + tile * BYTES_PER_TILE + row * 2;
+            memory[address..address + 2].copy_from_slice(&bytes);
+        }
+    }
+
+    // Identity palettes, tiles at 0x8000 and every tile solid: tile 0 is
+    // colour 0, tile 1 colour 3, tile 2 colour 1 and tile 3 colour 3 with
+    // colour 2 on its last row
+    fn sprite_memory(lcdc: u8) -> Vec<u8> {
+        let mut memory = vec![0; 0x10000];
+        memory[LCDC] = LCD_ENABLE | BG_ENABLE | OBJ_ENABLE | TILE_DATA | lcdc;
+        memory[BGP] = 0xe4;
+        memory[OBP0] = 0xe4;
+        memory[OBP1] = 0xe4;
+        fill_tile(&mut memory, 1, 0..8, [0xff, 0xff]);
+        fill_tile(&mut memory, 2, 0..8, [0xff, 0x00]);
+        fill_tile(&mut memory, 3, 0..7, [0xff, 0xff]);
+        fill_tile(&mut memory, 3, 7..8, [0x00, 0xff]);
+        memory
+    }
+
+    // Puts OAM entry `idx` with its top left corner at `x`, `y` on screen
+    fn set_sprite(memory: &mut [u8], idx: usize, x: i16, y: i16, tile: u8, attributes: u8) {
+        let address = OAM_START + idx * BYTES_PER_SPRITE;
+        memory[address..address + BYTES_PER_SPRITE].copy_from_slice(&[
+            (y + 16) as u8,
+            (x + 8) as u8,
+            tile,
+            attributes,
+        ]);
+    }
+
+    #[test]
+    fn draws_the_first_10_sprites_on_a_line() {
+        let mut memory = sprite_memory(0);
+        // Off screen to the left, but it still uses up a slot
+        set_sprite(&mut memory, 0, -8, 0, 1, 0);
+        for idx in 1..11 {
+            set_sprite(&mut memory, idx, (idx as i16 - 1) * 8, 0, 1, 0);
+        }
+
+        let line = rendered_line(&memory, 0);
+        assert_eq!(line[..72], [OBP0_PIXEL | 3; 72][..]);
+        assert_eq!(line[72..80], [0; 8]);
+
+        // Sprites on other lines don't count
+        set_sprite(&mut memory, 0, -8, 8, 1, 0);
+        assert_eq!(rendered_line(&memory, 0)[..80], [OBP0_PIXEL | 3; 80][..]);
+    }
+
+    #[test]
+    fn sprites_further_left_then_earlier_in_oam_win() {
+        let mut memory = sprite_memory(0);
+        set_sprite(&mut memory, 0, 4, 0, 2, 0);
+        set_sprite(&mut memory, 1, 0, 0, 1, PALETTE);
+        let line = rendered_line(&memory, 0);
+        assert_eq!(line[..8], [OBP1_PIXEL | 3; 8]);
+        assert_eq!(line[8..12], [OBP0_PIXEL | 1; 4]);
+
+        set_sprite(&mut memory, 2, 20, 0, 1, PALETTE);
+        set_sprite(&mut memory, 3, 20, 0, 2, 0);
+        assert_eq!(rendered_line(&memory, 0)[20..28], [OBP1_PIXEL | 3; 8]);
+    }
+
+    #[test]
+    fn sprites_behind_the_background_only_show_over_colour_0() {
+        let mut memory = sprite_memory(0);
+        // Colour 1 for the first 8 pixels, colour 0 after
+        memory[0x9800] = 2;
+        set_sprite(&mut memory, 0, 0, 0, 1, BEHIND_BG);
+        set_sprite(&mut memory, 1, 8, 0, 1, BEHIND_BG);
+        // Hidden along with the sprite in front of it
+        set_sprite(&mut memory, 2, 0, 0, 2, 0);
+
+        let line = rendered_line(&memory, 0);
+        assert_eq!(line[..8], [1; 8]);
+        assert_eq!(line[8..16], [OBP0_PIXEL | 3; 8]);
+    }
+
+    #[test]
+    fn tall_sprites_use_an_even_and_odd_tile_pair() {
+        let mut memory = sprite_memory(OBJ_SIZE);
+        // Bit 0 of the tile number is ignored
+        set_sprite(&mut memory, 0, 0, 0, 3, 0);
+        assert_eq!(rendered_line(&memory, 0)[0], OBP0_PIXEL | 1);
+        assert_eq!(rendered_line(&memory, 8)[0], OBP0_PIXEL | 3);
+        assert_eq!(rendered_line(&memory, 15)[0], OBP0_PIXEL | 2);
+
+        // Flipped, the bottom tile's last row is at the top
+        set_sprite(&mut memory, 0, 0, 0, 3, Y_FLIP);
+        assert_eq!(rendered_line(&memory, 0)[0], OBP0_PIXEL | 2);
+        assert_eq!(rendered_line(&memory, 7)[0], OBP0_PIXEL | 3);
+        assert_eq!(rendered_line(&memory, 8)[0], OBP0_PIXEL | 1);
+    }
+
+    // A window of tile 1 over a colour 0 background. Row N of tile 1 only
+    // has pixel N set, so the window row drawn shows in where it is.
+    fn window_memory(wx: u8, wy: u8) -> Vec<u8> {
+        let mut memory = vec![0; 0x10000];
+        memory[LCDC] = LCD_ENABLE | BG_ENABLE | TILE_DATA | BG_TILE_MAP | WINDOW_ENABLE;
+        memory[BGP] = 0xe4;
+        memory[WX] = wx;
+        memory[WY] = wy;
+        for row in 0..8 {
+            fill_tile(&mut memory, 1, row..row + 1, [0x80 >> row, 0x80 >> row]);
+        }
+        memory[0x9800..0x9800 + TILE_MAP_WIDTH]
+            .iter_mut()
+            .for_each(|tile| *tile = 1);
+        memory
+    }
+
+    // The window row a line of `window_memory` shows at screen X `x`
+    fn window_row(ppu: &mut Ppu, memory: &[u8], ly: u8, x: usize) -> Option<usize> {
+        ppu.render_line(memory, ly);
+        let start = ly as usize * SCREEN_WIDTH + x;
+        ppu.screen[start..start + 8]
+            .iter()
+            .position(|pixel| *pixel == 3)
+    }
+
+    #[test]
+    fn offsets_the_window_by_7_pixels() {
+        let mut ppu = Ppu::default();
+        let memory = window_memory(7 + 16, 0);
+        assert_eq!(window_row(&mut ppu, &memory, 0, 0), None);
+        assert_eq!(window_row(&mut ppu, &memory, 1, 8), None);
+        assert_eq!(window_row(&mut ppu, &memory, 2, 16), Some(2));
+
+        // Below 7 the window starts off the left edge
+        let mut ppu = Ppu::default();
+        let memory = window_memory(3, 0);
+        assert_eq!(window_row(&mut ppu, &memory, 0, 0), Some(4));
+
+        let mut ppu = Ppu::default();
+        let memory = window_memory(7 + SCREEN_WIDTH as u8, 0);
+        assert_eq!(window_row(&mut ppu, &memory, 0, SCREEN_WIDTH - 8), None);
+    }
+
+    #[test]
+    fn starts_the_window_on_line_wy() {
+        let mut ppu = Ppu::default();
+        let memory = window_memory(7, 10);
+        for ly in 0..10 {
+            assert_eq!(window_row(&mut ppu, &memory, ly, 0), None, "line {}", ly);
+        }
+        assert_eq!(window_row(&mut ppu, &memory, 10, 0), Some(0));
+        assert_eq!(window_row(&mut ppu, &memory, 11, 0), Some(1));
+    }
+
+    #[test]
+    fn only_counts_window_lines_that_were_drawn() {
+        let mut ppu = Ppu::default();
+        let mut memory = window_memory(7, 0);
+        assert_eq!(window_row(&mut ppu, &memory, 0, 0), Some(0));
+        assert_eq!(window_row(&mut ppu, &memory, 1, 0), Some(1));
+
+        memory[LCDC] &= !WINDOW_ENABLE;
+        assert_eq!(window_row(&mut ppu, &memory, 2, 0), None);
+        memory[LCDC] |= WINDOW_ENABLE;
+        memory[WX] = 7 + SCREEN_WIDTH as u8;
+        assert_eq!(window_row(&mut ppu, &memory, 3, 0), None);
+
+        // Picks up where it left off
+        memory[WX] = 7;
+        assert_eq!(window_row(&mut ppu, &memory, 4, 0), Some(2));
+
+        // And starts over with the next frame
+        ppu.finish_frame();
+        assert_eq!(window_row(&mut ppu, &memory, 5, 0), Some(0));
+    }
+
+    #[test]
+    fn steps_through_oam_scan_pixel_transfer_and_hblank() {
+        let (mut ppu, mut memory) = lcd_on(0);
+        let expected = [
+            (0, Mode::OamScan),
+            (76, Mode::OamScan),
+            (80, Mode::PixelTransfer),
+            (248, Mode::PixelTransfer),
+            (252, Mode::HBlank),
+            (452, Mode::HBlank),
+        ];
+        for (line_cycle, mode) in expected.iter() {
+            run_to(&mut ppu, &mut memory, 1, *line_cycle);
+            assert_eq!(ppu.mode(), *mode, "dot {}", line_cycle);
+            assert_eq!(memory[STAT] & 0b11, *mode as u8, "dot {}", line_cycle);
+        }
+
+        // SCX stretches the transfer by SCX mod 8 dots
+        memory[SCX] = 3;
+        run_to(&mut ppu, &mut memory, 2, 252);
+        assert_eq!(ppu.mode(), Mode::PixelTransfer);
+        run_to(&mut ppu, &mut memory, 2, 256);
+        assert_eq!(ppu.mode(), Mode::HBlank);
+    }
+
+    #[test]
+    fn enters_vblank_after_the_last_visible_line() {
+        let (mut ppu, mut memory) = lcd_on(0);
+        run_to(&mut ppu, &mut memory, VBLANK_START - 1, DOTS_PER_LINE - 4);
+        let interrupts = ppu.tick(&mut memory, DOTS_PER_M_CYCLE as u8);
+        assert_eq!(interrupts & VBLANK_INTERRUPT, VBLANK_INTERRUPT);
+        assert_eq!(ppu.mode(), Mode::VBlank);
+        assert_eq!(memory[LY], VBLANK_START);
+
+        run_to(&mut ppu, &mut memory, 0, 0);
+        assert_eq!(ppu.mode(), Mode::OamScan);
+    }
+
+    #[test]
+    fn requests_a_stat_interrupt_for_each_source() {
+        assert_eq!(stat_interrupts_per_frame(HBLANK_INTERRUPT, 0xff), 144);
+        assert_eq!(stat_interrupts_per_frame(VBLANK_STAT_INTERRUPT, 0xff), 1);
+        // Every visible line, and once more as VBlank starts
+        assert_eq!(stat_interrupts_per_frame(OAM_INTERRUPT, 0xff), 145);
+        assert_eq!(stat_interrupts_per_frame(LYC_INTERRUPT, 0), 1);
+    }
+
+    #[test]
+    fn sources_that_follow_each_other_share_one_interrupt() {
+        // HBlank runs straight into the next line's OAM scan, the line never
+        // drops in between
+        assert_eq!(
+            stat_interrupts_per_frame(HBLANK_INTERRUPT | OAM_INTERRUPT, 0xff),
+            145
+        );
+        // VBlank starts with the line already up from HBlank
+        assert_eq!(
+            stat_interrupts_per_frame(HBLANK_INTERRUPT | VBLANK_STAT_INTERRUPT, 0xff),
+            144
+        );
+    }
+
+    #[test]
+    fn enabling_a_source_while_the_line_is_up_does_nothing() {
+        let (mut ppu, mut memory) = lcd_on(HBLANK_INTERRUPT);
+        memory[LYC] = 1;
+        run_to(&mut ppu, &mut memory, 1, 300);
+        assert_eq!(ppu.mode(), Mode::HBlank);
+
+        memory[STAT] |= LYC_INTERRUPT;
+        assert!(!ppu.update_stat(&mut memory));
+
+        // With the line down it goes up straight away
+        let (mut ppu, mut memory) = lcd_on(0);
+        memory[LYC] = 1;
+        run_to(&mut ppu, &mut memory, 1, 300);
+        memory[STAT] |= LYC_INTERRUPT;
+        assert!(ppu.update_stat(&mut memory));
+    }
+}
@@ -0,0 +1,213 @@
+//! DIV, TIMA, TMA and TAC. Everything is driven by the 16 bit system counter,
+//! DIV being its upper byte. TIMA counts falling edges of one counter bit,
+//! picked by TAC and gated by the timer enable bit, which is why writes to
+//! DIV and TAC can bump TIMA on their own.
+
+pub const DIV: u16 = 0xff04;
+pub const TIMA: u16 = 0xff05;
+pub const TMA: u16 = 0xff06;
+pub const TAC: u16 = 0xff07;
+
+const CLOCK_CYCLES_PER_MACHINE_CYCLE: u8 = 4;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Timer {
+    system_counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    // TIMA overflowed and reads 0 for one machine cycle before TMA is loaded
+    is_overflow_pending: bool,
+    // The machine cycle TMA is loaded in, where TIMA ignores writes and a
+    // TMA write goes straight through to it
+    is_reloading: bool,
+}
+
+impl Timer {
+    pub fn with_system_counter(system_counter: u16) -> Timer {
+        Timer {
+            system_counter,
+            ..Timer::default()
+        }
+    }
+
+    /// Advances the timer by `cycles` clock cycles, one machine cycle at a
+    /// time. Returns whether the timer interrupt should be requested.
+    pub fn tick(&mut self, cycles: u8) -> bool {
+        let mut interrupt = false;
+
+        for _ in 0..cycles / CLOCK_CYCLES_PER_MACHINE_CYCLE {
+            self.is_reloading = false;
+            if self.is_overflow_pending {
+                self.is_overflow_pending = false;
+                self.is_reloading = true;
+                self.tima = self.tma;
+                interrupt = true;
+            }
+
+            let counter = self
+                .system_counter
+                .wrapping_add(CLOCK_CYCLES_PER_MACHINE_CYCLE as u16);
+            self.set_system_counter(counter, self.tac);
+        }
+
+        interrupt
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            DIV => self.div(),
+            TIMA => self.tima,
+            TMA => self.tma,
+            // The unused upper bits of TAC read back as 1
+            _ => self.tac | 0b11111000,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            // Any write resets the whole system counter
+            DIV => self.set_system_counter(0, self.tac),
+            TIMA => {
+                if !self.is_reloading {
+                    self.tima = value;
+                    // Writing during the overflow cycle cancels the reload
+                    // and the interrupt
+                    self.is_overflow_pending = false;
+                }
+            }
+            TMA => {
+                self.tma = value;
+                if self.is_reloading {
+                    self.tima = value;
+                }
+            }
+            _ => self.set_system_counter(self.system_counter, value & 0b111),
+        }
+    }
+
+    pub fn div(&self) -> u8 {
+        (self.system_counter >> 8) as u8
+    }
+
+    pub fn system_counter(&self) -> u16 {
+        self.system_counter
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tac & 0b100 == 0b100
+    }
+
+    /// How many times a second TIMA counts up while enabled.
+    pub fn frequency(&self) -> usize {
+        match self.tac & 0b11 {
+            0 => 4096,
+            1 => 262144,
+            2 => 65536,
+            _ => 16384,
+        }
+    }
+
+    // Changing the counter or TAC can both take the selected bit from 1 to 0,
+    // which the edge detector sees the same way as the counter ticking over
+    fn set_system_counter(&mut self, system_counter: u16, tac: u8) {
+        let was_high = timer_signal(self.system_counter, self.tac);
+        self.system_counter = system_counter;
+        self.tac = tac;
+
+        if was_high && !timer_signal(self.system_counter, self.tac) {
+            self.increment_tima();
+        }
+    }
+
+    fn increment_tima(&mut self) {
+        let (tima, is_overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        if is_overflow {
+            self.is_overflow_pending = true;
+        }
+    }
+}
+
+// The input to the falling edge detector: the system counter bit selected by
+// TAC, ANDed with the enable bit
+fn timer_signal(system_counter: u16, tac: u8) -> bool {
+    let bit = match tac & 0b11 {
+        0 => 9,
+        1 => 3,
+        2 => 5,
+        _ => 7,
+    };
+
+    tac & 0b100 == 0b100 && system_counter & (1 << bit) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Enabled, counting falling edges of bit 3, every 16 clock cycles
+    const TAC_16_CYCLES: u8 = 0b101;
+
+    fn overflowing_timer() -> Timer {
+        let mut timer = Timer::default();
+        timer.write(TAC, TAC_16_CYCLES);
+        timer.write(TMA, 0x42);
+        timer.write(TIMA, 0xff);
+        timer
+    }
+
+    #[test]
+    fn reloads_tima_a_machine_cycle_after_it_overflows() {
+        let mut timer = overflowing_timer();
+        assert!(!timer.tick(16));
+        assert_eq!(timer.read(TIMA), 0x00);
+
+        assert!(timer.tick(4));
+        assert_eq!(timer.read(TIMA), 0x42);
+    }
+
+    #[test]
+    fn writing_tima_before_the_reload_cancels_it() {
+        let mut timer = overflowing_timer();
+        timer.tick(16);
+        timer.write(TIMA, 0x10);
+        assert!(!timer.tick(4));
+        assert_eq!(timer.read(TIMA), 0x10);
+    }
+
+    #[test]
+    fn the_reload_cycle_ignores_tima_writes_and_passes_tma_writes_through() {
+        let mut timer = overflowing_timer();
+        timer.tick(20);
+        timer.write(TIMA, 0x10);
+        assert_eq!(timer.read(TIMA), 0x42);
+        timer.write(TMA, 0x33);
+        assert_eq!(timer.read(TIMA), 0x33);
+    }
+
+    #[test]
+    fn resetting_div_bumps_tima_on_a_falling_edge() {
+        // Bit 3 set, resetting takes it to 0
+        let mut timer = Timer::with_system_counter(0x0108);
+        timer.write(TAC, TAC_16_CYCLES);
+        timer.write(DIV, 0x12);
+        assert_eq!(timer.read(DIV), 0);
+        assert_eq!(timer.read(TIMA), 1);
+
+        // Bit 3 clear, nothing to fall
+        let mut timer = Timer::with_system_counter(0x0104);
+        timer.write(TAC, TAC_16_CYCLES);
+        timer.write(DIV, 0x12);
+        assert_eq!(timer.read(TIMA), 0);
+    }
+
+    #[test]
+    fn disabling_the_timer_bumps_tima_on_a_falling_edge() {
+        let mut timer = Timer::with_system_counter(0x0008);
+        timer.write(TAC, TAC_16_CYCLES);
+        timer.write(TAC, 0b001);
+        assert_eq!(timer.read(TIMA), 1);
+        assert_eq!(timer.read(TAC), 0b11111001);
+    }
+}
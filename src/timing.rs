@@ -0,0 +1,173 @@
+//! How many clock cycles each instruction takes. The tables are in machine
+//! cycles, one machine cycle being 4 clock cycles.
+
+const CLOCK_CYCLES_PER_MACHINE_CYCLE: u8 = 4;
+
+// Conditional JR/JP/CALL/RET are listed with their not taken timing. The
+// illegal opcodes lock up real hardware, here they're treated as 1 cycle NOPs
+// so the cycle budget still runs out. 0xCB is covered by the CB table.
+#[rustfmt::skip]
+const OPCODE_MACHINE_CYCLES: [u8; 256] = [
+//  0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+    1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1, // 0x
+    1, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1, // 1x
+    2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1, // 2x
+    2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1, // 3x
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 4x
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 5x
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 6x
+    2, 2, 2, 2, 2, 2, 1, 2, 1, 1, 1, 1, 1, 1, 2, 1, // 7x
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 8x
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 9x
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // Ax
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // Bx
+    2, 3, 3, 4, 3, 4, 2, 4, 2, 4, 3, 0, 3, 6, 2, 4, // Cx
+    2, 3, 3, 1, 3, 4, 2, 4, 2, 4, 3, 1, 3, 1, 2, 4, // Dx
+    3, 3, 2, 1, 1, 4, 2, 4, 4, 1, 4, 1, 1, 1, 2, 4, // Ex
+    3, 3, 2, 1, 1, 4, 2, 4, 3, 2, 4, 1, 1, 1, 2, 4, // Fx
+];
+
+// Includes fetching the 0xCB prefix itself
+#[rustfmt::skip]
+const CB_OPCODE_MACHINE_CYCLES: [u8; 256] = [
+//  0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 0x RLC/RRC
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 1x RL/RR
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 2x SLA/SRA
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 3x SWAP/SRL
+    2, 2, 2, 2, 2, 2, 3, 2, 2, 2, 2, 2, 2, 2, 3, 2, // 4x BIT
+    2, 2, 2, 2, 2, 2, 3, 2, 2, 2, 2, 2, 2, 2, 3, 2, // 5x BIT
+    2, 2, 2, 2, 2, 2, 3, 2, 2, 2, 2, 2, 2, 2, 3, 2, // 6x BIT
+    2, 2, 2, 2, 2, 2, 3, 2, 2, 2, 2, 2, 2, 2, 3, 2, // 7x BIT
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 8x RES
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 9x RES
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // Ax RES
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // Bx RES
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // Cx SET
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // Dx SET
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // Ex SET
+    2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // Fx SET
+];
+
+/// Clock cycles taken by `opcode`, given the byte after it (only used for
+/// 0xCB) and the Z and C flags before it executes, which decide whether a
+/// conditional jump, call or return is taken.
+pub fn instruction_cycles(opcode: u8, cb_opcode: u8, flag_z: bool, flag_c: bool) -> u8 {
+    let machine_cycles = if opcode == 0xcb {
+        CB_OPCODE_MACHINE_CYCLES[cb_opcode as usize]
+    } else if is_condition_met(opcode, flag_z, flag_c) {
+        OPCODE_MACHINE_CYCLES[opcode as usize] + branch_taken_machine_cycles(opcode)
+    } else {
+        OPCODE_MACHINE_CYCLES[opcode as usize]
+    };
+
+    machine_cycles * CLOCK_CYCLES_PER_MACHINE_CYCLE
+}
+
+// Extra cycles spent when a conditional instruction takes its branch
+fn branch_taken_machine_cycles(opcode: u8) -> u8 {
+    match opcode {
+        // JR cc,e
+        0x20 | 0x28 | 0x30 | 0x38 => 1,
+        // RET cc
+        0xc0 | 0xc8 | 0xd0 | 0xd8 => 3,
+        // JP cc,nn
+        0xc2 | 0xca | 0xd2 | 0xda => 1,
+        // CALL cc,nn
+        0xc4 | 0xcc | 0xd4 | 0xdc => 3,
+        _ => 0,
+    }
+}
+
+fn is_condition_met(opcode: u8, flag_z: bool, flag_c: bool) -> bool {
+    if branch_taken_machine_cycles(opcode) == 0 {
+        return false;
+    }
+
+    // Bits 3-4 of every conditional opcode select NZ, Z, NC or C
+    match (opcode >> 3) & 0b11 {
+        0 => !flag_z,
+        1 => flag_z,
+        2 => !flag_c,
+        _ => flag_c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Clock cycles when not taken and when taken, for each of NZ, Z, NC, C
+    const CONDITIONAL: [(&str, [u8; 4], u8, u8); 4] = [
+        ("JR cc,e", [0x20, 0x28, 0x30, 0x38], 8, 12),
+        ("JP cc,nn", [0xc2, 0xca, 0xd2, 0xda], 12, 16),
+        ("CALL cc,nn", [0xc4, 0xcc, 0xd4, 0xdc], 12, 24),
+        ("RET cc", [0xc0, 0xc8, 0xd0, 0xd8], 8, 20),
+    ];
+
+    #[test]
+    fn conditional_branches_take_longer_when_taken() {
+        for (name, opcodes, not_taken, taken) in CONDITIONAL.iter() {
+            let [nz, z, nc, c] = *opcodes;
+            // The flags each condition holds for, then the ones it doesn't
+            let cases = [
+                (nz, (false, false), (true, false)),
+                (z, (true, false), (false, false)),
+                (nc, (false, false), (false, true)),
+                (c, (false, true), (false, false)),
+            ];
+            for (opcode, (met_z, met_c), (unmet_z, unmet_c)) in cases.iter() {
+                assert_eq!(
+                    instruction_cycles(*opcode, 0, *met_z, *met_c),
+                    *taken,
+                    "{} {:#04x} taken",
+                    name,
+                    opcode
+                );
+                assert_eq!(
+                    instruction_cycles(*opcode, 0, *unmet_z, *unmet_c),
+                    *not_taken,
+                    "{} {:#04x} not taken",
+                    name,
+                    opcode
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unconditional_branches_ignore_the_flags() {
+        // JR e, JP nn, CALL nn, RET, RETI, JP (HL)
+        let branches = [
+            (0x18, 12),
+            (0xc3, 16),
+            (0xcd, 24),
+            (0xc9, 16),
+            (0xd9, 16),
+            (0xe9, 4),
+        ];
+        for (opcode, cycles) in branches.iter() {
+            for flags in [(false, false), (true, true)].iter() {
+                assert_eq!(instruction_cycles(*opcode, 0, flags.0, flags.1), *cycles);
+            }
+        }
+    }
+
+    #[test]
+    fn cb_instructions_on_hl_access_memory() {
+        // RLC (HL), SWAP (HL), BIT 0,(HL), BIT 7,(HL), RES 0,(HL), SET 7,(HL)
+        let hl = [
+            (0x06, 16),
+            (0x36, 16),
+            (0x46, 12),
+            (0x7e, 12),
+            (0x86, 16),
+            (0xfe, 16),
+        ];
+        for (cb_opcode, cycles) in hl.iter() {
+            assert_eq!(instruction_cycles(0xcb, *cb_opcode, false, false), *cycles);
+            // The register versions are 8 cycles
+            assert_eq!(instruction_cycles(0xcb, *cb_opcode + 1, false, false), 8);
+        }
+    }
+}
@@ -0,0 +1,671 @@
+//! Browser frontend: draws the emulator core onto canvases, plays channel 1
+//! through Web Audio and mirrors serial output into the page.
+
+use bit_vec::BitVec;
+use log::info;
+use log::Level;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{AudioContext, OscillatorType};
+
+use crate::{
+    Channel, DisplayPalette, Gameboy, SerializedGameboy, BACKGROUND_HEIGHT, BACKGROUND_WIDTH,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+const MAX_GAMEBOY_VOLUME: u8 = 0xf;
+const PIXEL_ZOOM: u32 = 1;
+const BYTES_PER_TILE: usize = 16;
+const BYTES_PER_8_PIXEL: usize = 2;
+const SCREEN_PIXEL_NUM_PER_ROW: usize = 160;
+const IMAGE_DATA_LENGTH_PER_PIXEL: usize = 4; //r,g,b,a
+const PIXEL_NUM_PER_TILE_COL: usize = 8;
+const BACKGROUND_PIXEL_NUM_PER_ROW: usize = 256;
+const SPRITE_PIXEL_NUM_PER_ROW: usize = 8;
+// Rumble runs until the game switches the motor off, this just caps it
+const RUMBLE_DURATION_MS: u32 = 10000;
+
+#[wasm_bindgen]
+pub struct Canvases {
+    background_canvas: web_sys::CanvasRenderingContext2d,
+    screen_canvas: web_sys::CanvasRenderingContext2d,
+    char_map_canvas: web_sys::CanvasRenderingContext2d,
+    char_map_debug_canvas: web_sys::CanvasRenderingContext2d,
+    update_char_map_canvas_last_data: Vec<u8>,
+    palette: DisplayPalette,
+}
+
+#[wasm_bindgen]
+impl Canvases {
+    pub fn new() -> Canvases {
+        let background_canvas = Canvases::make_canvas(
+            "gameboy-background-canvas-rust",
+            PIXEL_ZOOM * BACKGROUND_WIDTH,
+            PIXEL_ZOOM * BACKGROUND_HEIGHT,
+        );
+        let screen_canvas = Canvases::make_canvas(
+            "gameboy-screen-canvas-rust",
+            PIXEL_ZOOM * SCREEN_WIDTH,
+            PIXEL_ZOOM * SCREEN_HEIGHT,
+        );
+
+        let char_map_canvas = Canvases::make_canvas("char-map-actual-canvas-rust", 8, 1024);
+
+        let char_map_debug_canvas =
+            Canvases::make_canvas("char-map-debug-canvas-rust", 8 * 12, 8 * 8);
+
+        Canvases {
+            background_canvas,
+            screen_canvas,
+            char_map_canvas,
+            char_map_debug_canvas,
+            update_char_map_canvas_last_data: Vec::new(),
+            palette: DisplayPalette::default(),
+        }
+    }
+
+    /// Sets the colours every canvas is drawn in from now on.
+    pub fn set_display_palette(&mut self, palette: &DisplayPalette) {
+        self.palette = palette.clone();
+    }
+
+    pub fn render_background_map_as_image_data(&mut self, gameboy: &mut Gameboy) {
+        // if !gameboy.is_vblank() {
+        //     return;
+        // }
+
+        let background_map = gameboy.bg_map(); //Tile index
+        let char_map_vec = gameboy.bg_window_char_map_bytes(); //Tile data
+        let mut char_map_tiles_bytes = Vec::new();
+
+        //Get Tiles
+        for idx in (0..char_map_vec.len()).step_by(BYTES_PER_TILE) {
+            let tile_bytes = &char_map_vec[idx..idx + BYTES_PER_TILE];
+            let mut image_data_source = Vec::new();
+            //Get Tile Pixel rgba data
+            for i in (0..tile_bytes.len()).step_by(BYTES_PER_8_PIXEL) {
+                let low_bits = BitVec::from_bytes(&[tile_bytes[i]]);
+                let high_bits = BitVec::from_bytes(&[tile_bytes[i + 1]]);
+
+                for pixel_index in 0..8 {
+                    let color = (high_bits[pixel_index] as u8) << 1 | low_bits[pixel_index] as u8;
+                    let [r, g, b, a] = self.palette.bg_rgba(gameboy.bg_shade(color));
+
+                    image_data_source.push(r);
+                    image_data_source.push(g);
+                    image_data_source.push(b);
+                    image_data_source.push(a);
+                }
+            }
+
+            char_map_tiles_bytes.push(image_data_source);
+        }
+
+        //Clear context
+        self.background_canvas.clear_rect(
+            0.0,
+            0.0,
+            self.background_canvas.canvas().unwrap().width() as f64,
+            self.background_canvas.canvas().unwrap().height() as f64,
+        );
+
+        let mut x = 0;
+        let mut y = 0;
+
+        for ele in background_map {
+            // Generate Tile Image data
+            let tile_idx: usize = if gameboy.get_tile_data_selection() == 1 {
+                ele as usize
+            } else {
+                ((ele as i8) as i16 + 128) as usize
+            };
+
+            let tile_bytes = &mut char_map_tiles_bytes[tile_idx];
+            let clamped_image_source = wasm_bindgen::Clamped(&tile_bytes[..]);
+
+            let tile_image_data =
+                web_sys::ImageData::new_with_u8_clamped_array(clamped_image_source, 8).unwrap();
+
+            self.background_canvas
+                .put_image_data(&tile_image_data, x as f64, y as f64)
+                .unwrap();
+
+            x += 8;
+            if x >= 32 * 8 {
+                x = 0;
+                y += 8;
+            }
+        }
+    }
+
+    pub fn update_char_map_canvas(&mut self, gameboy: &mut Gameboy) {
+        let image_source = gameboy.char_map_to_image_data(&self.palette);
+        let clamped_image_source = wasm_bindgen::Clamped(&image_source[..]);
+
+        let image_data: web_sys::ImageData =
+            web_sys::ImageData::new_with_u8_clamped_array(clamped_image_source, 8).unwrap();
+
+        self.char_map_canvas
+            .put_image_data(&image_data, 0.0, 0.0)
+            .unwrap();
+
+        let tiles_per_row = 12;
+        let width = self.char_map_debug_canvas.canvas().unwrap().width() as f64;
+        let height = self.char_map_debug_canvas.canvas().unwrap().height() as f64;
+
+        self.char_map_debug_canvas
+            .clear_rect(0.0, 0.0, width, height);
+
+        for tile_idx in 0..96 {
+            //Get tile image data
+            let y0 = (tile_idx * 8) as f64;
+            let image_data = self.char_map_canvas.get_image_data(0.0, y0, 8.0, y0 + 8.0);
+            let tile = image_data.unwrap();
+
+            let x = (tile_idx % tiles_per_row) as f64;
+            let y = ((tile_idx / tiles_per_row) as f64).floor();
+            self.char_map_debug_canvas
+                .put_image_data(&tile, x * 8.0, y * 8.0)
+                .unwrap();
+        }
+
+        self.update_char_map_canvas_last_data = image_source;
+    }
+
+    pub fn draw_screen_from_memory(&self, gameboy: &mut Gameboy) {
+        if !gameboy.is_vblank() {
+            return;
+        }
+
+        let is_lcd_enable = gameboy.is_lcd_display_enable();
+        if !is_lcd_enable {
+            return;
+        }
+
+        let background_map = gameboy.bg_map();
+        let char_map_vec = gameboy.bg_window_char_map_bytes();
+
+        //Generate background bytes from char map
+        let mut background_pixels_row_rgba: Vec<Vec<u8>> = Vec::new();
+        background_pixels_row_rgba.resize(256, Vec::new());
+
+        for (idx, ele) in background_map.into_iter().enumerate() {
+            let tile_idx: usize = if gameboy.get_tile_data_selection() == 1 {
+                ele as usize
+            } else {
+                ((ele as i8) as i16 + 128) as usize
+            };
+            let tile_start_idx = tile_idx * BYTES_PER_TILE;
+            let tile_end_idx = tile_start_idx + BYTES_PER_TILE;
+
+            let tile_bytes = &char_map_vec[tile_start_idx..tile_end_idx];
+            for i in (0..tile_bytes.len()).step_by(BYTES_PER_8_PIXEL) {
+                let background_y = (idx / 32) * PIXEL_NUM_PER_TILE_COL + i / BYTES_PER_8_PIXEL;
+                let low_bits = BitVec::from_bytes(&[tile_bytes[i]]);
+                let high_bits = BitVec::from_bytes(&[tile_bytes[i + 1]]);
+
+                for pixel_index in 0..8 {
+                    let color = (high_bits[pixel_index] as u8) << 1 | low_bits[pixel_index] as u8;
+                    let [r, g, b, a] = self.palette.bg_rgba(gameboy.bg_shade(color));
+
+                    background_pixels_row_rgba[background_y].push(r);
+                    background_pixels_row_rgba[background_y].push(g);
+                    background_pixels_row_rgba[background_y].push(b);
+                    background_pixels_row_rgba[background_y].push(a);
+                }
+            }
+        }
+
+        let background_pixels_rgba_vec: Vec<u8> = background_pixels_row_rgba.concat();
+
+        //Get screen bytes from background bytes
+        let screen_pixels_rgba_vec = screen_from_background(
+            &background_pixels_rgba_vec,
+            gameboy.get_scroll_x(),
+            gameboy.get_scroll_y(),
+        );
+
+        //Drawing screen
+        self.screen_canvas.clear_rect(
+            0.0,
+            0.0,
+            self.screen_canvas.canvas().unwrap().width() as f64,
+            self.screen_canvas.canvas().unwrap().height() as f64,
+        );
+
+        for screen_y in 0..144 {
+            let start_row = screen_y * SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
+            let end_row = start_row + SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
+            let clamped_image_source =
+                wasm_bindgen::Clamped(&screen_pixels_rgba_vec[start_row..end_row]);
+
+            let pixel_row_image_data =
+                web_sys::ImageData::new_with_u8_clamped_array_and_sh(clamped_image_source, 160, 1)
+                    .unwrap();
+            self.screen_canvas
+                .put_image_data(&pixel_row_image_data, 0.0, screen_y as f64)
+                .unwrap();
+        }
+    }
+
+    /// Puts the last frame the core finished on the screen canvas.
+    pub fn draw_screen_with_obj(&self, gameboy: &mut Gameboy) {
+        let frame = gameboy.frame_buffer();
+        let mut screen_pixels_rgba_vec: Vec<u8> =
+            Vec::with_capacity(frame.len() * IMAGE_DATA_LENGTH_PER_PIXEL);
+        for shade in frame {
+            screen_pixels_rgba_vec.extend_from_slice(&self.palette.frame_rgba(shade));
+        }
+
+        let clamped_image_source = wasm_bindgen::Clamped(&screen_pixels_rgba_vec[..]);
+        let screen_image_data = web_sys::ImageData::new_with_u8_clamped_array_and_sh(
+            clamped_image_source,
+            SCREEN_WIDTH,
+            SCREEN_HEIGHT,
+        )
+        .unwrap();
+        self.screen_canvas
+            .put_image_data(&screen_image_data, 0.0, 0.0)
+            .unwrap();
+    }
+
+    pub fn draw_obj(&self, gameboy: &mut Gameboy) {
+        //Drawing screen
+        self.screen_canvas.clear_rect(
+            0.0,
+            0.0,
+            self.screen_canvas.canvas().unwrap().width() as f64,
+            self.screen_canvas.canvas().unwrap().height() as f64,
+        );
+
+        let blank_screen_with_sprites_rgba = self.get_blank_screen_pixel_with_sprites(gameboy);
+
+        for screen_y in 0..144 {
+            let start_row = screen_y * SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
+            let end_row = start_row + SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL;
+
+            let clamped_image_source =
+                wasm_bindgen::Clamped(&blank_screen_with_sprites_rgba[start_row..end_row]);
+
+            let pixel_row_image_data =
+                web_sys::ImageData::new_with_u8_clamped_array_and_sh(clamped_image_source, 160, 1)
+                    .unwrap();
+            self.screen_canvas
+                .put_image_data(&pixel_row_image_data, 0.0, screen_y as f64)
+                .unwrap();
+        }
+    }
+
+    fn get_blank_screen_pixel_with_sprites(&self, gameboy: &mut Gameboy) -> Vec<u8> {
+        let screen_rbga_vec_length =
+            SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * IMAGE_DATA_LENGTH_PER_PIXEL;
+
+        let mut entire_screen_pixels_rgba = Vec::new();
+        entire_screen_pixels_rgba.resize_with(screen_rbga_vec_length, || 255);
+
+        // Fill in sprites data in blank temp_screen, the later ones in OAM
+        // first so earlier ones end up on top
+        let height = gameboy.sprite_height();
+        for obj in gameboy.all_sprites().iter().rev() {
+            for obj_row in 0..height {
+                let y = obj.y + obj_row as i16;
+                if !(0..SCREEN_HEIGHT as i16).contains(&y) {
+                    continue;
+                }
+                let row_address = obj.row_address(obj_row, height);
+
+                for obj_col in 0..SPRITE_PIXEL_NUM_PER_ROW {
+                    let x = obj.x + obj_col as i16;
+                    if !(0..SCREEN_WIDTH as i16).contains(&x) {
+                        continue;
+                    }
+
+                    // Colour 0 is transparent
+                    let color = gameboy.sprite_pixel(obj, row_address, obj_col);
+                    if color == 0 {
+                        continue;
+                    }
+
+                    let start = y as usize * SCREEN_PIXEL_NUM_PER_ROW * IMAGE_DATA_LENGTH_PER_PIXEL
+                        + x as usize * IMAGE_DATA_LENGTH_PER_PIXEL;
+                    entire_screen_pixels_rgba[start..start + IMAGE_DATA_LENGTH_PER_PIXEL]
+                        .copy_from_slice(
+                            &self
+                                .palette
+                                .obj_rgba(obj.palette_num, gameboy.sprite_shade(obj, color)),
+                        );
+                }
+            }
+        }
+
+        entire_screen_pixels_rgba
+    }
+
+    pub fn make_canvas(
+        canvas_selector: &str,
+        width: u32,
+        height: u32,
+    ) -> web_sys::CanvasRenderingContext2d {
+        let document = web_sys::window().unwrap().document().unwrap();
+
+        let el = document.get_element_by_id(canvas_selector).unwrap();
+        let el: web_sys::HtmlCanvasElement = el
+            .dyn_into::<web_sys::HtmlCanvasElement>()
+            .map_err(|_| ())
+            .unwrap();
+
+        let ctx = el
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<web_sys::CanvasRenderingContext2d>()
+            .unwrap();
+
+        el.set_width(width);
+        el.set_height(height);
+
+        ctx.set_image_smoothing_enabled(false);
+
+        ctx
+    }
+}
+
+// The 160x144 window of the 256x256 background RGBA at the scroll position,
+// wrapping around the edges of the background
+fn screen_from_background(background_rgba: &[u8], scroll_x: u8, scroll_y: u8) -> Vec<u8> {
+    let mut screen_rgba = Vec::with_capacity(
+        SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * IMAGE_DATA_LENGTH_PER_PIXEL,
+    );
+
+    for screen_y in 0..SCREEN_HEIGHT as u8 {
+        let y = scroll_y.wrapping_add(screen_y) as usize;
+        for screen_x in 0..SCREEN_WIDTH as u8 {
+            let x = scroll_x.wrapping_add(screen_x) as usize;
+            let start = (y * BACKGROUND_PIXEL_NUM_PER_ROW + x) * IMAGE_DATA_LENGTH_PER_PIXEL;
+            screen_rgba
+                .extend_from_slice(&background_rgba[start..start + IMAGE_DATA_LENGTH_PER_PIXEL]);
+        }
+    }
+
+    screen_rgba
+}
+
+#[wasm_bindgen]
+pub struct FmOsc {
+    ctx: AudioContext,
+    /// The primary oscillator.  This will be the fundamental frequency
+    primary: web_sys::OscillatorNode,
+    /// Overall gain (volume) control
+    gain: web_sys::GainNode,
+    /// Amount of frequency modulation
+    fm_gain: web_sys::GainNode,
+    /// The oscillator that will modulate the primary oscillator's frequency
+    fm_osc: web_sys::OscillatorNode,
+    /// The ratio between the primary frequency and the fm_osc frequency.
+    /// Generally fractional values like 1/2 or 1/4 sound best
+    fm_freq_ratio: f32,
+    fm_gain_ratio: f32,
+}
+
+#[wasm_bindgen]
+impl FmOsc {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<FmOsc, JsValue> {
+        let ctx = web_sys::AudioContext::new()?;
+
+        // Create our web audio objects.
+        let primary = ctx.create_oscillator()?;
+        let fm_osc = ctx.create_oscillator()?;
+        let gain = ctx.create_gain()?;
+        let fm_gain = ctx.create_gain()?;
+
+        // Some initial settings:
+        primary.set_type(OscillatorType::Square);
+        primary.frequency().set_value(0.0);
+        gain.gain().set_value(0.0); // starts muted
+        fm_gain.gain().set_value(0.0); // no initial frequency modulation
+        fm_osc.set_type(OscillatorType::Square);
+        fm_osc.frequency().set_value(0.0);
+
+        // Connect the nodes up!
+
+        // The primary oscillator is routed through the gain node, so that
+        // it can control the overall output volume.
+        primary.connect_with_audio_node(&gain)?;
+
+        // Then connect the gain node to the AudioContext destination (aka
+        // your speakers).
+        gain.connect_with_audio_node(&ctx.destination())?;
+
+        // The FM oscillator is connected to its own gain node, so it can
+        // control the amount of modulation.
+        fm_osc.connect_with_audio_node(&fm_gain)?;
+
+        // Connect the FM oscillator to the frequency parameter of the main
+        // oscillator, so that the FM node can modulate its frequency.
+        fm_gain.connect_with_audio_param(&primary.frequency())?;
+
+        // Start the oscillators!
+        primary.start()?;
+        fm_osc.start()?;
+
+        Ok(FmOsc {
+            ctx,
+            primary,
+            gain,
+            fm_gain,
+            fm_osc,
+            fm_freq_ratio: 0.0,
+            fm_gain_ratio: 0.0,
+        })
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.gain.gain().value()
+    }
+
+    pub fn frequency(&self) -> f32 {
+        self.primary.frequency().value()
+    }
+
+    /// Sets the gain for this oscillator, between 0.0 and 1.0.
+    #[wasm_bindgen]
+    pub fn set_gain(&self, mut gain: f32) {
+        gain = gain.clamp(0.0, 1.0);
+        self.gain.gain().set_value(gain);
+    }
+
+    #[wasm_bindgen]
+    pub fn set_primary_frequency(&self, freq: f32) {
+        self.primary.frequency().set_value(freq);
+
+        // The frequency of the FM oscillator depends on the frequency of the
+        // primary oscillator, so we update the frequency of both in this method.
+        self.fm_osc.frequency().set_value(self.fm_freq_ratio * freq);
+        self.fm_gain.gain().set_value(self.fm_gain_ratio * freq);
+    }
+
+    /// This should be between 0 and 1, though higher values are accepted.
+    #[wasm_bindgen]
+    pub fn set_fm_amount(&mut self, amt: f32) {
+        self.fm_gain_ratio = amt;
+
+        self.fm_gain
+            .gain()
+            .set_value(self.fm_gain_ratio * self.primary.frequency().value());
+    }
+
+    #[wasm_bindgen]
+    pub fn set_gain_shift(&mut self, original_volume_float: f32, shift_num: u8, is_increase: bool) {
+        let current_time = self.ctx.current_time();
+        let one64th = 1.0 / 64.0;
+        let shift_length = (one64th) * shift_num as f64;
+        let original_volume = (original_volume_float * 10.0) as u8;
+
+        if is_increase {
+            let steps_to_max = MAX_GAMEBOY_VOLUME - (original_volume * 10);
+            for shift_offset in 1..steps_to_max {
+                let at_time = current_time + (shift_offset as f64 * shift_length);
+                let volume = (original_volume + (shift_offset)) as f32 / 10.0;
+
+                match self.gain.gain().set_value_at_time(volume, at_time) {
+                    Ok(_v) => (),
+                    Err(_e) => (),
+                }
+            }
+        } else {
+            let steps_to_min = original_volume + 1;
+            for shift_offset in 1..steps_to_min {
+                let at_time = current_time + (shift_offset as f64 * shift_length);
+                let volume = (original_volume - (shift_offset)) as f32 / 10.0;
+
+                // info!(
+                //     "volume={:?} original_volume={:?} shift_offset={:?}",
+                //     volume, original_volume, shift_offset
+                // );
+
+                match self.gain.gain().set_value_at_time(volume, at_time) {
+                    Ok(_v) => (),
+                    Err(_e) => (),
+                }
+            }
+        }
+    }
+
+    /// This should be between 0 and 1, though higher values are accepted.
+    #[wasm_bindgen]
+    pub fn set_fm_frequency(&mut self, amt: f32) {
+        self.fm_freq_ratio = amt;
+        self.fm_osc
+            .frequency()
+            .set_value(self.fm_freq_ratio * self.primary.frequency().value());
+    }
+}
+
+/// Owns the browser outputs and feeds them from a running `Gameboy`.
+#[wasm_bindgen]
+pub struct Frontend {
+    canvases: Canvases,
+    fm_osc: FmOsc,
+}
+
+#[wasm_bindgen]
+impl Frontend {
+    pub fn new() -> Result<Frontend, JsValue> {
+        Ok(Frontend {
+            canvases: Canvases::new(),
+            fm_osc: FmOsc::new()?,
+        })
+    }
+
+    pub fn set_display_palette(&mut self, palette: &DisplayPalette) {
+        self.canvases.set_display_palette(palette);
+    }
+
+    /// Emulation errors are thrown to JS once the output produced before the
+    /// gameboy stopped has been flushed.
+    pub fn execute_opcodes_no_stop(
+        &mut self,
+        gameboy: &mut Gameboy,
+        count: u32,
+    ) -> Result<(), JsValue> {
+        let frame_ready = gameboy.execute_opcodes_no_stop(count);
+        self.flush_output(gameboy);
+
+        if frame_ready? {
+            self.canvases.draw_screen_with_obj(gameboy);
+        }
+        Ok(())
+    }
+
+    pub fn execute_opcodes(&mut self, gameboy: &mut Gameboy, count: u8) -> Result<(), JsValue> {
+        let frame_ready = gameboy.execute_opcodes(count);
+        self.flush_output(gameboy);
+
+        if frame_ready? {
+            self.canvases.update_char_map_canvas(gameboy);
+            self.canvases.render_background_map_as_image_data(gameboy);
+            self.canvases.draw_screen_from_memory(gameboy);
+        }
+        Ok(())
+    }
+
+    fn flush_output(&mut self, gameboy: &mut Gameboy) {
+        if let Some(square1) = gameboy.take_square1_update() {
+            self.reset_fm_osc(square1);
+        }
+
+        if let Some(is_rumble_on) = gameboy.take_rumble_update() {
+            self.set_rumble(is_rumble_on);
+        }
+
+        let serial_output = gameboy.take_serial_output();
+        if !serial_output.is_empty() {
+            self.debug_serial_value(&serial_output);
+        }
+    }
+
+    // Vibrates the device for as long as the motor is on, where the browser
+    // supports it
+    fn set_rumble(&self, is_rumble_on: bool) {
+        let duration = if is_rumble_on { RUMBLE_DURATION_MS } else { 0 };
+        web_sys::window()
+            .unwrap()
+            .navigator()
+            .vibrate_with_duration(duration);
+    }
+
+    fn reset_fm_osc(&mut self, square1: Channel) {
+        self.fm_osc.set_primary_frequency(square1.frequency());
+        self.fm_osc.set_gain_shift(
+            square1.volume() as f32 * 0.1,
+            square1.envelop_shift_num(),
+            square1.is_envelop_increase(),
+        );
+    }
+
+    fn debug_serial_value(&self, characters: &str) {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let serial_debug_id = "serial-debug";
+
+        let el = document.get_element_by_id(serial_debug_id).unwrap();
+
+        let current_html = el.inner_html();
+        let new_html = format!("{}{}", current_html, characters);
+        info!("{}", new_html);
+        el.set_inner_html(&new_html);
+    }
+}
+
+impl SerializedGameboy {
+    #[allow(deprecated)]
+    pub fn to_json(&self) -> JsValue {
+        JsValue::from_serde(&self).unwrap()
+    }
+
+    #[allow(deprecated)]
+    pub fn from_json(val: &JsValue, rom: &[u8]) -> Result<Gameboy, JsValue> {
+        let serialized: SerializedGameboy = val
+            .into_serde()
+            .map_err(|err| JsValue::from_str(&format!("Invalid save state: {}", err)))?;
+        Ok(crate::gameboy_from_serializable(serialized, rom)?)
+    }
+}
+
+#[wasm_bindgen]
+pub fn to_save_state(gameboy: Gameboy) -> JsValue {
+    gameboy.to_serializable().to_json()
+}
+
+#[wasm_bindgen]
+pub fn load_state(val: &JsValue, rom: &[u8]) -> Result<Gameboy, JsValue> {
+    SerializedGameboy::from_json(val, rom)
+}
+
+#[wasm_bindgen]
+pub fn init() {
+    match console_log::init_with_level(Level::Debug) {
+        Ok(_value) => info!("WASM Gameboy Emulator initialized"),
+        Err(_err) => println!("Failed to initialize console logger"),
+    }
+}
@@ -0,0 +1,64 @@
+//! Starts each model with and without a boot ROM to check what the cartridge
+//! is handed over with.
+
+mod common;
+
+use wasm_gameboy_emulator::{CartridgeHeader, Gameboy, Model};
+
+const ROM_ONLY: u8 = 0x00;
+
+#[test]
+fn starts_at_0x100_with_each_models_post_boot_registers() {
+    let rom = common::build_rom(b"BOOT", ROM_ONLY, &[]);
+    let is_checksum_non_zero = CartridgeHeader::parse(&rom).unwrap().header_checksum() != 0;
+
+    // A, Z, H and C, DIV
+    let models = [
+        (Model::Dmg, 0x01, true, is_checksum_non_zero, 0xab),
+        (Model::Mgb, 0xff, true, is_checksum_non_zero, 0xab),
+        (Model::Sgb, 0x01, false, false, 0xd8),
+        (Model::Cgb, 0x11, true, false, 0x26),
+    ];
+    for (model, a, flag_z, flag_hc, div) in models.iter().cloned() {
+        let gameboy = Gameboy::from_rom(&rom, model, None).unwrap();
+        assert_eq!(gameboy.get_a(), a, "{}", model);
+        assert_eq!(gameboy.get_flag_z(), flag_z, "{}", model);
+        assert!(!gameboy.get_flag_n(), "{}", model);
+        assert_eq!(gameboy.get_flag_h(), flag_hc, "{}", model);
+        assert_eq!(gameboy.get_flag_c(), flag_hc, "{}", model);
+        assert_eq!(gameboy.get_sp(), 0xfffe, "{}", model);
+        assert_eq!(gameboy.get_pc(), 0x0100, "{}", model);
+        assert_eq!(gameboy.get_divide_register(), div, "{}", model);
+    }
+}
+
+#[test]
+fn writing_ff50_maps_the_cartridge_back_over_the_boot_rom() {
+    // Both the boot ROM and the cartridge go on to load $0010 into A
+    let load_0x0010 = [0xfa, 0x10, 0x00]; // ld a, ($0010)
+    let mut rom = common::build_rom(b"BOOT", ROM_ONLY, &[]);
+    rom[0x0008..0x000b].copy_from_slice(&load_0x0010);
+    rom[0x0010] = 0xca;
+    common::fix_checksums(&mut rom);
+
+    let mut boot_rom = vec![0; Model::Dmg.boot_rom_size()];
+    #[rustfmt::skip]
+    let program = [
+        0xfa, 0x10, 0x00, // ld a, ($0010)
+        0x47,             // ld b, a
+        0x3e, 0x01,       // ld a, $01
+        0xe0, 0x50,       // ldh ($50), a
+    ];
+    boot_rom[..program.len()].copy_from_slice(&program);
+    boot_rom[0x0008..0x000b].copy_from_slice(&load_0x0010);
+    boot_rom[0x0010] = 0xb0;
+
+    let mut gameboy = Gameboy::from_rom(&rom, Model::Dmg, Some(boot_rom)).unwrap();
+    assert_eq!(gameboy.get_pc(), 0x0000);
+    for _ in 0..5 {
+        gameboy.execute_opcode().unwrap();
+    }
+    assert_eq!(gameboy.get_b(), 0xb0);
+    assert_eq!(gameboy.get_a(), 0xca);
+    assert_eq!(gameboy.get_pc(), 0x000b);
+}
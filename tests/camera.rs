@@ -0,0 +1,102 @@
+//! Takes a picture with the Pocket Camera mapper, fed from a fixed image
+//! instead of a sensor.
+
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use wasm_gameboy_emulator::{
+    Gameboy, ImageSource, Model, StaticImage, CAMERA_IMAGE_HEIGHT, CAMERA_IMAGE_WIDTH,
+};
+
+const CYCLES_PER_FRAME: u32 = 70224;
+const POCKET_CAMERA: u8 = 0xfc;
+// Where the camera writes the captured tiles to in RAM bank 0
+const IMAGE_ADDRESS: usize = 0x100;
+
+// Enables RAM, maps the camera registers, writes a dither matrix with the
+// same thresholds (0x40, 0x80, 0xC0) for every pixel, starts a capture and
+// waits for it to finish before mapping RAM bank 0 back in.
+#[rustfmt::skip]
+const CAPTURE_PROGRAM: [u8; 45] = [
+    0x3e, 0x0a, 0xea, 0x00, 0x00, // ld a, $0a; ld ($0000), a
+    0x3e, 0x10, 0xea, 0x00, 0x40, // ld a, $10; ld ($4000), a
+    0x21, 0x06, 0xa0,             // ld hl, $a006
+    0x06, 0x10,                   // ld b, 16
+    0x3e, 0x40, 0x22,             // .matrix: ld a, $40; ld (hl+), a
+    0x3e, 0x80, 0x22,             // ld a, $80; ld (hl+), a
+    0x3e, 0xc0, 0x22,             // ld a, $c0; ld (hl+), a
+    0x05, 0x20, 0xf4,             // dec b; jr nz, .matrix
+    0x3e, 0x01, 0xea, 0x00, 0xa0, // ld a, $01; ld ($a000), a
+    0xfa, 0x00, 0xa0,             // .wait: ld a, ($a000)
+    0xe6, 0x01, 0x20, 0xf9,       // and $01; jr nz, .wait
+    0xaf, 0xea, 0x00, 0x40,       // xor a; ld ($4000), a
+    0x18, 0xfe,                   // jr @
+];
+
+fn camera_rom() -> Vec<u8> {
+    common::build_rom(b"CAMERA", POCKET_CAMERA, &CAPTURE_PROGRAM)
+}
+
+fn capture(gameboy: &mut Gameboy) -> Vec<u8> {
+    gameboy.start_running();
+    for _ in 0..10 {
+        gameboy.execute_opcodes_no_stop(CYCLES_PER_FRAME).unwrap();
+    }
+    gameboy.export_save()
+}
+
+// The first row of the tile at column `tile_x` of the top tile row
+fn first_tile_row(save: &[u8], tile_x: usize) -> [u8; 2] {
+    let address = IMAGE_ADDRESS + tile_x * 16;
+    [save[address], save[address + 1]]
+}
+
+#[test]
+fn captures_the_image_source_dithered_to_tiles() {
+    let mut gameboy = Gameboy::from_rom(&camera_rom(), Model::Dmg, None).unwrap();
+
+    // Four bands of 32 columns, from black to white
+    let bands = [0x00, 0x50, 0xa0, 0xff];
+    let pixels = (0..CAMERA_IMAGE_WIDTH * CAMERA_IMAGE_HEIGHT)
+        .map(|idx| bands[(idx % CAMERA_IMAGE_WIDTH) / 32])
+        .collect();
+    gameboy.set_camera_image_source(Box::new(StaticImage::new(pixels)));
+
+    let save = capture(&mut gameboy);
+
+    // 4 tiles to a band, each shade as its low and high bit planes
+    assert_eq!(first_tile_row(&save, 0), [0xff, 0xff]);
+    assert_eq!(first_tile_row(&save, 4), [0x00, 0xff]);
+    assert_eq!(first_tile_row(&save, 8), [0xff, 0x00]);
+    assert_eq!(first_tile_row(&save, 12), [0x00, 0x00]);
+
+    // The last row of the bottom right tile
+    let last_row = IMAGE_ADDRESS + (16 * 14 - 1) * 16 + 14;
+    assert_eq!(&save[last_row..last_row + 2], &[0x00, 0x00]);
+    assert_eq!(save[IMAGE_ADDRESS - 1], 0);
+}
+
+// Counts the pictures taken, all of them black
+struct CountingSource(Rc<Cell<u32>>);
+
+impl ImageSource for CountingSource {
+    fn capture(&mut self) -> Vec<u8> {
+        self.0.set(self.0.get() + 1);
+        vec![0; CAMERA_IMAGE_WIDTH * CAMERA_IMAGE_HEIGHT]
+    }
+}
+
+#[test]
+fn takes_one_picture_per_capture() {
+    let mut gameboy = Gameboy::from_rom(&camera_rom(), Model::Dmg, None).unwrap();
+    let capture_count = Rc::new(Cell::new(0));
+    gameboy.set_camera_image_source(Box::new(CountingSource(capture_count.clone())));
+
+    let save = capture(&mut gameboy);
+
+    assert_eq!(capture_count.get(), 1);
+    let image = &save[IMAGE_ADDRESS..IMAGE_ADDRESS + 16 * 14 * 16];
+    assert!(image.iter().all(|byte| *byte == 0xff));
+}
@@ -0,0 +1,29 @@
+//! Helpers shared by the integration tests.
+
+/// A 32KB ROM that jumps from the entry point to `program` at $0150, with
+/// a header for `cartridge_type` and both checksums filled in.
+pub fn build_rom(title: &[u8], cartridge_type: u8, program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    // nop; jp $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+    rom[0x134..0x134 + title.len()].copy_from_slice(title);
+    rom[0x147] = cartridge_type;
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+    fix_checksums(&mut rom);
+    rom
+}
+
+/// Recomputes both checksums after the header or program was changed.
+pub fn fix_checksums(rom: &mut [u8]) {
+    let header_checksum = rom[0x134..0x14d]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+    rom[0x14d] = header_checksum;
+    // The checksum bytes themselves aren't part of the sum
+    let global_checksum = rom
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| !(0x14e..0x150).contains(idx))
+        .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16));
+    rom[0x14e..0x150].copy_from_slice(&global_checksum.to_be_bytes());
+}
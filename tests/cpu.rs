@@ -0,0 +1,131 @@
+//! Runs small programs an instruction at a time to check interrupt dispatch,
+//! EI and HALT.
+
+mod common;
+
+use wasm_gameboy_emulator::{Gameboy, Model};
+
+const ROM_ONLY: u8 = 0x00;
+const PROGRAM_START: u16 = 0x150;
+const IF: usize = 0xff0f;
+const INTERRUPT_DISPATCH_CYCLES: usize = 20;
+
+// Runs the entry point's jump to `program`
+fn run_to_program(program: &[u8]) -> Gameboy {
+    let rom = common::build_rom(b"CPU", ROM_ONLY, program);
+    let mut gameboy = Gameboy::from_rom(&rom, Model::Dmg, None).unwrap();
+    while gameboy.get_pc() != PROGRAM_START {
+        gameboy.execute_opcode().unwrap();
+    }
+    gameboy
+}
+
+fn step(gameboy: &mut Gameboy, count: usize) {
+    for _ in 0..count {
+        gameboy.execute_opcode().unwrap();
+    }
+}
+
+fn read(gameboy: &Gameboy, address: usize) -> u8 {
+    unsafe { *gameboy.memory().add(address) }
+}
+
+#[test]
+fn ei_takes_effect_after_the_next_instruction() {
+    #[rustfmt::skip]
+    let program = [
+        0x3e, 0x01, 0xe0, 0xff, // ld a, $01; ldh ($ff), a
+        0xe0, 0x0f,             // ldh ($0f), a
+        0xfb,                   // ei
+        0x00,                   // nop
+        0x00,                   // nop
+    ];
+    let mut gameboy = run_to_program(&program);
+    step(&mut gameboy, 4);
+    assert_eq!(gameboy.get_pc(), 0x157);
+    assert!(!gameboy.get_flag_ime());
+
+    // The NOP after EI still runs with the VBlank interrupt pending
+    step(&mut gameboy, 1);
+    assert_eq!(gameboy.get_pc(), 0x158);
+    assert!(gameboy.get_flag_ime());
+
+    let sp = gameboy.get_sp();
+    let cycles = gameboy.total_cycle();
+    step(&mut gameboy, 1);
+    assert_eq!(gameboy.get_pc(), 0x40);
+    assert_eq!(gameboy.total_cycle() - cycles, INTERRUPT_DISPATCH_CYCLES);
+    assert!(!gameboy.get_flag_ime());
+    assert_eq!(read(&gameboy, IF) & 0b1, 0);
+
+    // The return address is pushed high byte first
+    assert_eq!(gameboy.get_sp(), sp - 2);
+    assert_eq!(read(&gameboy, sp as usize - 1), 0x01);
+    assert_eq!(read(&gameboy, sp as usize - 2), 0x58);
+}
+
+#[test]
+fn services_only_the_highest_priority_interrupt() {
+    #[rustfmt::skip]
+    let program = [
+        0x3e, 0x1f, 0xe0, 0xff, // ld a, $1f; ldh ($ff), a
+        0x3e, 0x14, 0xe0, 0x0f, // ld a, $14; ldh ($0f), a
+        0xfb,                   // ei
+        0x00,                   // nop
+    ];
+    let mut gameboy = run_to_program(&program);
+    step(&mut gameboy, 7);
+
+    // Timer beats joypad, which stays requested
+    assert_eq!(gameboy.get_pc(), 0x50);
+    assert_eq!(read(&gameboy, IF) & 0b11100, 0b10000);
+}
+
+#[test]
+fn halt_with_an_interrupt_pending_and_ime_off_repeats_the_next_byte() {
+    #[rustfmt::skip]
+    let program = [
+        0x3e, 0x04, 0xe0, 0xff, // ld a, $04; ldh ($ff), a
+        0xe0, 0x0f,             // ldh ($0f), a
+        0x76,                   // halt
+        0x3c,                   // inc a
+        0x18, 0xfe,             // jr @
+    ];
+    let mut gameboy = run_to_program(&program);
+    step(&mut gameboy, 4);
+    assert_eq!(gameboy.get_pc(), 0x157);
+
+    // INC A is read twice, PC only moving past it the second time
+    step(&mut gameboy, 1);
+    assert_eq!(gameboy.get_pc(), 0x157);
+    assert_eq!(gameboy.get_a(), 0x05);
+    step(&mut gameboy, 1);
+    assert_eq!(gameboy.get_pc(), 0x158);
+    assert_eq!(gameboy.get_a(), 0x06);
+}
+
+#[test]
+fn halt_waits_for_an_interrupt_even_with_ime_off() {
+    #[rustfmt::skip]
+    let program = [
+        0x3e, 0x04, 0xe0, 0xff, // ld a, $04; ldh ($ff), a
+        0xaf, 0xe0, 0x0f,       // xor a; ldh ($0f), a
+        0x76,                   // halt
+        0x3c,                   // inc a
+        0x18, 0xfe,             // jr @
+    ];
+    let mut gameboy = run_to_program(&program);
+    step(&mut gameboy, 5);
+    assert_eq!(gameboy.get_pc(), 0x158);
+
+    let cycles = gameboy.total_cycle();
+    step(&mut gameboy, 10);
+    assert_eq!(gameboy.get_pc(), 0x158);
+    assert_eq!(gameboy.total_cycle() - cycles, 10 * 4);
+
+    // Woken up without being dispatched
+    gameboy.request_timer_interrupt();
+    step(&mut gameboy, 1);
+    assert_eq!(gameboy.get_pc(), 0x159);
+    assert_eq!(gameboy.get_a(), 0x01);
+}
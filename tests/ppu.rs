@@ -0,0 +1,55 @@
+//! Draws a scrolled background through the scanline renderer and checks the
+//! finished frame.
+
+mod common;
+
+use wasm_gameboy_emulator::{Gameboy, Model};
+
+const CYCLES_PER_FRAME: u32 = 70224;
+const ROM_ONLY: u8 = 0x00;
+const SCREEN_WIDTH: usize = 160;
+const BLACK: u8 = 3;
+const WHITE: u8 = 0;
+
+// Fills tile 1 with colour 3, puts it in the last column of the background
+// map, scrolls the background 200 pixels to the left and turns the LCD back
+// on with the background enabled.
+#[rustfmt::skip]
+const SCROLL_PROGRAM: [u8; 39] = [
+    0xaf, 0xe0, 0x40,             // xor a; ldh ($40), a
+    0x21, 0x10, 0x80,             // ld hl, $8010
+    0x06, 0x10,                   // ld b, 16
+    0x3e, 0xff,                   // ld a, $ff
+    0x22, 0x05, 0x20, 0xfc,       // .tile: ld (hl+), a; dec b; jr nz, .tile
+    0x21, 0x1f, 0x98,             // ld hl, $981f
+    0x11, 0x20, 0x00,             // ld de, 32
+    0x06, 0x20,                   // ld b, 32
+    0x3e, 0x01,                   // ld a, 1
+    0x77, 0x19, 0x05, 0x20, 0xfb, // .map: ld (hl), a; add hl, de; dec b; jr nz, .map
+    0x3e, 0xc8, 0xe0, 0x43,       // ld a, 200; ldh ($43), a
+    0x3e, 0x91, 0xe0, 0x40,       // ld a, $91; ldh ($40), a
+    0x18, 0xfe,                   // jr @
+];
+
+fn scroll_rom() -> Vec<u8> {
+    common::build_rom(b"SCROLL", ROM_ONLY, &SCROLL_PROGRAM)
+}
+
+#[test]
+fn wraps_the_background_horizontally() {
+    let mut gameboy = Gameboy::from_rom(&scroll_rom(), Model::Dmg, None).unwrap();
+    gameboy.start_running();
+    for _ in 0..5 {
+        gameboy.execute_opcodes_no_stop(CYCLES_PER_FRAME).unwrap();
+    }
+
+    // The last tile column, background X 248-255, ends up at screen X 48-55
+    // and the rest of the line comes from the start of the background
+    let frame = gameboy.frame_buffer();
+    for row in frame.chunks(SCREEN_WIDTH) {
+        for (x, pixel) in row.iter().enumerate() {
+            let expected = if (48..56).contains(&x) { BLACK } else { WHITE };
+            assert_eq!(*pixel, expected, "pixel at x {}", x);
+        }
+    }
+}
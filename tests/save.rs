@@ -0,0 +1,92 @@
+//! Loads battery saves into cartridges and writes them back out in the .sav
+//! layout.
+
+mod common;
+
+use wasm_gameboy_emulator::{Gameboy, LoadError, Model};
+
+const MBC2_BATTERY: u8 = 0x06;
+const MBC3_TIMER_RAM_BATTERY: u8 = 0x10;
+const RAM_32KB: u8 = 0x03;
+const MBC2_RAM_SIZE: usize = 0x200;
+const RTC_SAVE_SIZE: usize = 48;
+const SHORT_RTC_SAVE_SIZE: usize = 44;
+
+fn cartridge(cartridge_type: u8, ram_size_code: u8) -> Gameboy {
+    // jr @
+    let mut rom = common::build_rom(b"SAVE", cartridge_type, &[0x18, 0xfe]);
+    rom[0x149] = ram_size_code;
+    common::fix_checksums(&mut rom);
+    Gameboy::from_rom(&rom, Model::Dmg, None).unwrap()
+}
+
+// The live and then latched clock registers, each a little endian u32,
+// followed by the host time as a u64 or u32
+fn rtc_trailer(host_time_size: usize) -> Vec<u8> {
+    let registers = [12, 34, 5, 0x67, 0x01, 11, 33, 4, 0x66, 0x01];
+    let mut trailer: Vec<u8> = registers
+        .iter()
+        .flat_map(|register: &u32| register.to_le_bytes().to_vec())
+        .collect();
+    trailer.resize(trailer.len() + host_time_size, 0);
+    trailer
+}
+
+#[test]
+fn round_trips_mbc3_saves_with_the_clock() {
+    let mut gameboy = cartridge(MBC3_TIMER_RAM_BATTERY, RAM_32KB);
+    let mut save: Vec<u8> = (0..0x8000).map(|idx| idx as u8).collect();
+    save.extend(rtc_trailer(8));
+    assert_eq!(save.len(), 0x8000 + RTC_SAVE_SIZE);
+
+    gameboy.import_save(&save).unwrap();
+    assert_eq!(gameboy.export_save(), save);
+    assert!(!gameboy.is_save_dirty());
+}
+
+#[test]
+fn reads_mbc3_saves_with_a_32_bit_host_time() {
+    let mut gameboy = cartridge(MBC3_TIMER_RAM_BATTERY, RAM_32KB);
+    let mut save = vec![0x55; 0x8000];
+    save.extend(rtc_trailer(4));
+    assert_eq!(save.len(), 0x8000 + SHORT_RTC_SAVE_SIZE);
+
+    gameboy.import_save(&save).unwrap();
+    // Written back out with the full size host time
+    let mut expected = save.clone();
+    expected.extend(&[0; 4]);
+    assert_eq!(gameboy.export_save(), expected);
+}
+
+#[test]
+fn round_trips_mbc2_saves() {
+    let mut gameboy = cartridge(MBC2_BATTERY, 0x00);
+    let save: Vec<u8> = (0..MBC2_RAM_SIZE).map(|idx| idx as u8 & 0xf).collect();
+
+    gameboy.import_save(&save).unwrap();
+    assert_eq!(gameboy.export_save(), save);
+}
+
+#[test]
+fn rejects_saves_too_short_for_the_cartridge() {
+    let mut gameboy = cartridge(MBC3_TIMER_RAM_BATTERY, RAM_32KB);
+    let expected = 0x8000 + RTC_SAVE_SIZE;
+    for len in [0x100, 0x8000 + 10].iter() {
+        assert_eq!(
+            gameboy.import_save(&vec![0; *len]),
+            Err(LoadError::InvalidSaveSize {
+                expected,
+                len: *len
+            })
+        );
+    }
+
+    let mut gameboy = cartridge(MBC2_BATTERY, 0x00);
+    assert_eq!(
+        gameboy.import_save(&[0; MBC2_RAM_SIZE - 1]),
+        Err(LoadError::InvalidSaveSize {
+            expected: MBC2_RAM_SIZE,
+            len: MBC2_RAM_SIZE - 1
+        })
+    );
+}
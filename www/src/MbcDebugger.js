@@ -3,7 +3,20 @@ import {compareUint8Array, interestingRanges, toHex} from './utils.js';
 import React, {useState} from 'react';
 
 const MbcDebugger = props => {
-  const {mbc, romBank, ramBank, isRamEnabled, isRomEnabled} = props;
+  const {
+    mapper,
+    title,
+    cartridgeType,
+    romSize,
+    ramSize,
+    licensee,
+    version,
+    romBank,
+    ramBank,
+    isRamEnabled,
+    isRomEnabled,
+    mapperDescription,
+  } = props;
 
   return (
     <div className="break-point-wrapper">
@@ -11,8 +24,28 @@ const MbcDebugger = props => {
       <table>
         <tbody>
           <tr>
-            <td>MBC type:</td>
-            <td>{mbc}</td>
+            <td>Title:</td>
+            <td>{title}</td>
+          </tr>
+          <tr>
+            <td>Cartridge:</td>
+            <td>{cartridgeType}</td>
+          </tr>
+          <tr>
+            <td>ROM / RAM size:</td>
+            <td>
+              {romSize / 1024}KB / {ramSize / 1024}KB
+            </td>
+          </tr>
+          <tr>
+            <td>Licensee:</td>
+            <td>
+              {licensee} v{version}
+            </td>
+          </tr>
+          <tr>
+            <td>Mapper:</td>
+            <td>{mapper}</td>
           </tr>
           <tr>
             <td>Rom bank:</td>
@@ -30,6 +63,12 @@ const MbcDebugger = props => {
             <td>rom?</td>
             <td>{String(isRomEnabled)}</td>
           </tr>
+          {mapperDescription && (
+            <tr>
+              <td>State:</td>
+              <td>{mapperDescription}</td>
+            </tr>
+          )}
         </tbody>
       </table>
     </div>
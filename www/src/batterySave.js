@@ -0,0 +1,81 @@
+import {toHex} from './utils.js';
+
+// Battery saves live in localStorage as base64, keyed by the cartridge so
+// each game keeps its own
+const FLUSH_INTERVAL_MS = 1000;
+
+const saveKey = gameboy => {
+  const header = gameboy.cartridge_header();
+  return `sav:${header.title()}:${toHex(header.global_checksum(), 4)}`;
+};
+
+const toBase64 = bytes => {
+  let binary = '';
+  for (let idx = 0; idx < bytes.length; idx++) {
+    binary += String.fromCharCode(bytes[idx]);
+  }
+  return btoa(binary);
+};
+
+const fromBase64 = text => {
+  const binary = atob(text);
+  const bytes = new Uint8Array(binary.length);
+  for (let idx = 0; idx < binary.length; idx++) {
+    bytes[idx] = binary.charCodeAt(idx);
+  }
+  return bytes;
+};
+
+const loadBatterySave = gameboy => {
+  if (!gameboy.cartridge_header().has_battery()) {
+    return;
+  }
+
+  const save = localStorage.getItem(saveKey(gameboy));
+  if (save) {
+    try {
+      gameboy.import_save(fromBase64(save));
+    } catch (err) {
+      console.warn(`Ignoring the stored save: ${err}`);
+    }
+  }
+};
+
+const storeBatterySave = gameboy => {
+  const save = gameboy.take_dirty_save();
+  if (save) {
+    localStorage.setItem(saveKey(gameboy), toBase64(save));
+  }
+};
+
+// Games write to RAM all the time, so only write the save out once a second
+let lastFlushTime = 0;
+const flushBatterySave = gameboy => {
+  const now = Date.now();
+  if (now - lastFlushTime >= FLUSH_INTERVAL_MS) {
+    lastFlushTime = now;
+    storeBatterySave(gameboy);
+  }
+};
+
+const downloadBatterySave = gameboy => {
+  const blob = new Blob([gameboy.export_save()]);
+  const link = document.createElement('a');
+  link.href = URL.createObjectURL(blob);
+  link.download = `${gameboy.cartridge_header().title()}.sav`;
+  link.click();
+  URL.revokeObjectURL(link.href);
+};
+
+const importBatterySave = (gameboy, save) => {
+  gameboy.import_save(save);
+  localStorage.setItem(saveKey(gameboy), toBase64(gameboy.export_save()));
+};
+
+export {
+  loadBatterySave,
+  storeBatterySave,
+  flushBatterySave,
+  downloadBatterySave,
+  importBatterySave,
+};
@@ -0,0 +1,66 @@
+import React, {useState} from 'react';
+
+// Cheats are added to the running gameboy straight away, the list here only
+// keeps track of their ids to toggle and remove them by
+const CheatManager = props => {
+  const {gameboy} = props;
+  const [cheats, setCheats] = useState([]);
+  const [newCode, setNewCode] = useState('');
+  const [error, setError] = useState('');
+
+  const addCheat = () => {
+    try {
+      const id = gameboy.add_cheat(newCode);
+      setCheats([...cheats, {id, code: newCode.trim(), isEnabled: true}]);
+      setNewCode('');
+      setError('');
+    } catch (err) {
+      setError(String(err));
+    }
+  };
+
+  const toggleCheat = (cheat, isEnabled) => {
+    gameboy.set_cheat_enabled(cheat.id, isEnabled);
+    setCheats(
+      cheats.map(other =>
+        other.id === cheat.id ? {...other, isEnabled} : other,
+      ),
+    );
+  };
+
+  const removeCheat = cheat => {
+    gameboy.remove_cheat(cheat.id);
+    setCheats(cheats.filter(other => other.id !== cheat.id));
+  };
+
+  const handleKeyPress = e => {
+    if (e.key === 'Enter' && newCode != '') {
+      addCheat();
+    }
+  };
+
+  return (
+    <div className="break-point-wrapper">
+      <h3>Cheats</h3>
+      <input
+        placeholder="Game Genie or GameShark code"
+        value={newCode}
+        onKeyPress={event => handleKeyPress(event)}
+        onChange={event => setNewCode(event.target.value)}
+      />
+      {error && <div>{error}</div>}
+      {cheats.map(cheat => (
+        <div key={cheat.id}>
+          <input
+            type="checkbox"
+            checked={cheat.isEnabled}
+            onChange={event => toggleCheat(cheat, event.target.checked)}
+          />
+          <label>{cheat.code}</label>
+          <button onClick={() => removeCheat(cheat)}>Remove</button>
+        </div>
+      ))}
+    </div>
+  );
+};
+export {CheatManager};
@@ -8,6 +8,8 @@ import {
   init_panic_hook,
   opcode_name,
   Canvases,
+  Frontend,
+  Model,
 } from 'wasm-gameboy-emulator/wasm_gameboy_emulator';
 import {memory} from 'wasm-gameboy-emulator/wasm_gameboy_emulator_bg';
 import React, {useState} from 'react';
@@ -21,8 +23,17 @@ import {Debugger} from './debugControls.js';
 import {SoundDebugger} from './soundDebugger.js';
 import {BreakPointDebugger} from './breakPointDebugger.js';
 import {MbcDebugger} from './MbcDebugger.js';
+import {CheatManager} from './cheatManager.js';
+import {PaletteManager} from './paletteManager.js';
 import {LcdDebugger} from './LcdDebugger.js';
 import {SaveStateManager} from './saveStateManager.js';
+import {
+  loadBatterySave,
+  storeBatterySave,
+  flushBatterySave,
+  downloadBatterySave,
+  importBatterySave,
+} from './batterySave.js';
 import {square1, playSquare, playSquare1, playSquare2} from './channels.jsx';
 
 var ReactDOM = require('react-dom');
@@ -32,8 +43,9 @@ const config = {
 };
 
 initEmulation();
-const gameboyInst = Gameboy.new();
+let gameboyInst = null;
 const canvases = Canvases.new();
+const frontend = Frontend.new();
 
 const playSound = gameboy => {
   //TODO:Implement playsound
@@ -44,12 +56,22 @@ var domContainer = document.querySelector('#memory-viewer');
 // var soundContainer = document.getElementById('sound-container');
 var breakPointContainer = document.getElementById('break-point-container');
 var mbcContainer = document.getElementById('mbc-container');
+var cheatContainer = document.getElementById('cheat-container');
+var paletteContainer = document.getElementById('palette-container');
+var emulationError = document.getElementById('emulation-error');
 var lcdContainer = document.getElementById('lcd-container');
 let tick = -1;
 const opLogMaxLength = 16;
 const opLog = [];
 var isRunning = false;
 
+// The palette applies to whichever game is loaded, so it's rendered once
+// instead of with the per-game panels
+ReactDOM.render(
+  React.createElement(PaletteManager, {canvases, frontend}),
+  paletteContainer,
+);
+
 document.addEventListener('keydown', event => {
   let key;
   switch (event.key) {
@@ -80,7 +102,9 @@ document.addEventListener('keydown', event => {
     default:
       console.log('invalid key');
   }
-  gameboyInst.joypad_key_pressed(key);
+  if (gameboyInst) {
+    gameboyInst.joypad_key_pressed(key);
+  }
 });
 
 document.addEventListener('keyup', event => {
@@ -113,19 +137,34 @@ document.addEventListener('keyup', event => {
     default:
       console.log('invalid key');
   }
-  gameboyInst.joypad_key_released(key);
+  if (gameboyInst) {
+    gameboyInst.joypad_key_released(key);
+  }
 });
 
 var render = function render(gameboy) {
-  var memoryPtr = gameboyInst.memory();
+  var memoryPtr = gameboy.memory();
   var memoryBytes = new Uint8Array(memory.buffer, memoryPtr, 65535);
-  isRunning = gameboyInst.is_running();
+  isRunning = gameboy.is_running();
   tick = tick + 1;
 
   const next = opNum => {
     if (gameboy.is_running()) {
       const startTime = Date.now();
-      gameboy.execute_opcodes_no_stop(opNum ? opNum : 15000000);
+      if (gameboy.is_rtc_host_clock()) {
+        gameboy.sync_rtc_host_time(startTime / 1000);
+      }
+      try {
+        frontend.execute_opcodes_no_stop(gameboy, opNum ? opNum : 15000000);
+      } catch (err) {
+        // The gameboy has already stopped itself, render once more so the
+        // debugger shows the state it stopped in
+        emulationError.textContent = err;
+        storeBatterySave(gameboy);
+        requestAnimationFrame(() => render(gameboy));
+        return;
+      }
+      flushBatterySave(gameboy);
       // if (gameboy.is_vblank()) {
       //   canvases.update_char_map_canvas(gameboy);
       //   canvases.render_background_map_1_as_image_data(gameboy);
@@ -224,11 +263,8 @@ var render = function render(gameboy) {
   let timerCycleToIncreaseCount = gameboy.timer_cycle_to_cpu_clock();
   let timerFrequency = gameboy.timer_frequency();
 
-  let mbc = gameboy.get_mbc();
-  let romBank = gameboy.get_rom_bank();
-  let ramBank = gameboy.get_ram_bank();
-  let isRamEnabled = gameboy.get_is_ram_enabled();
-  let isRomEnabled = gameboy.get_is_rom_banking_enabled();
+  let cartridgeHeader = gameboy.cartridge_header();
+  let mapperStatus = gameboy.mapper_status();
 
   let isLdcDisplayEnabled = gameboy.is_lcd_display_enable();
   let windowTileMapSelection = gameboy.get_window_tile_map_selection();
@@ -268,15 +304,31 @@ var render = function render(gameboy) {
 
   ReactDOM.render(
     React.createElement(MbcDebugger, {
-      mbc,
-      romBank,
-      ramBank,
-      isRamEnabled,
-      isRomEnabled,
+      mapper: mapperStatus.name(),
+      title: cartridgeHeader.title(),
+      cartridgeType: cartridgeHeader.cartridge_type_name(),
+      romSize: cartridgeHeader.rom_size(),
+      ramSize: cartridgeHeader.ram_size(),
+      licensee: cartridgeHeader.licensee_code(),
+      version: cartridgeHeader.version(),
+      romBank: mapperStatus.rom_bank(),
+      ramBank: mapperStatus.ram_bank(),
+      isRamEnabled: mapperStatus.is_ram_enabled(),
+      isRomEnabled: mapperStatus.is_rom_banking_enabled(),
+      mapperDescription: mapperStatus.description(),
     }),
     mbcContainer,
   );
 
+  ReactDOM.render(
+    React.createElement(CheatManager, {
+      // Start with an empty list for each new game
+      key: cartridgeHeader.global_checksum(),
+      gameboy,
+    }),
+    cheatContainer,
+  );
+
   ReactDOM.render(
     React.createElement(LcdDebugger, {
       isLdcDisplayEnabled,
@@ -365,7 +417,75 @@ var render = function render(gameboy) {
 
 init_panic_hook();
 
-// gameboyInst.start_running();
-// gameboyInst.execute_opcodes_no_stop();
-window.gb = gameboyInst;
-requestAnimationFrame(() => render(gameboyInst));
+// Optional, without one the game starts in the post-boot state
+let bootRom = undefined;
+document.getElementById('boot-rom-input').addEventListener('change', event => {
+  const file = event.target.files[0];
+  if (!file) {
+    bootRom = undefined;
+    return;
+  }
+
+  file.arrayBuffer().then(buffer => {
+    bootRom = new Uint8Array(buffer);
+  });
+});
+
+document.getElementById('rom-input').addEventListener('change', event => {
+  const file = event.target.files[0];
+  if (!file) {
+    return;
+  }
+
+  file.arrayBuffer().then(buffer => {
+    let newGameboy;
+    try {
+      const model = Model[document.getElementById('model-select').value];
+      newGameboy = Gameboy.from_rom(new Uint8Array(buffer), model, bootRom);
+      newGameboy.set_rtc_host_clock(
+        document.getElementById('rtc-host-clock').checked,
+      );
+      loadBatterySave(newGameboy);
+    } catch (err) {
+      alert(`Could not load ${file.name}: ${err}`);
+      return;
+    }
+
+    if (gameboyInst) {
+      gameboyInst.stop_running();
+      storeBatterySave(gameboyInst);
+    }
+    gameboyInst = newGameboy;
+    emulationError.textContent = '';
+    window.gb = gameboyInst;
+    requestAnimationFrame(() => render(gameboyInst));
+  });
+});
+
+document.getElementById('export-save').addEventListener('click', () => {
+  if (gameboyInst) {
+    downloadBatterySave(gameboyInst);
+  }
+});
+
+document.getElementById('import-save').addEventListener('change', event => {
+  const file = event.target.files[0];
+  if (!file || !gameboyInst) {
+    return;
+  }
+
+  file.arrayBuffer().then(buffer => {
+    try {
+      importBatterySave(gameboyInst, new Uint8Array(buffer));
+    } catch (err) {
+      alert(`Could not import ${file.name}: ${err}`);
+    }
+  });
+});
+
+// Don't lose the last second of saving when the page is closed
+window.addEventListener('beforeunload', () => {
+  if (gameboyInst) {
+    storeBatterySave(gameboyInst);
+  }
+});
@@ -0,0 +1,53 @@
+import React, {useState} from 'react';
+import {DisplayPalette} from 'wasm-gameboy-emulator/wasm_gameboy_emulator';
+
+const PRESETS = ['grey', 'dmg', 'pocket', 'light', 'high-contrast'];
+
+// Picks the colours the screen and debug canvases are drawn in, from the
+// presets or pasted in as text
+const PaletteManager = props => {
+  const {canvases, frontend} = props;
+  const [text, setText] = useState(DisplayPalette.preset('grey').to_text());
+  const [error, setError] = useState('');
+
+  const applyPalette = palette => {
+    canvases.set_display_palette(palette);
+    frontend.set_display_palette(palette);
+    setText(palette.to_text());
+    setError('');
+    palette.free();
+  };
+
+  const importPalette = () => {
+    try {
+      applyPalette(DisplayPalette.parse(text));
+    } catch (err) {
+      setError(String(err));
+    }
+  };
+
+  return (
+    <div className="break-point-wrapper">
+      <h3>Display palette</h3>
+      <select
+        onChange={event => applyPalette(DisplayPalette.preset(event.target.value))}>
+        {PRESETS.map(name => (
+          <option key={name} value={name}>
+            {name}
+          </option>
+        ))}
+      </select>
+      <div>
+        <textarea
+          rows={3}
+          cols={40}
+          value={text}
+          onChange={event => setText(event.target.value)}
+        />
+      </div>
+      <button onClick={importPalette}>Import</button>
+      {error && <div>{error}</div>}
+    </div>
+  );
+};
+export {PaletteManager};